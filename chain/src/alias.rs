@@ -0,0 +1,42 @@
+//!
+//! This module defines types to describe resources sharing the same memory.
+//! Resources may share memory if they are never used at the same time.
+//! Only images are aliased, buffers always own their memory.
+//!
+
+use std::ops::Range;
+
+use crate::{chain::Chain, resource::Resource, schedule::QueueId, Id};
+
+/// Groups of images that share memory.
+/// Each group lists images in order of their lifetimes within a frame.
+/// Content of aliased images doesn't persist between uses.
+#[derive(Clone, Debug, Default)]
+pub struct Aliasing {
+    /// Groups of aliased images.
+    pub images: Vec<Vec<Id>>,
+}
+
+/// Get lifetime of the resource within a frame.
+/// Returns queue and range of indices of submissions that use the resource.
+/// Returns `None` if resource is used by more than one queue,
+/// as submissions from different queues can run simultaneously.
+pub fn lifetime<R>(chain: &Chain<R>) -> Option<(QueueId, Range<usize>)>
+where
+    R: Resource,
+{
+    let mut result: Option<(QueueId, Range<usize>)> = None;
+    for link in chain.links() {
+        for (qid, queue) in link.queues() {
+            result = match result {
+                None => Some((qid, queue.first..queue.last + 1)),
+                Some((other, _)) if other != qid => return None,
+                Some((qid, range)) => Some((
+                    qid,
+                    range.start.min(queue.first)..range.end.max(queue.last + 1),
+                )),
+            };
+        }
+    }
+    result
+}
@@ -0,0 +1,574 @@
+//! This module exports synchronized schedule in human and machine readable forms.
+//! Queues are shown as lanes and submissions as nodes.
+//! Semaphores and barriers are shown as edges between submissions.
+//!
+
+use std::fmt::{Debug, Write};
+
+use crate::{
+    chain::Chain,
+    collect::Chains,
+    resource::Resource,
+    schedule::{Queue, Schedule, Submission, SubmissionId},
+    sync::{Barrier, Barriers, SyncData},
+    Id,
+};
+
+/// Side of submission barrier is recorded at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Acquire,
+    Release,
+}
+
+impl Side {
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Acquire => "acquire",
+            Side::Release => "release",
+        }
+    }
+}
+
+/// Edge between submissions.
+/// `None` stands for state before or after whole schedule.
+struct Edge {
+    from: Option<SubmissionId>,
+    to: Option<SubmissionId>,
+    label: String,
+    semaphore: bool,
+}
+
+fn queues<S>(schedule: &Schedule<S>) -> Vec<&Queue<S>> {
+    let mut families = schedule.iter().collect::<Vec<_>>();
+    families.sort_by_key(|family| family.id().0);
+    families
+        .into_iter()
+        .flat_map(|family| family.iter())
+        .collect()
+}
+
+fn submissions<S>(schedule: &Schedule<S>) -> Vec<&Submission<S>> {
+    queues(schedule)
+        .into_iter()
+        .flat_map(|queue| queue.iter())
+        .collect()
+}
+
+fn sorted<R: Resource>(barriers: &Barriers<R>) -> Vec<(&Id, &Barrier<R>)> {
+    let mut barriers = barriers.iter().collect::<Vec<_>>();
+    barriers.sort_by_key(|(id, _)| **id);
+    barriers
+}
+
+fn node_name(sid: SubmissionId) -> String {
+    format!(
+        "s{}_{}_{}",
+        sid.family().0,
+        sid.queue().index(),
+        sid.index()
+    )
+}
+
+fn barrier_label<R: Resource>(kind: &str, id: Id, side: Side, barrier: &Barrier<R>) -> String {
+    let mut label = format!(
+        "{} {} {}: {:?} {:?} {:?} -> {:?} {:?} {:?}",
+        kind,
+        id.0,
+        side.name(),
+        barrier.states.start.0,
+        barrier.states.start.1,
+        barrier.states.start.2,
+        barrier.states.end.0,
+        barrier.states.end.1,
+        barrier.states.end.2,
+    );
+    if let Some(families) = &barrier.families {
+        write!(
+            label,
+            " (family {} -> {})",
+            families.start.0, families.end.0
+        )
+        .unwrap();
+    }
+    label
+}
+
+/// Find submissions on the other side of the barrier.
+/// Acquire barriers come from the last submissions of the previous link.
+/// Release barriers lead to the first submissions of the next link.
+fn barrier_peers<R: Resource>(
+    chain: Option<&Chain<R>>,
+    link: usize,
+    side: Side,
+) -> Vec<SubmissionId> {
+    let links = chain.map_or(&[][..], |chain| chain.links());
+    let peer = match side {
+        Side::Acquire if link > 0 => links.get(link - 1),
+        Side::Acquire => None,
+        Side::Release => links.get(link + 1),
+    };
+
+    peer.map_or(Vec::new(), |peer| {
+        peer.queues()
+            .map(|(qid, queue)| match side {
+                Side::Acquire => SubmissionId::new(qid, queue.last),
+                Side::Release => SubmissionId::new(qid, queue.first),
+            })
+            .collect()
+    })
+}
+
+fn barrier_edges<R: Resource, S>(
+    edges: &mut Vec<Edge>,
+    kind: &str,
+    chains: &fnv::FnvHashMap<Id, Chain<R>>,
+    submission: &Submission<S>,
+    barriers: &Barriers<R>,
+    side: Side,
+    link_index: impl Fn(Id) -> usize,
+) {
+    for (&id, barrier) in sorted(barriers) {
+        let label = barrier_label(kind, id, side, barrier);
+        let peers = barrier_peers(chains.get(&id), link_index(id), side);
+        let this = Some(submission.id());
+        if peers.is_empty() {
+            let (from, to) = match side {
+                Side::Acquire => (None, this),
+                Side::Release => (this, None),
+            };
+            edges.push(Edge {
+                from,
+                to,
+                label,
+                semaphore: false,
+            });
+        }
+        for peer in peers {
+            let (from, to) = match side {
+                Side::Acquire => (Some(peer), this),
+                Side::Release => (this, Some(peer)),
+            };
+            edges.push(Edge {
+                from,
+                to,
+                label: label.clone(),
+                semaphore: false,
+            });
+        }
+    }
+}
+
+fn edges<S, W>(schedule: &Schedule<SyncData<S, W>>, chains: &Chains) -> Vec<Edge>
+where
+    S: Debug,
+    W: Debug + PartialEq<S>,
+{
+    let submissions = submissions(schedule);
+    let mut edges = Vec::new();
+
+    for submission in &submissions {
+        for signal in &submission.sync().signal {
+            for waiting in &submissions {
+                for wait in &waiting.sync().wait {
+                    if wait.semaphore() == signal.semaphore() {
+                        edges.push(Edge {
+                            from: Some(submission.id()),
+                            to: Some(waiting.id()),
+                            label: format!(
+                                "semaphore {:?} wait at {:?}",
+                                signal.semaphore(),
+                                wait.stage()
+                            ),
+                            semaphore: true,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    for submission in &submissions {
+        let ref sync = submission.sync();
+        for &(guard, side) in &[
+            (&sync.acquire, Side::Acquire),
+            (&sync.release, Side::Release),
+        ] {
+            barrier_edges(
+                &mut edges,
+                "buffer",
+                &chains.buffers,
+                submission,
+                &guard.buffers,
+                side,
+                |id| submission.buffer_link_index(id),
+            );
+            barrier_edges(
+                &mut edges,
+                "image",
+                &chains.images,
+                submission,
+                &guard.images,
+                side,
+                |id| submission.image_link_index(id),
+            );
+        }
+    }
+
+    edges
+}
+
+/// Escape string to be placed in quoted DOT string.
+/// Control characters other than line feed are replaced with spaces.
+fn dot_escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape string to be placed in JSON string.
+fn json_escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Debug representation of the value as JSON string.
+fn json_debug<T: Debug>(value: T) -> String {
+    format!("\"{}\"", json_escape(&format!("{:?}", value)))
+}
+
+/// Render synchronized schedule in Graphviz DOT format.
+/// Each queue is a cluster of submissions in queue order.
+/// Semaphores are drawn as bold red edges, barriers as plain edges labeled with state transition.
+pub fn export_dot<S, W>(schedule: &Schedule<SyncData<S, W>>, chains: &Chains) -> String
+where
+    S: Debug,
+    W: Debug + PartialEq<S>,
+{
+    let mut dot = String::new();
+    writeln!(dot, "digraph schedule {{").unwrap();
+    writeln!(dot, "    rankdir=LR;").unwrap();
+    writeln!(dot, "    node [shape=box];").unwrap();
+    writeln!(dot, "    begin [shape=point];").unwrap();
+    writeln!(dot, "    end [shape=point];").unwrap();
+
+    for queue in queues(schedule) {
+        let qid = queue.id();
+        writeln!(
+            dot,
+            "    subgraph cluster_{}_{} {{",
+            qid.family().0,
+            qid.index()
+        )
+        .unwrap();
+        writeln!(
+            dot,
+            "        label=\"queue {}:{}\";",
+            qid.family().0,
+            qid.index()
+        )
+        .unwrap();
+        let mut previous = None;
+        for submission in queue.iter() {
+            let name = node_name(submission.id());
+            writeln!(
+                dot,
+                "        {} [label=\"node {}\\nsubmission {}\\norder {}\"];",
+                name,
+                submission.node(),
+                submission.id().index(),
+                submission.submit_order()
+            )
+            .unwrap();
+            if let Some(previous) = previous {
+                writeln!(dot, "        {} -> {} [style=dotted];", previous, name).unwrap();
+            }
+            previous = Some(name);
+        }
+        writeln!(dot, "    }}").unwrap();
+    }
+
+    for edge in edges(schedule, chains) {
+        let from = edge.from.map_or("begin".to_owned(), node_name);
+        let to = edge.to.map_or("end".to_owned(), node_name);
+        let style = if edge.semaphore {
+            ", color=red, style=bold"
+        } else {
+            ""
+        };
+        writeln!(
+            dot,
+            "    {} -> {} [label=\"{}\"{}];",
+            from,
+            to,
+            dot_escape(&edge.label),
+            style
+        )
+        .unwrap();
+    }
+
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+fn json_sid(sid: Option<SubmissionId>) -> String {
+    match sid {
+        Some(sid) => format!(
+            "{{\"family\":{},\"queue\":{},\"index\":{}}}",
+            sid.family().0,
+            sid.queue().index(),
+            sid.index()
+        ),
+        None => "null".to_owned(),
+    }
+}
+
+fn json_barriers<R: Resource>(barriers: &Barriers<R>) -> String {
+    let barriers = sorted(barriers)
+        .into_iter()
+        .map(|(id, barrier)| {
+            let families = barrier.families.as_ref().map_or("null".to_owned(), |f| {
+                format!("[{},{}]", f.start.0, f.end.0)
+            });
+            format!(
+                "{{\"id\":{},\"families\":{},\"from\":{{\"access\":{},\"layout\":{},\"stages\":{}}},\"to\":{{\"access\":{},\"layout\":{},\"stages\":{}}}}}",
+                id.0,
+                families,
+                json_debug(&barrier.states.start.0),
+                json_debug(&barrier.states.start.1),
+                json_debug(&barrier.states.start.2),
+                json_debug(&barrier.states.end.0),
+                json_debug(&barrier.states.end.1),
+                json_debug(&barrier.states.end.2),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", barriers.join(","))
+}
+
+fn json_chains<R: Resource>(chains: &fnv::FnvHashMap<Id, Chain<R>>) -> String {
+    let mut ids = chains.keys().collect::<Vec<_>>();
+    ids.sort();
+    let chains = ids
+        .into_iter()
+        .map(|id| {
+            let links = chains[id]
+                .links()
+                .iter()
+                .map(|link| {
+                    let queues = link
+                        .queues()
+                        .map(|(qid, queue)| {
+                            format!(
+                                "{{\"queue\":{},\"first\":{},\"last\":{}}}",
+                                qid.index(),
+                                queue.first,
+                                queue.last
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    format!(
+                        "{{\"family\":{},\"access\":{},\"layout\":{},\"usage\":{},\"queues\":[{}]}}",
+                        link.family().0,
+                        json_debug(link.access()),
+                        json_debug(link.layout()),
+                        json_debug(link.usage()),
+                        queues.join(",")
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("{{\"id\":{},\"links\":[{}]}}", id.0, links.join(","))
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", chains.join(","))
+}
+
+/// Render synchronized schedule as JSON document.
+/// Contains queues with their submissions and synchronization,
+/// semaphore and barrier edges between submissions,
+/// and links of all resource chains.
+/// Flags and layouts are written as their `Debug` representation.
+pub fn export_json<S, W>(schedule: &Schedule<SyncData<S, W>>, chains: &Chains) -> String
+where
+    S: Debug,
+    W: Debug + PartialEq<S>,
+{
+    let queues = queues(schedule)
+        .into_iter()
+        .map(|queue| {
+            let submissions = queue
+                .iter()
+                .map(|submission| {
+                    let ref sync = submission.sync();
+                    let wait = sync
+                        .wait
+                        .iter()
+                        .map(|wait| {
+                            format!(
+                                "{{\"semaphore\":{},\"stage\":{}}}",
+                                json_debug(wait.semaphore()),
+                                json_debug(wait.stage())
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    let signal = sync
+                        .signal
+                        .iter()
+                        .map(|signal| json_debug(signal.semaphore()))
+                        .collect::<Vec<_>>();
+                    format!(
+                        "{{\"index\":{},\"node\":{},\"submit_order\":{},\"wait_factor\":{},\"wait\":[{}],\"signal\":[{}],\"acquire\":{{\"buffers\":{},\"images\":{}}},\"release\":{{\"buffers\":{},\"images\":{}}}}}",
+                        submission.id().index(),
+                        submission.node(),
+                        submission.submit_order(),
+                        submission.wait_factor(),
+                        wait.join(","),
+                        signal.join(","),
+                        json_barriers(&sync.acquire.buffers),
+                        json_barriers(&sync.acquire.images),
+                        json_barriers(&sync.release.buffers),
+                        json_barriers(&sync.release.images),
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "{{\"family\":{},\"index\":{},\"submissions\":[{}]}}",
+                queue.id().family().0,
+                queue.id().index(),
+                submissions.join(",")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let edges = edges(schedule, chains)
+        .into_iter()
+        .map(|edge| {
+            format!(
+                "{{\"from\":{},\"to\":{},\"kind\":\"{}\",\"label\":\"{}\"}}",
+                json_sid(edge.from),
+                json_sid(edge.to),
+                if edge.semaphore {
+                    "semaphore"
+                } else {
+                    "barrier"
+                },
+                json_escape(&edge.label)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "{{\"queues\":[{}],\"edges\":[{}],\"buffers\":{},\"images\":{}}}",
+        queues.join(","),
+        edges.join(","),
+        json_chains(&chains.buffers),
+        json_chains(&chains.images),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{collect::collect, node::Node, sync::sync},
+        gfx_hal::{buffer, pso::PipelineStage, queue::QueueFamilyId},
+    };
+
+    /// Buffer written on one family and read on another.
+    fn two_queues() -> (Schedule<SyncData<usize, usize>>, Chains) {
+        let node = |id, family, dependencies, access, stages, usage| Node {
+            id,
+            family: QueueFamilyId(family),
+            dependencies,
+            buffers: Some((
+                Id(0),
+                crate::node::State {
+                    access,
+                    layout: (),
+                    stages,
+                    usage,
+                },
+            ))
+            .into_iter()
+            .collect(),
+            images: Default::default(),
+        };
+
+        let nodes = vec![
+            node(
+                0,
+                0,
+                vec![],
+                buffer::Access::TRANSFER_WRITE,
+                PipelineStage::TRANSFER,
+                buffer::Usage::TRANSFER_DST,
+            ),
+            node(
+                1,
+                1,
+                vec![0],
+                buffer::Access::SHADER_READ,
+                PipelineStage::VERTEX_SHADER,
+                buffer::Usage::STORAGE,
+            ),
+        ];
+
+        let chains = collect(nodes, |_| 1);
+        let mut semaphores = 0;
+        let schedule = sync(&chains, || {
+            semaphores += 1;
+            (semaphores, semaphores)
+        });
+        (schedule, chains)
+    }
+
+    #[test]
+    fn json_is_valid() {
+        let (schedule, chains) = two_queues();
+        let json: serde_json::Value =
+            serde_json::from_str(&export_json(&schedule, &chains)).unwrap();
+
+        let queues = json["queues"].as_array().unwrap();
+        assert_eq!(queues.len(), 2);
+        for (family, queue) in queues.iter().enumerate() {
+            assert_eq!(queue["family"], family);
+            assert_eq!(queue["submissions"].as_array().unwrap().len(), 1);
+        }
+        assert_eq!(queues[1]["submissions"][0]["node"], 1);
+
+        let edges = json["edges"].as_array().unwrap();
+        assert!(edges.iter().any(|edge| edge["kind"] == "semaphore"
+            && edge["from"]["family"] == 0
+            && edge["to"]["family"] == 1));
+        assert_eq!(json["buffers"][0]["links"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dot_is_escaped() {
+        assert_eq!(dot_escape("a\"b\\c\nd\u{1}e"), "a\\\"b\\\\c\\nd e");
+
+        let (schedule, chains) = two_queues();
+        let dot = export_dot(&schedule, &chains);
+        assert!(dot.contains("subgraph cluster_0_0"));
+        assert!(dot.contains("subgraph cluster_1_0"));
+        assert!(dot.contains("color=red"));
+        assert!(!dot.contains("\\u"));
+    }
+}
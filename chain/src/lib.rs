@@ -16,18 +16,25 @@
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Id(pub usize);
 
+mod alias;
 mod chain;
 mod collect;
+mod export;
 mod node;
 mod resource;
 mod schedule;
 mod sync;
 
 pub use crate::{
+    alias::{lifetime, Aliasing},
     chain::{Chain, Link, LinkNode},
     collect::{collect, Chains, Unsynchronized},
+    export::{export_dot, export_json},
     node::{BufferState, ImageState, Node, State},
     resource::{AccessFlags, Buffer, Image, Resource, UsageFlags},
     schedule::{Family, Queue, QueueId, Schedule, Submission, SubmissionId},
-    sync::{sync, Barrier, Barriers, BufferBarriers, Guard, ImageBarriers, Signal, SyncData, Wait},
+    sync::{
+        sync, sync_aliased, Barrier, Barriers, BufferBarriers, Guard, ImageBarriers, Signal,
+        SyncData, Wait,
+    },
 };
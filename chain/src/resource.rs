@@ -69,6 +69,9 @@ pub trait Resource: 'static {
 
     /// Layout suitable for specified accesses.
     fn layout_for(access: Self::Access) -> Self::Layout;
+
+    /// Layout that allows content of the resource to be discarded.
+    fn discard_layout() -> Self::Layout;
 }
 
 /// Buffer resource type.
@@ -85,6 +88,8 @@ impl Resource for Buffer {
     }
 
     fn layout_for(_access: gfx_hal::buffer::Access) {}
+
+    fn discard_layout() {}
 }
 
 /// Image resource type.
@@ -148,6 +153,10 @@ impl Resource for Image {
         }
         acc.unwrap_or(gfx_hal::image::Layout::General)
     }
+
+    fn discard_layout() -> gfx_hal::image::Layout {
+        gfx_hal::image::Layout::Undefined
+    }
 }
 
 fn common_layout(
@@ -4,6 +4,7 @@
 use std::ops::{Range, RangeFrom, RangeTo};
 
 use crate::{
+    alias::Aliasing,
     chain::{Chain, Link},
     collect::Chains,
     node::State,
@@ -257,7 +258,20 @@ impl SyncTemp {
 }
 
 /// Find required synchronization for all submissions in `Chains`.
-pub fn sync<F, S, W>(chains: &Chains, mut new_semaphore: F) -> Schedule<SyncData<S, W>>
+pub fn sync<F, S, W>(chains: &Chains, new_semaphore: F) -> Schedule<SyncData<S, W>>
+where
+    F: FnMut() -> (S, W),
+{
+    sync_aliased(chains, &Aliasing::default(), new_semaphore)
+}
+
+/// Find required synchronization for all submissions in `Chains`
+/// where images from `aliasing` groups share memory.
+pub fn sync_aliased<F, S, W>(
+    chains: &Chains,
+    aliasing: &Aliasing,
+    mut new_semaphore: F,
+) -> Schedule<SyncData<S, W>>
 where
     F: FnMut() -> (S, W),
 {
@@ -265,12 +279,24 @@ where
     let ref buffers = chains.buffers;
     let ref images = chains.images;
 
+    let aliased_images = aliasing
+        .images
+        .iter()
+        .filter(|group| group.len() > 1)
+        .flatten()
+        .cloned()
+        .collect::<fnv::FnvHashSet<Id>>();
+
     let mut sync = SyncTemp(fnv::FnvHashMap::default());
     for (&id, chain) in buffers {
-        sync_chain(id, chain, schedule, &mut sync);
+        sync_chain(id, chain, schedule, &mut sync, true);
     }
     for (&id, chain) in images {
-        sync_chain(id, chain, schedule, &mut sync);
+        let wrap = !aliased_images.contains(&id);
+        sync_chain(id, chain, schedule, &mut sync, wrap);
+    }
+    for group in &aliasing.images {
+        sync_alias_group(group, images, schedule, &mut sync);
     }
     if schedule.queue_count() > 1 {
         optimize(schedule, &mut sync);
@@ -367,8 +393,15 @@ fn generate_semaphore_pair<R: Resource>(
     }
 }
 
-fn sync_chain<R, S>(id: Id, chain: &Chain<R>, schedule: &Schedule<S>, sync: &mut SyncTemp)
-where
+/// Synchronize links of the chain.
+/// If `wrap` is set then last link is synchronized with first link of the next frame.
+fn sync_chain<R, S>(
+    id: Id,
+    chain: &Chain<R>,
+    schedule: &Schedule<S>,
+    sync: &mut SyncTemp,
+    wrap: bool,
+) where
     R: Resource,
 {
     let uid = id.into();
@@ -381,7 +414,8 @@ where
             chain
                 .links()
                 .first()
-                .and_then(|first| chain.links().last().map(move |last| (last, first))),
+                .and_then(|first| chain.links().last().map(move |last| (last, first)))
+                .filter(|_| wrap),
         );
 
     for (prev_link, link) in pairs {
@@ -457,6 +491,55 @@ where
     }
 }
 
+/// Synchronize resources sharing memory.
+/// First link of each resource waits for last link of the previous one in the group,
+/// and first resource waits for the last one from the previous frame.
+/// Content of the previous resource is discarded.
+fn sync_alias_group<R, S>(
+    group: &[Id],
+    chains: &fnv::FnvHashMap<Id, Chain<R>>,
+    schedule: &Schedule<S>,
+    sync: &mut SyncTemp,
+) where
+    R: Resource,
+{
+    if group.len() < 2 {
+        return;
+    }
+
+    for (index, &id) in group.iter().enumerate() {
+        let prev_id = group[(index + group.len() - 1) % group.len()];
+        let prev_link = chains[&prev_id]
+            .links()
+            .last()
+            .expect("Aliased resource must be used");
+        let link = chains[&id]
+            .links()
+            .first()
+            .expect("Aliased resource must be used");
+        assert_eq!(
+            prev_link.family(),
+            link.family(),
+            "Aliased resources must be used by the same family"
+        );
+
+        let wait_sid = earliest(link, schedule);
+        for (queue_id, queue) in prev_link.queues() {
+            let tail = SubmissionId::new(queue_id, queue.last);
+            generate_semaphore_pair(sync, id, link, tail..wait_sid);
+        }
+
+        let discarded = State {
+            layout: R::discard_layout(),
+            ..prev_link.state()
+        };
+        sync.get_sync(wait_sid)
+            .acquire
+            .pick::<R>()
+            .insert(id, Barrier::new(discarded..link.state()));
+    }
+}
+
 fn optimize_submission(
     sid: SubmissionId,
     found: &mut fnv::FnvHashMap<QueueId, usize>,
@@ -206,6 +206,30 @@ where
         }
     }
 
+    /// Write timestamp to the query after all previous commands reach `stage`.
+    pub fn write_timestamp(
+        &mut self,
+        stage: gfx_hal::pso::PipelineStage,
+        query: gfx_hal::query::Query<'_, B>,
+    ) {
+        unsafe { gfx_hal::command::RawCommandBuffer::write_timestamp(self.raw, stage, query) }
+    }
+
+    /// Begin query.
+    /// Query must be reset and ended in the same command buffer.
+    pub fn begin_query(
+        &mut self,
+        query: gfx_hal::query::Query<'_, B>,
+        flags: gfx_hal::query::ControlFlags,
+    ) {
+        unsafe { gfx_hal::command::RawCommandBuffer::begin_query(self.raw, query, flags) }
+    }
+
+    /// End query begun in this command buffer.
+    pub fn end_query(&mut self, query: gfx_hal::query::Query<'_, B>) {
+        unsafe { gfx_hal::command::RawCommandBuffer::end_query(self.raw, query) }
+    }
+
     /// Push graphics constants.
     pub fn push_constants<'b>(
         &mut self,
@@ -598,6 +622,34 @@ where
         self.level
     }
 
+    /// Reborrow encoder.
+    pub fn reborrow(&mut self) -> Encoder<'_, B, C, L>
+    where
+        C: Copy,
+        L: Copy,
+    {
+        Encoder {
+            inner: EncoderCommon {
+                capability: self.inner.capability,
+                raw: &mut *self.inner.raw,
+                family: self.inner.family,
+            },
+            level: self.level,
+        }
+    }
+
+    /// Reset queries in the pool.
+    /// Queries must be reset before they are written.
+    pub fn reset_query_pool(
+        &mut self,
+        pool: &B::QueryPool,
+        queries: std::ops::Range<gfx_hal::query::Id>,
+    ) {
+        unsafe {
+            gfx_hal::command::RawCommandBuffer::reset_query_pool(self.inner.raw, pool, queries)
+        }
+    }
+
     /// Copy buffer regions.
     /// `src` and `dst` can be the same buffer or alias in memory.
     /// But regions must not overlap.
@@ -665,6 +717,56 @@ where
         }
     }
 
+    /// Copy image subresource range to buffer region.
+    pub fn copy_image_to_buffer(
+        &mut self,
+        src: &B::Image,
+        src_layout: gfx_hal::image::Layout,
+        dst: &B::Buffer,
+        regions: impl IntoIterator<Item = gfx_hal::command::BufferImageCopy>,
+    ) where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+
+        unsafe {
+            gfx_hal::command::RawCommandBuffer::copy_image_to_buffer(
+                self.inner.raw,
+                src,
+                src_layout,
+                dst,
+                regions,
+            )
+        }
+    }
+
+    /// Blit image regions, converting format and scaling if necessary.
+    pub fn blit_image(
+        &mut self,
+        src: &B::Image,
+        src_layout: gfx_hal::image::Layout,
+        dst: &B::Image,
+        dst_layout: gfx_hal::image::Layout,
+        filter: gfx_hal::image::Filter,
+        regions: impl IntoIterator<Item = gfx_hal::command::ImageBlit>,
+    ) where
+        C: Supports<Graphics>,
+    {
+        self.capability.assert();
+
+        unsafe {
+            gfx_hal::command::RawCommandBuffer::blit_image(
+                self.inner.raw,
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                filter,
+                regions,
+            )
+        }
+    }
+
     /// Dispatch compute.
     pub fn dispatch(&mut self, x: u32, y: u32, z: u32)
     where
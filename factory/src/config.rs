@@ -2,7 +2,7 @@ use std::cmp::min;
 
 use crate::{
     command::FamilyId,
-    memory::{DynamicConfig, HeapsConfig, LinearConfig},
+    memory::{BuddyConfig, DynamicConfig, HeapsConfig, LinearConfig},
 };
 
 /// Factory initialization config.
@@ -71,7 +71,8 @@ pub unsafe trait HeapsConfigure {
     type Types: IntoIterator<Item = (gfx_hal::memory::Properties, u32, HeapsConfig)>;
 
     /// Iterator over heaps.
-    type Heaps: IntoIterator<Item = u64>;
+    /// Each heap is specified with size and optional soft budget.
+    type Heaps: IntoIterator<Item = (u64, Option<u64>)>;
 
     /// Configure.
     fn configure(
@@ -87,7 +88,7 @@ pub struct BasicHeapsConfigure;
 
 unsafe impl HeapsConfigure for BasicHeapsConfigure {
     type Types = Vec<(gfx_hal::memory::Properties, u32, HeapsConfig)>;
-    type Heaps = Vec<u64>;
+    type Heaps = Vec<(u64, Option<u64>)>;
 
     fn configure(
         self,
@@ -121,14 +122,35 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
                             properties.memory_heaps[mt.heap_index as usize] / 1024,
                         ),
                         blocks_per_chunk: 64,
+                        max_chunk_size: min(
+                            32 * 1024 * 1024,
+                            properties.memory_heaps[mt.heap_index as usize] / 8,
+                        ),
                     }),
+                    buddy: match properties.memory_heaps[mt.heap_index as usize] / 8 {
+                        // Heap is too small to split.
+                        0 => None,
+                        eighth => {
+                            // Largest power of two not greater than 1/8 of the heap.
+                            let chunk_size =
+                                min(64 * 1024 * 1024, 1 << (63 - eighth.leading_zeros()));
+                            Some(BuddyConfig {
+                                min_block_size: min(256 * 1024, chunk_size),
+                                chunk_size,
+                            })
+                        }
+                    },
                 };
 
                 (mt.properties, mt.heap_index as u32, config)
             })
             .collect();
 
-        let heaps = properties.memory_heaps.iter().cloned().collect();
+        let heaps = properties
+            .memory_heaps
+            .iter()
+            .map(|&size| (size, None))
+            .collect();
 
         (types, heaps)
     }
@@ -139,12 +161,12 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SavedHeapsConfig {
     types: Vec<(gfx_hal::memory::Properties, u32, HeapsConfig)>,
-    heaps: Vec<u64>,
+    heaps: Vec<(u64, Option<u64>)>,
 }
 
 unsafe impl HeapsConfigure for SavedHeapsConfig {
     type Types = Vec<(gfx_hal::memory::Properties, u32, HeapsConfig)>;
-    type Heaps = Vec<u64>;
+    type Heaps = Vec<(u64, Option<u64>)>;
 
     fn configure(
         self,
@@ -2,7 +2,10 @@ use {
     crate::{
         command::{families_from_device, CommandPool, Family, FamilyId, Fence, QueueType, Reset},
         config::{Config, DevicesConfigure, HeapsConfigure, QueuesConfigure},
-        memory::{Heaps, Write},
+        memory::{
+            AllocationInfo, Heaps, HeapsError, HeapsUtilization, MemoryBlock, MemoryTypeInfo,
+            MemoryTypePolicy, MemoryUsage, Write,
+        },
         resource::{
             buffer::{self, Buffer},
             image::{self, Image, ImageView},
@@ -22,12 +25,119 @@ use {
 
 static FACTORY_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
+thread_local! {
+    /// Set while eviction callback runs on this thread.
+    /// Heaps are locked during eviction.
+    static EVICTING: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Clears `EVICTING` flag when eviction callback returns or panics.
+struct EvictionGuard;
+
+impl EvictionGuard {
+    fn new() -> Self {
+        EVICTING.with(|evicting| evicting.set(true));
+        EvictionGuard
+    }
+}
+
+impl Drop for EvictionGuard {
+    fn drop(&mut self) {
+        EVICTING.with(|evicting| evicting.set(false));
+    }
+}
+
+/// Memory released by eviction callback.
+#[derive(Debug)]
+pub enum Evicted<B: Backend> {
+    /// Memory block allocated with `Factory::allocate_memory`.
+    Block(MemoryBlock<B>),
+
+    /// Buffer created by the `Factory`.
+    Buffer(Buffer<B>),
+
+    /// Image created by the `Factory`.
+    Image(Image<B>),
+}
+
+impl<B: Backend> From<MemoryBlock<B>> for Evicted<B> {
+    fn from(block: MemoryBlock<B>) -> Self {
+        Evicted::Block(block)
+    }
+}
+
+impl<B: Backend> From<Buffer<B>> for Evicted<B> {
+    fn from(buffer: Buffer<B>) -> Self {
+        Evicted::Buffer(buffer)
+    }
+}
+
+impl<B: Backend> From<Image<B>> for Evicted<B> {
+    fn from(image: Image<B>) -> Self {
+        Evicted::Image(image)
+    }
+}
+
+/// Raw resources which memory was released by eviction.
+/// Heaps are locked during eviction so they are destroyed on cleanup.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+struct EvictedRaw<B: Backend> {
+    buffers: Vec<B::Buffer>,
+    images: Vec<B::Image>,
+}
+
+impl<B> EvictedRaw<B>
+where
+    B: Backend,
+{
+    /// Release memory of the resource.
+    /// Resources kept alive elsewhere can't be evicted.
+    fn release(&mut self, evicted: Evicted<B>) -> Option<MemoryBlock<B>> {
+        match evicted {
+            Evicted::Block(block) => Some(block),
+            Evicted::Buffer(buffer) => match buffer.unescape() {
+                Some(inner) => {
+                    let (raw, block) = inner.dispose();
+                    self.buffers.push(raw);
+                    Some(block)
+                }
+                None => {
+                    log::warn!("Evicted buffer is kept alive and can't be destroyed");
+                    None
+                }
+            },
+            Evicted::Image(image) => match image.unescape() {
+                Some(inner) => {
+                    let (raw, block) = inner.dispose();
+                    self.images.push(raw);
+                    block
+                }
+                None => {
+                    log::warn!("Evicted image is kept alive and can't be destroyed");
+                    None
+                }
+            },
+        }
+    }
+
+    unsafe fn destroy(&mut self, device: &impl Device<B>) {
+        for buffer in self.buffers.drain(..) {
+            device.destroy_buffer(buffer);
+        }
+        for image in self.images.drain(..) {
+            device.destroy_image(image);
+        }
+    }
+}
+
 /// Higher level device interface.
 /// Manges memory, resources and queue families.
 #[derive(derivative::Derivative)]
 #[derivative(Debug)]
 pub struct Factory<B: Backend> {
     heaps: ManuallyDrop<parking_lot::Mutex<Heaps<B>>>,
+    evicted: std::sync::Arc<parking_lot::Mutex<EvictedRaw<B>>>,
     resources: ManuallyDrop<parking_lot::RwLock<Resources<B>>>,
     families: Vec<Family<B>>,
     families_indices: Vec<usize>,
@@ -38,7 +148,7 @@ pub struct Factory<B: Backend> {
     #[derivative(Debug = "ignore")]
     adapter: Adapter<B>,
     #[derivative(Debug = "ignore")]
-    instance: Box<dyn std::any::Any>,
+    instance: Box<dyn std::any::Any + Send + Sync>,
 }
 
 impl<B> Drop for Factory<B>
@@ -61,6 +171,10 @@ where
         }
         log::trace!("Families disposed");
 
+        unsafe {
+            self.evicted.lock().destroy(&self.device);
+        }
+
         unsafe {
             // Device is idle.
             std::ptr::read(&mut *self.resources)
@@ -176,6 +290,7 @@ where
             adapter: adapter,
             device,
             heaps: ManuallyDrop::new(parking_lot::Mutex::new(heaps)),
+            evicted: Default::default(),
             resources: ManuallyDrop::new(parking_lot::RwLock::new(Resources::new())),
             uploads: Uploader::new(families.len()),
             epochs: families
@@ -192,6 +307,20 @@ where
         Ok(factory)
     }
 
+    /// Lock memory heaps.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if called from eviction callback
+    /// as heaps are already locked by the allocation that triggered eviction.
+    fn heaps(&self) -> parking_lot::MutexGuard<'_, Heaps<B>> {
+        assert!(
+            !EVICTING.with(std::cell::Cell::get),
+            "Eviction callback must not allocate or free memory through the Factory"
+        );
+        self.heaps.lock()
+    }
+
     /// Wait for whole device become idle.
     /// This function is very heavy and
     /// usually used only for teardown.
@@ -201,6 +330,77 @@ where
         Ok(())
     }
 
+    /// Get utilization of memory heaps and memory types.
+    pub fn memory_utilization(&self) -> HeapsUtilization {
+        self.heaps().utilization()
+    }
+
+    /// Set soft budget for the memory heap.
+    /// `None` removes the budget.
+    pub fn set_memory_budget(&self, heap_index: usize, budget: Option<u64>) {
+        self.heaps().set_budget(heap_index, budget);
+    }
+
+    /// Get description of all memory types of the device.
+    pub fn memory_types(&self) -> Vec<MemoryTypeInfo> {
+        self.heaps().memory_types().collect()
+    }
+
+    /// Set user tag for the memory block.
+    /// Blocks of resources are accessible with `Buffer::block` and `Image::block`.
+    /// Tag is reported along with the block if it leaks.
+    pub fn set_memory_tag(
+        &self,
+        block: &MemoryBlock<B>,
+        tag: impl Into<std::borrow::Cow<'static, str>>,
+    ) {
+        self.heaps().set_tag(block, tag);
+    }
+
+    /// Get information about all memory blocks that are not freed yet.
+    pub fn live_allocations(&self) -> Vec<AllocationInfo> {
+        self.heaps().live_allocations().cloned().collect()
+    }
+
+    /// Set policy that ranks memory types for allocations.
+    pub fn set_memory_policy(&self, policy: impl MemoryTypePolicy + 'static) {
+        self.heaps().set_policy(policy);
+    }
+
+    /// Set callback to be invoked when allocation would exceed soft budget of the heap
+    /// or the heap is full.
+    /// Callback receives heap index and amount of memory to release.
+    /// Memory of blocks, buffers and images returned by the callback
+    /// is freed before allocation proceeds.
+    /// Returned buffers and images are destroyed on next `cleanup`,
+    /// ones kept alive elsewhere are not evicted.
+    ///
+    /// Callback runs while memory heaps are locked.
+    /// It must not allocate or free memory or resources through the `Factory`,
+    /// such calls panic instead of deadlocking.
+    ///
+    /// # Safety
+    ///
+    /// Device must not use resources returned by the callback.
+    pub unsafe fn set_eviction_callback(
+        &self,
+        mut callback: impl FnMut(usize, u64) -> Vec<Evicted<B>> + Send + 'static,
+    ) {
+        let evicted = self.evicted.clone();
+        self.heaps().set_eviction_callback(move |heap_index, size| {
+            let resources = {
+                let _guard = EvictionGuard::new();
+                callback(heap_index, size)
+            };
+
+            let mut evicted = evicted.lock();
+            resources
+                .into_iter()
+                .filter_map(|resource| evicted.release(resource))
+                .collect()
+        });
+    }
+
     /// Creates a buffer that is managed with the specified properties.
     pub fn create_buffer(
         &self,
@@ -208,7 +408,7 @@ where
         size: u64,
         usage: impl buffer::Usage,
     ) -> Result<Buffer<B>, failure::Error> {
-        let mut heaps = self.heaps.lock();
+        let mut heaps = self.heaps();
         self.resources
             .read()
             .create_buffer(&self.device, &mut heaps, align, size, usage)
@@ -225,7 +425,7 @@ where
         view_caps: image::ViewCapabilities,
         usage: impl image::Usage,
     ) -> Result<Image<B>, failure::Error> {
-        let mut heaps = self.heaps.lock();
+        let mut heaps = self.heaps();
         self.resources.read().create_image(
             &self.device,
             &mut heaps,
@@ -239,6 +439,71 @@ where
         )
     }
 
+    /// Create an image without memory bound to it.
+    /// Returns raw image with its memory requirements.
+    pub fn create_unbound_image(
+        &self,
+        info: &image::Info,
+    ) -> Result<(B::Image, gfx_hal::memory::Requirements), failure::Error> {
+        self.resources
+            .read()
+            .create_unbound_image(&self.device, info)
+    }
+
+    /// Bind memory of the block at `offset` to the raw image.
+    /// Image doesn't own the memory.
+    ///
+    /// # Safety
+    ///
+    /// * `raw` must be created by `create_unbound_image` with the same `info`.
+    /// * Memory region must satisfy image memory requirements.
+    /// * Memory block must not be freed until image is destroyed.
+    ///
+    /// Raw image is destroyed if binding fails.
+    pub unsafe fn bind_image(
+        &self,
+        raw: B::Image,
+        info: image::Info,
+        block: &MemoryBlock<B>,
+        offset: u64,
+    ) -> Result<Image<B>, failure::Error> {
+        self.resources
+            .read()
+            .bind_image(&self.device, raw, info, block, offset)
+    }
+
+    /// Destroy raw image created by `create_unbound_image`
+    /// that wasn't bound to memory.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be created by `create_unbound_image`.
+    pub unsafe fn destroy_unbound_image(&self, raw: B::Image) {
+        self.device.destroy_image(raw);
+    }
+
+    /// Allocate memory block
+    /// from one of memory types specified by `mask`.
+    pub fn allocate_memory(
+        &self,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        self.heaps()
+            .allocate(&self.device, mask, usage, size, align)
+    }
+
+    /// Free memory block allocated with `allocate_memory`.
+    ///
+    /// # Safety
+    ///
+    /// Device must not use the memory.
+    pub unsafe fn free_memory(&self, block: MemoryBlock<B>) {
+        self.heaps().free(&self.device, block);
+    }
+
     /// Create an image view that is managed with the specified properties
     pub fn create_image_view(
         &self,
@@ -412,6 +677,16 @@ where
         }
     }
 
+    /// Recreate swapchain of the target.
+    /// New swapchain images match current extent of the surface.
+    ///
+    /// # Safety
+    ///
+    /// Swapchain images of the target must be not in use.
+    pub unsafe fn recreate_target(&self, target: &mut Target<B>) -> Result<(), failure::Error> {
+        target.recreate(&self.adapter.physical_device, &self.device)
+    }
+
     /// Destroy target returning underlying window back to the caller.
     pub unsafe fn destroy_target(&self, target: Target<B>) {
         target.dispose(&self.device);
@@ -466,6 +741,20 @@ where
         self.device.destroy_semaphore(semaphore);
     }
 
+    /// Create new query pool
+    pub fn create_query_pool(
+        &self,
+        ty: gfx_hal::query::Type,
+        count: gfx_hal::query::Id,
+    ) -> Result<B::QueryPool, gfx_hal::query::Error> {
+        self.device.create_query_pool(ty, count)
+    }
+
+    /// Destroy query pool
+    pub unsafe fn destroy_query_pool(&self, pool: B::QueryPool) {
+        self.device.destroy_query_pool(pool);
+    }
+
     /// Create new fence
     pub fn create_fence(&self, signaled: bool) -> Result<Fence<B>, OutOfMemory> {
         Fence::new(&self.device, signaled)
@@ -629,6 +918,7 @@ where
         let next = self.next_epochs();
         let complete = self.complete_epochs();
         unsafe {
+            self.evicted.lock().destroy(&self.device);
             self.resources
                 .get_mut()
                 .cleanup(&self.device, self.heaps.get_mut(), next, complete);
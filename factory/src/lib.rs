@@ -24,6 +24,6 @@ pub use crate::{
         BasicDevicesConfigure, BasicHeapsConfigure, Config, DevicesConfigure, HeapsConfigure,
         OneGraphicsQueue, QueuesConfigure, SavedHeapsConfig, SavedQueueConfig,
     },
-    factory::Factory,
+    factory::{Evicted, Factory},
     upload::{BufferState, ImageState, ImageStateOrLayout},
 };
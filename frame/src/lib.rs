@@ -12,8 +12,10 @@
 )]
 use rendy_command as command;
 use rendy_factory as factory;
+use rendy_resource as resource;
 
 pub mod cirque;
 mod frame;
+mod ring;
 
-pub use crate::frame::*;
+pub use crate::{frame::*, ring::*};
@@ -0,0 +1,221 @@
+//! Ring allocator for per-frame data.
+
+use {
+    crate::{
+        factory::Factory,
+        frame::Frames,
+        resource::buffer::{Buffer, Usage},
+    },
+    std::{collections::VecDeque, ops::Range},
+};
+
+/// Ring allocator that hands out sub-ranges of one persistently mapped buffer.
+/// Ranges allocated for a frame are reclaimed once that frame is complete.
+///
+/// Buffer usage must have `Dynamic` or `Upload` memory usage
+/// so that buffer memory stays mapped.
+#[derive(Debug)]
+pub struct FrameRing<B: gfx_hal::Backend> {
+    buffer: Buffer<B>,
+    ring: Ring,
+}
+
+impl<B> FrameRing<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Create new `FrameRing` of specified size.
+    pub fn new(factory: &Factory<B>, size: u64, usage: impl Usage) -> Result<Self, failure::Error> {
+        assert!(size > 0, "Ring size must be positive");
+        let buffer = factory.create_buffer(1, size, usage)?;
+        if !buffer.visible() {
+            failure::bail!("Ring buffer must be bound to host-visible memory");
+        }
+
+        Ok(FrameRing {
+            ring: Ring::new(buffer.size()),
+            buffer,
+        })
+    }
+
+    /// Get buffer of the ring.
+    pub fn buffer(&self) -> &Buffer<B> {
+        &self.buffer
+    }
+
+    /// Get size of the ring.
+    pub fn size(&self) -> u64 {
+        self.ring.size
+    }
+
+    /// Get amount of bytes in use by pending frames.
+    pub fn used(&self) -> u64 {
+        self.ring.used()
+    }
+
+    /// Reclaim ranges allocated for complete frames.
+    pub fn reclaim(&mut self, frames: &Frames<B>) {
+        self.ring.reclaim(frames.complete_upper_bound());
+    }
+
+    /// Allocate range of the buffer for the next frame.
+    /// Ranges of complete frames are reclaimed first.
+    /// Returns `None` if ring has not enough free space.
+    pub fn allocate(&mut self, frames: &Frames<B>, size: u64, align: u64) -> Option<Range<u64>> {
+        self.reclaim(frames);
+        self.ring.allocate(frames.next().index(), size, align)
+    }
+
+    /// Allocate range for the next frame and write `data` into it.
+    /// Returns offset of the range in the buffer.
+    ///
+    /// # Safety
+    ///
+    /// * `factory` must be the same `Factory` that created this ring.
+    pub unsafe fn upload<T: Copy>(
+        &mut self,
+        factory: &Factory<B>,
+        frames: &Frames<B>,
+        data: &[T],
+        align: u64,
+    ) -> Result<u64, failure::Error> {
+        let size = (data.len() * std::mem::size_of::<T>()) as u64;
+        let range = self
+            .allocate(frames, size, align)
+            .ok_or_else(|| failure::format_err!("Frame ring is out of space"))?;
+
+        let content = std::slice::from_raw_parts(data.as_ptr() as *const u8, size as usize);
+        let mut mapped = self.buffer.persistent_map(range.clone())?;
+        mapped.write(factory.device(), 0..size)?.write(content);
+        Ok(range.start)
+    }
+}
+
+/// Ranges bookkeeping of `FrameRing`.
+#[derive(Debug)]
+struct Ring {
+    size: u64,
+
+    /// Offset of the next allocation.
+    /// Grows monotonically, offset in the buffer is `head % size`.
+    head: u64,
+
+    /// Offset of the oldest range in use.
+    tail: u64,
+
+    /// Frame indices with end of the last range allocated for the frame.
+    frames: VecDeque<(u64, u64)>,
+}
+
+impl Ring {
+    fn new(size: u64) -> Self {
+        Ring {
+            size,
+            head: 0,
+            tail: 0,
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn used(&self) -> u64 {
+        self.head - self.tail
+    }
+
+    /// Reclaim ranges of frames with indices below `complete`.
+    fn reclaim(&mut self, complete: u64) {
+        while let Some(&(index, end)) = self.frames.front() {
+            if index >= complete {
+                break;
+            }
+            self.tail = end;
+            self.frames.pop_front();
+        }
+    }
+
+    /// Allocate range for the frame with index `next`.
+    fn allocate(&mut self, next: u64, size: u64, align: u64) -> Option<Range<u64>> {
+        assert!(align.is_power_of_two(), "Alignment must be power of two");
+
+        let capacity = self.size;
+        let offset = self.head % capacity;
+        let aligned = (offset + align - 1) & !(align - 1);
+
+        // Skip the rest of the buffer if range doesn't fit before the end.
+        let start = if aligned + size > capacity {
+            self.head + capacity - offset
+        } else {
+            self.head + aligned - offset
+        };
+        let end = start + size;
+
+        if end - self.tail > capacity {
+            return None;
+        }
+
+        self.head = end;
+        match self.frames.back_mut() {
+            Some((last, last_end)) if *last == next => *last_end = end,
+            _ => self.frames.push_back((next, end)),
+        }
+
+        let start = start % capacity;
+        Some(start..start + size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = Ring::new(256);
+        assert_eq!(ring.allocate(0, 96, 1), Some(0..96));
+        assert_eq!(ring.allocate(1, 96, 1), Some(96..192));
+        ring.reclaim(1);
+
+        // Tail of the buffer is too small and is skipped.
+        assert_eq!(ring.allocate(2, 96, 1), Some(0..96));
+        assert_eq!(ring.used(), 96 + 64 + 96);
+
+        // Alignment is applied to the offset in the buffer.
+        ring.reclaim(2);
+        assert_eq!(ring.allocate(3, 16, 64), Some(128..144));
+    }
+
+    #[test]
+    fn reclaims_complete_frames() {
+        let mut ring = Ring::new(256);
+        assert_eq!(ring.allocate(0, 128, 1), Some(0..128));
+        assert_eq!(ring.allocate(0, 64, 1), Some(128..192));
+        assert_eq!(ring.allocate(1, 64, 1), Some(192..256));
+        assert_eq!(ring.allocate(2, 64, 1), None);
+
+        // Frame 0 is still pending.
+        ring.reclaim(0);
+        assert_eq!(ring.allocate(2, 64, 1), None);
+
+        // All ranges of frame 0 are reclaimed at once.
+        ring.reclaim(1);
+        assert_eq!(ring.used(), 64);
+        assert_eq!(ring.allocate(2, 192, 1), Some(0..192));
+        assert_eq!(ring.allocate(2, 1, 1), None);
+
+        ring.reclaim(3);
+        assert_eq!(ring.used(), 0);
+    }
+
+    #[test]
+    fn rejects_oversize_requests() {
+        let mut ring = Ring::new(256);
+        assert_eq!(ring.allocate(0, 257, 1), None);
+        assert_eq!(ring.used(), 0);
+
+        assert_eq!(ring.allocate(0, 256, 1), Some(0..256));
+        ring.reclaim(1);
+
+        // Whole ring fits only if started at the beginning.
+        assert_eq!(ring.allocate(1, 1, 1), Some(0..1));
+        assert_eq!(ring.allocate(1, 256, 1), None);
+    }
+}
@@ -0,0 +1,625 @@
+//! Declarative graph description.
+//!
+//! `GraphDesc` declares buffers, images and nodes that reference each other by name.
+//! `GraphBuilder::add_desc` resolves the names and creates nodes with factories
+//! registered by type name in `NodeRegistry`.
+
+use {
+    super::GraphBuilder,
+    crate::{
+        memory::MemoryUsageValue,
+        node::{
+            render::{RenderGroupDesc, SimpleGraphicsPipeline, SubpassBuilder},
+            NodeBuilder, NodeDesc,
+        },
+        BufferId, ImageId, NodeId,
+    },
+    gfx_hal::Backend,
+    std::collections::HashMap,
+};
+
+/// Declarative description of the graph.
+/// Resources and nodes reference each other by name.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct GraphDesc {
+    /// Buffers owned by graph.
+    #[serde(default)]
+    pub buffers: Vec<BufferEntry>,
+
+    /// Images owned by graph.
+    #[serde(default)]
+    pub images: Vec<ImageEntry>,
+
+    /// Nodes of the graph.
+    #[serde(default)]
+    pub nodes: Vec<NodeEntry>,
+}
+
+/// Buffer declared in `GraphDesc`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BufferEntry {
+    /// Name of the buffer.
+    pub name: String,
+
+    /// Size of the buffer in bytes.
+    pub size: u64,
+
+    /// Memory usage of the buffer.
+    pub memory: MemoryUsageValue,
+}
+
+/// Image declared in `GraphDesc`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ImageEntry {
+    /// Name of the image.
+    pub name: String,
+
+    /// Kind and extent of the image.
+    pub kind: gfx_hal::image::Kind,
+
+    /// Number of mip levels.
+    #[serde(default = "default_levels")]
+    pub levels: gfx_hal::image::Level,
+
+    /// Format of the image.
+    pub format: gfx_hal::format::Format,
+
+    /// Memory usage of the image.
+    pub memory: MemoryUsageValue,
+
+    /// Value image is cleared with before first use in frame.
+    #[serde(default)]
+    pub clear: Option<gfx_hal::command::ClearValue>,
+
+    /// See `GraphBuilder::make_transient`.
+    #[serde(default)]
+    pub transient: bool,
+}
+
+fn default_levels() -> gfx_hal::image::Level {
+    1
+}
+
+/// Node declared in `GraphDesc`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NodeEntry {
+    /// Name of the node.
+    pub name: String,
+
+    /// Name of the factory in `NodeRegistry` that creates the node.
+    pub node: String,
+
+    /// Buffers used by the node.
+    #[serde(default)]
+    pub buffers: Vec<String>,
+
+    /// Images used by the node.
+    #[serde(default)]
+    pub images: Vec<String>,
+
+    /// Color attachments of render group nodes.
+    #[serde(default)]
+    pub colors: Vec<String>,
+
+    /// Depth-stencil attachment of render group nodes.
+    #[serde(default)]
+    pub depth_stencil: Option<String>,
+
+    /// Nodes this one depends on.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+/// Resources of the node entry resolved to ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeResources {
+    /// Buffers used by the node.
+    pub buffers: Vec<BufferId>,
+
+    /// Images used by the node.
+    pub images: Vec<ImageId>,
+
+    /// Color attachments.
+    pub colors: Vec<ImageId>,
+
+    /// Depth-stencil attachment.
+    pub depth_stencil: Option<ImageId>,
+
+    /// Nodes this one depends on.
+    pub dependencies: Vec<NodeId>,
+}
+
+/// Ids of entries added from `GraphDesc`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GraphDescIds {
+    /// Buffer ids by name.
+    pub buffers: HashMap<String, BufferId>,
+
+    /// Image ids by name.
+    pub images: HashMap<String, ImageId>,
+
+    /// Node ids by name.
+    pub nodes: HashMap<String, NodeId>,
+}
+
+/// Error in `GraphDesc`.
+#[derive(Debug, failure::Fail)]
+pub enum GraphDescError {
+    /// Two entries of the same kind have the same name.
+    #[fail(display = "Duplicate {} name `{}`", _0, _1)]
+    DuplicateName(&'static str, String),
+
+    /// Node references buffer that isn't declared.
+    #[fail(display = "Node `{}` references unknown buffer `{}`", _0, _1)]
+    UnknownBuffer(String, String),
+
+    /// Node references image that isn't declared.
+    #[fail(display = "Node `{}` references unknown image `{}`", _0, _1)]
+    UnknownImage(String, String),
+
+    /// Node depends on node that isn't declared.
+    #[fail(display = "Node `{}` depends on unknown node `{}`", _0, _1)]
+    UnknownDependency(String, String),
+
+    /// Node factory is not registered.
+    #[fail(display = "Node `{}` has unregistered type `{}`", _0, _1)]
+    UnknownNodeType(String, String),
+
+    /// Node factory failed.
+    #[fail(display = "Failed to create node `{}`: {}", _0, _1)]
+    Factory(String, failure::Error),
+}
+
+/// Function that creates node builder from resolved resources.
+pub type NodeFactory<B, T> =
+    Box<dyn FnMut(&NodeResources) -> Result<Box<dyn NodeBuilder<B, T>>, failure::Error>>;
+
+/// Named node factories `GraphDesc` nodes are resolved with.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub struct NodeRegistry<B: Backend, T: ?Sized> {
+    #[derivative(Debug = "ignore")]
+    factories: HashMap<String, NodeFactory<B, T>>,
+}
+
+impl<B, T> NodeRegistry<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    /// Create empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register node factory.
+    /// Replaces factory with the same name.
+    pub fn add_factory<F>(&mut self, name: impl Into<String>, factory: F) -> &mut Self
+    where
+        F: FnMut(&NodeResources) -> Result<Box<dyn NodeBuilder<B, T>>, failure::Error> + 'static,
+    {
+        self.factories.insert(name.into(), Box::new(factory));
+        self
+    }
+
+    /// Register node factory.
+    /// Replaces factory with the same name.
+    pub fn with_factory<F>(mut self, name: impl Into<String>, factory: F) -> Self
+    where
+        F: FnMut(&NodeResources) -> Result<Box<dyn NodeBuilder<B, T>>, failure::Error> + 'static,
+    {
+        self.add_factory(name, factory);
+        self
+    }
+
+    /// Register node created from default description.
+    /// Node gets entry's buffers, images and dependencies.
+    pub fn add_node_desc<N>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        B: 'static,
+        T: 'static,
+        N: NodeDesc<B, T> + Default,
+    {
+        self.add_factory(name, |resources: &NodeResources| {
+            let mut builder = N::default().builder();
+            resources.buffers.iter().for_each(|&buffer| {
+                builder.add_buffer(buffer);
+            });
+            resources.images.iter().for_each(|&image| {
+                builder.add_image(image);
+            });
+            resources.dependencies.iter().for_each(|&dependency| {
+                builder.add_dependency(dependency);
+            });
+            Ok(Box::new(builder) as Box<dyn NodeBuilder<B, T>>)
+        })
+    }
+
+    /// Register node created from default description.
+    /// Node gets entry's buffers, images and dependencies.
+    pub fn with_node_desc<N>(mut self, name: impl Into<String>) -> Self
+    where
+        B: 'static,
+        T: 'static,
+        N: NodeDesc<B, T> + Default,
+    {
+        self.add_node_desc::<N>(name);
+        self
+    }
+
+    /// Register render pass with single subpass that draws render group
+    /// created from default description.
+    /// Group gets entry's buffers, images and dependencies.
+    /// Subpass gets entry's color and depth-stencil attachments.
+    pub fn add_render_group<D>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        B: 'static,
+        T: 'static,
+        D: RenderGroupDesc<B, T> + Default + 'static,
+    {
+        self.add_factory(name, |resources: &NodeResources| {
+            let mut group = D::default().builder();
+            resources.buffers.iter().for_each(|&buffer| {
+                group.add_buffer(buffer);
+            });
+            resources.images.iter().for_each(|&image| {
+                group.add_image(image);
+            });
+            resources.dependencies.iter().for_each(|&dependency| {
+                group.add_dependency(dependency);
+            });
+
+            let mut subpass = SubpassBuilder::new().with_group(group);
+            resources.colors.iter().for_each(|&color| {
+                subpass.add_color(color);
+            });
+            if let Some(depth_stencil) = resources.depth_stencil {
+                subpass.set_depth_stencil(depth_stencil);
+            }
+            Ok(Box::new(subpass.into_pass()) as Box<dyn NodeBuilder<B, T>>)
+        })
+    }
+
+    /// Register render pass with single subpass that draws render group
+    /// created from default description.
+    /// Group gets entry's buffers, images and dependencies.
+    /// Subpass gets entry's color and depth-stencil attachments.
+    pub fn with_render_group<D>(mut self, name: impl Into<String>) -> Self
+    where
+        B: 'static,
+        T: 'static,
+        D: RenderGroupDesc<B, T> + Default + 'static,
+    {
+        self.add_render_group::<D>(name);
+        self
+    }
+
+    /// Register render pass that draws `SimpleGraphicsPipeline`.
+    /// Same as `add_render_group` with description of the pipeline.
+    pub fn add_pipeline<P>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        B: 'static,
+        T: 'static,
+        P: SimpleGraphicsPipeline<B, T>,
+    {
+        self.add_render_group::<std::marker::PhantomData<P>>(name)
+    }
+
+    /// Register render pass that draws `SimpleGraphicsPipeline`.
+    /// Same as `add_render_group` with description of the pipeline.
+    pub fn with_pipeline<P>(mut self, name: impl Into<String>) -> Self
+    where
+        B: 'static,
+        T: 'static,
+        P: SimpleGraphicsPipeline<B, T>,
+    {
+        self.add_pipeline::<P>(name);
+        self
+    }
+}
+
+impl<B, T> GraphBuilder<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    /// Add buffers, images and nodes declared in `desc`.
+    /// Node types are resolved with `registry`.
+    ///
+    /// Whole description is validated before anything is added to the builder.
+    pub fn add_desc(
+        &mut self,
+        desc: &GraphDesc,
+        registry: &mut NodeRegistry<B, T>,
+    ) -> Result<GraphDescIds, GraphDescError> {
+        let mut ids = GraphDescIds::default();
+
+        for (index, entry) in desc.buffers.iter().enumerate() {
+            let id = BufferId(self.buffers.len() + index);
+            if ids.buffers.insert(entry.name.clone(), id).is_some() {
+                return Err(GraphDescError::DuplicateName("buffer", entry.name.clone()));
+            }
+        }
+
+        for (index, entry) in desc.images.iter().enumerate() {
+            let id = ImageId(self.images.len() + index);
+            if ids.images.insert(entry.name.clone(), id).is_some() {
+                return Err(GraphDescError::DuplicateName("image", entry.name.clone()));
+            }
+        }
+
+        for (index, entry) in desc.nodes.iter().enumerate() {
+            let id = NodeId(self.nodes.len() + index);
+            if ids.nodes.insert(entry.name.clone(), id).is_some() {
+                return Err(GraphDescError::DuplicateName("node", entry.name.clone()));
+            }
+        }
+
+        let resources = desc
+            .nodes
+            .iter()
+            .map(|entry| {
+                let resources = node_resources(entry, &ids)?;
+                if !registry.factories.contains_key(&entry.node) {
+                    return Err(GraphDescError::UnknownNodeType(
+                        entry.name.clone(),
+                        entry.node.clone(),
+                    ));
+                }
+                Ok(resources)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let builders = desc
+            .nodes
+            .iter()
+            .zip(&resources)
+            .map(|(entry, resources)| {
+                let factory = registry.factories.get_mut(&entry.node).unwrap();
+                factory(resources).map_err(|err| GraphDescError::Factory(entry.name.clone(), err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for entry in &desc.buffers {
+            self.create_buffer(entry.size, entry.memory);
+        }
+
+        for entry in &desc.images {
+            let image = self.create_image(
+                entry.kind,
+                entry.levels,
+                entry.format,
+                entry.memory,
+                entry.clear,
+            );
+            if entry.transient {
+                self.make_transient(image);
+            }
+        }
+
+        for builder in builders {
+            self.add_boxed_node(builder);
+        }
+
+        Ok(ids)
+    }
+
+    /// Add buffers, images and nodes declared in `desc`.
+    /// Node types are resolved with `registry`.
+    ///
+    /// Whole description is validated before anything is added to the builder.
+    pub fn with_desc(
+        mut self,
+        desc: &GraphDesc,
+        registry: &mut NodeRegistry<B, T>,
+    ) -> Result<(Self, GraphDescIds), GraphDescError> {
+        let ids = self.add_desc(desc, registry)?;
+        Ok((self, ids))
+    }
+}
+
+/// Resolve names referenced by node entry.
+fn node_resources(entry: &NodeEntry, ids: &GraphDescIds) -> Result<NodeResources, GraphDescError> {
+    let buffer = |name: &String| {
+        ids.buffers
+            .get(name)
+            .cloned()
+            .ok_or_else(|| GraphDescError::UnknownBuffer(entry.name.clone(), name.clone()))
+    };
+    let image = |name: &String| {
+        ids.images
+            .get(name)
+            .cloned()
+            .ok_or_else(|| GraphDescError::UnknownImage(entry.name.clone(), name.clone()))
+    };
+    let node = |name: &String| {
+        ids.nodes
+            .get(name)
+            .cloned()
+            .ok_or_else(|| GraphDescError::UnknownDependency(entry.name.clone(), name.clone()))
+    };
+
+    Ok(NodeResources {
+        buffers: entry.buffers.iter().map(buffer).collect::<Result<_, _>>()?,
+        images: entry.images.iter().map(image).collect::<Result<_, _>>()?,
+        colors: entry.colors.iter().map(image).collect::<Result<_, _>>()?,
+        depth_stencil: match entry.depth_stencil {
+            Some(ref name) => Some(image(name)?),
+            None => None,
+        },
+        dependencies: entry
+            .dependencies
+            .iter()
+            .map(node)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, gfx_backend_empty::Backend as Empty};
+
+    fn buffer(name: &str) -> BufferEntry {
+        BufferEntry {
+            name: name.to_owned(),
+            size: 1024,
+            memory: MemoryUsageValue::Data,
+        }
+    }
+
+    fn image(name: &str) -> ImageEntry {
+        ImageEntry {
+            name: name.to_owned(),
+            kind: gfx_hal::image::Kind::D2(1, 1, 1, 1),
+            levels: 1,
+            format: gfx_hal::format::Format::Rgba8Unorm,
+            memory: MemoryUsageValue::Data,
+            clear: None,
+            transient: false,
+        }
+    }
+
+    fn node(name: &str) -> NodeEntry {
+        NodeEntry {
+            name: name.to_owned(),
+            node: "draw".to_owned(),
+            buffers: Vec::new(),
+            images: Vec::new(),
+            colors: Vec::new(),
+            depth_stencil: None,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Add description with empty registry.
+    /// Checks that nothing is added to the builder.
+    fn add_desc(desc: GraphDesc) -> GraphDescError {
+        let mut builder = GraphBuilder::<Empty, ()>::new();
+        let err = builder
+            .add_desc(&desc, &mut NodeRegistry::new())
+            .expect_err("Description must be rejected");
+        assert!(builder.buffers.is_empty());
+        assert!(builder.images.is_empty());
+        assert!(builder.nodes.is_empty());
+        err
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let desc = GraphDesc {
+            buffers: vec![buffer("a"), buffer("a")],
+            ..GraphDesc::default()
+        };
+        match add_desc(desc) {
+            GraphDescError::DuplicateName("buffer", name) => assert_eq!(name, "a"),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        let desc = GraphDesc {
+            images: vec![image("a"), image("a")],
+            ..GraphDesc::default()
+        };
+        match add_desc(desc) {
+            GraphDescError::DuplicateName("image", name) => assert_eq!(name, "a"),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        // Different kinds of entries don't clash.
+        let desc = GraphDesc {
+            buffers: vec![buffer("a")],
+            images: vec![image("a")],
+            nodes: vec![node("a"), node("a")],
+        };
+        match add_desc(desc) {
+            GraphDescError::DuplicateName("node", name) => assert_eq!(name, "a"),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn unknown_buffer() {
+        let desc = GraphDesc {
+            buffers: vec![buffer("a")],
+            nodes: vec![NodeEntry {
+                buffers: vec!["a".to_owned(), "b".to_owned()],
+                ..node("n")
+            }],
+            ..GraphDesc::default()
+        };
+        match add_desc(desc) {
+            GraphDescError::UnknownBuffer(node, buffer) => {
+                assert_eq!((&*node, &*buffer), ("n", "b"))
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn unknown_image() {
+        let entries = vec![
+            NodeEntry {
+                images: vec!["b".to_owned()],
+                ..node("n")
+            },
+            NodeEntry {
+                colors: vec!["a".to_owned(), "b".to_owned()],
+                ..node("n")
+            },
+            NodeEntry {
+                depth_stencil: Some("b".to_owned()),
+                ..node("n")
+            },
+        ];
+
+        for entry in entries {
+            let desc = GraphDesc {
+                images: vec![image("a")],
+                nodes: vec![entry],
+                ..GraphDesc::default()
+            };
+            match add_desc(desc) {
+                GraphDescError::UnknownImage(node, image) => {
+                    assert_eq!((&*node, &*image), ("n", "b"))
+                }
+                err => panic!("Unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_dependency() {
+        let desc = GraphDesc {
+            nodes: vec![
+                node("a"),
+                NodeEntry {
+                    dependencies: vec!["a".to_owned(), "b".to_owned()],
+                    ..node("n")
+                },
+            ],
+            ..GraphDesc::default()
+        };
+        match add_desc(desc) {
+            GraphDescError::UnknownDependency(node, dependency) => {
+                assert_eq!((&*node, &*dependency), ("n", "b"))
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn unknown_node_type() {
+        let desc = GraphDesc {
+            buffers: vec![buffer("a")],
+            images: vec![image("a")],
+            nodes: vec![NodeEntry {
+                buffers: vec!["a".to_owned()],
+                colors: vec!["a".to_owned()],
+                ..node("n")
+            }],
+        };
+        match add_desc(desc) {
+            GraphDescError::UnknownNodeType(node, ty) => assert_eq!((&*node, &*ty), ("n", "draw")),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+}
@@ -1,23 +1,201 @@
 use {
     crate::{
-        chain, command,
+        chain,
+        command::{
+            self, CommandBuffer, CommandPool, ExecutableState, FamilyId, IndividualReset,
+            MultiShot, PendingState, SimultaneousUse, Submission, Submit,
+        },
         factory::Factory,
         frame::{Fences, Frames},
-        memory::MemoryUsageValue,
-        node::{DynNode, NodeBuilder},
+        memory::{Block, MemoryBlock, MemoryUsageValue},
+        node::{
+            buffer_barriers, image_barriers, image_layout, BufferBarrier, DynNode, ImageBarrier,
+            NodeBuilder, NodeSubmit, NodeSubmits,
+        },
         resource::{buffer, image},
         BufferId, ImageId, NodeId,
     },
     gfx_hal::Backend,
 };
 
+#[cfg(feature = "serde")]
+mod desc;
+mod profile;
+
+use self::profile::Profiler;
+pub(crate) use self::profile::RecordedStatistics;
+pub use self::profile::{NodeProfile, ProfileReport, StatisticsQuery, TimestampProperties};
+
+#[cfg(feature = "serde")]
+pub use self::desc::*;
+
 // TODO: Use actual limits.
 const UNIVERSAL_ALIGNMENT: u64 = 512;
 
+type BufferDesc = (buffer::Info, MemoryUsageValue);
+type ImageDesc = (
+    image::Info,
+    MemoryUsageValue,
+    Option<gfx_hal::command::ClearValue>,
+);
+type GraphImage<B> = (Box<image::Image<B>>, Option<gfx_hal::command::ClearValue>);
+
+/// Everything node was built with besides its builder.
+/// Node can be reused in rebuilt graph only if signature is unchanged.
+#[derive(Clone, Debug, PartialEq)]
+struct NodeSignature {
+    buffers: Vec<(BufferId, Option<BufferBarrier>, Option<BufferBarrier>)>,
+    images: Vec<(
+        ImageId,
+        gfx_hal::image::Layout,
+        Option<gfx_hal::command::ClearValue>,
+        Option<ImageBarrier>,
+        Option<ImageBarrier>,
+    )>,
+}
+
+/// Predicate that decides if node runs this frame.
+pub type Condition<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+/// Commands submitted instead of skipped node.
+/// Contains only acquire and release barriers of the node
+/// so that resources end up in the same state as if node had run.
 #[derive(Debug)]
+struct SkipCommands<B: Backend> {
+    pool: CommandPool<B, gfx_hal::QueueType, IndividualReset>,
+    submit: Submit<B, SimultaneousUse>,
+    buffer: CommandBuffer<
+        B,
+        gfx_hal::QueueType,
+        PendingState<ExecutableState<MultiShot<SimultaneousUse>>>,
+    >,
+}
+
+impl<B> SkipCommands<B>
+where
+    B: Backend,
+{
+    /// Record barriers from node signature.
+    fn new(
+        factory: &Factory<B>,
+        family: FamilyId,
+        signature: &NodeSignature,
+        buffers: &[Option<buffer::Buffer<B>>],
+        images: &[Option<GraphImage<B>>],
+    ) -> Result<Self, failure::Error> {
+        let mut pool = factory.create_command_pool(family)?;
+        let initial = pool.allocate_buffers(1).pop().unwrap();
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+        for &release in &[false, true] {
+            let (stages, barriers) = skip_barriers(signature, buffers, images, release);
+            if !barriers.is_empty() {
+                recording.encoder().pipeline_barrier(
+                    stages,
+                    gfx_hal::memory::Dependencies::empty(),
+                    barriers,
+                );
+            }
+        }
+        let (submit, buffer) = recording.finish().submit();
+        Ok(SkipCommands {
+            pool,
+            submit,
+            buffer,
+        })
+    }
+
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        drop(self.submit);
+        let mut pool = self.pool;
+        pool.free_buffers(Some(self.buffer.mark_complete()));
+        factory.destroy_command_pool(pool);
+    }
+}
+
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
 struct GraphNode<B: Backend, T: ?Sized> {
     node: Box<dyn DynNode<B, T>>,
+    name: String,
     queue: command::QueueId,
+    key: Option<u64>,
+    signature: NodeSignature,
+    #[derivative(Debug = "ignore")]
+    condition: Option<Condition<T>>,
+    skip: Option<SkipCommands<B>>,
+}
+
+impl<B, T> GraphNode<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    unsafe fn dispose(self, factory: &mut Factory<B>, aux: &mut T) {
+        self.node.dispose(factory, aux);
+        if let Some(skip) = self.skip {
+            skip.dispose(factory);
+        }
+    }
+}
+
+/// Objects replaced during graph rebuild.
+/// They are disposed when all frames submitted before rebuild complete.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
+struct Retired<B: Backend, T: ?Sized> {
+    frame: u64,
+    nodes: Vec<GraphNode<B, T>>,
+    semaphores: Vec<B::Semaphore>,
+    buffers: Vec<buffer::Buffer<B>>,
+    images: Vec<Box<image::Image<B>>>,
+    aliased_memory: Vec<MemoryBlock<B>>,
+    profiler: Option<Profiler<B>>,
+}
+
+impl<B, T> Retired<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    /// Dispose of retired objects.
+    ///
+    /// # Safety
+    ///
+    /// Frame after which objects were retired must be complete.
+    unsafe fn dispose(self, factory: &mut Factory<B>, aux: &mut T) {
+        for node in self.nodes {
+            node.dispose(factory, aux);
+        }
+
+        for semaphore in self.semaphores {
+            factory.destroy_semaphore(semaphore);
+        }
+
+        drop(self.buffers);
+        drop(self.images);
+        for block in self.aliased_memory {
+            factory.free_memory(block);
+        }
+
+        if let Some(profiler) = self.profiler {
+            profiler.dispose(factory);
+        }
+    }
+}
+
+/// Statistics of the frame submitted by `Graph::run`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Index of the frame.
+    pub frame: u64,
+
+    /// CPU time spent in `Frames::wait_complete` before the frame was recorded.
+    /// Non-zero wait means GPU is behind and rendering is GPU-bound.
+    pub wait: std::time::Duration,
+
+    /// Frame range from `Frames::range` after waiting:
+    /// upper bound of complete frames .. this frame.
+    pub range: std::ops::Range<u64>,
 }
 
 /// Graph that renders whole frame.
@@ -26,11 +204,21 @@ pub struct Graph<B: Backend, T: ?Sized> {
     nodes: Vec<GraphNode<B, T>>,
     schedule: chain::Schedule<chain::SyncData<usize, usize>>,
     semaphores: Vec<B::Semaphore>,
-    buffers: Vec<buffer::Buffer<B>>,
-    images: Vec<image::Image<B>>,
+    semaphore_queues: Vec<(Option<chain::QueueId>, Option<chain::QueueId>)>,
+    buffers: Vec<Option<buffer::Buffer<B>>>,
+    /// Images are never moved after nodes are built.
+    /// Nodes may keep pointers to them.
+    images: Vec<Option<GraphImage<B>>>,
+    aliased_memory: Vec<MemoryBlock<B>>,
+    buffer_descs: Vec<BufferDesc>,
+    image_descs: Vec<ImageDesc>,
+    transient_images: Vec<ImageId>,
+    retired: Vec<Retired<B, T>>,
     frames: Frames<B>,
     fences: Vec<Fences<B>>,
     inflight: u64,
+    stats: Option<FrameStats>,
+    profiler: Option<Profiler<B>>,
 }
 
 impl<B, T> Graph<B, T>
@@ -57,6 +245,52 @@ where
     ///               If this function needs more fences they will be allocated from `device` and pushed to this `Vec`.
     ///               So it's OK to start with empty `Vec`.
     pub fn run(&mut self, factory: &mut Factory<B>, aux: &mut T) {
+        self.begin_frame(factory, aux);
+        let enabled = self.enabled(aux);
+        let recorded = (0..self.nodes.len()).map(|_| None).collect();
+        self.submit_frame(factory, aux, &enabled, recorded);
+    }
+
+    /// Perform graph execution recording command buffers in parallel.
+    /// Nodes record commands on `rayon` thread pool
+    /// and resulting command buffers are submitted in order computed by the schedule.
+    /// Nodes that don't support recording with shared access
+    /// are run on the calling thread during submission.
+    #[cfg(feature = "parallel")]
+    pub fn run_parallel(&mut self, factory: &mut Factory<B>, aux: &mut T)
+    where
+        T: Sync,
+        Factory<B>: Sync,
+    {
+        use rayon::prelude::*;
+
+        self.begin_frame(factory, aux);
+        let enabled = self.enabled(aux);
+        let recorded = {
+            let factory = &*factory;
+            let aux = &*aux;
+            let frames = &self.frames;
+            self.nodes
+                .iter_mut()
+                .zip(&enabled)
+                .map(|(node, &enabled)| (&mut node.node, enabled))
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|(node, enabled)| {
+                    if enabled {
+                        unsafe { node.record(factory, aux, frames) }
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
+        self.submit_frame(factory, aux, &enabled, recorded);
+    }
+
+    /// Wait for frames in flight, collect statistics and dispose retired objects.
+    fn begin_frame(&mut self, factory: &mut Factory<B>, aux: &mut T) {
+        let start = std::time::Instant::now();
         if self.frames.next().index() >= self.inflight {
             let wait = self.frames.next().index() - self.inflight;
             let ref mut self_fences = self.fences;
@@ -65,17 +299,69 @@ where
                 self_fences.push(fences);
             });
         }
+        self.stats = Some(FrameStats {
+            frame: self.frames.next().index(),
+            wait: start.elapsed(),
+            range: self.frames.range(),
+        });
+
+        let complete = self.frames.complete_upper_bound();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.collect(factory, complete);
+        }
+
+        while self
+            .retired
+            .first()
+            .map_or(false, |retired| retired.frame <= complete)
+        {
+            log::trace!("Dispose objects retired by graph rebuild");
+            unsafe {
+                // All frames that could use those objects are complete.
+                self.retired.remove(0).dispose(factory, aux);
+            }
+        }
+    }
+
+    /// Evaluate node conditions for the next frame.
+    fn enabled(&self, aux: &T) -> Vec<bool> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                node.condition
+                    .as_ref()
+                    .map_or(true, |condition| condition(aux))
+            })
+            .collect()
+    }
 
+    /// Submit command buffers of all nodes in schedule order.
+    /// Nodes without recorded command buffers are run here.
+    fn submit_frame(
+        &mut self,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        enabled: &[bool],
+        mut recorded: Vec<Option<NodeSubmits<B>>>,
+    ) {
         let mut fences = self.fences.pop().unwrap_or_else(Fences::<B>::default);
         let mut fences_used = 0;
         let ref semaphores = self.semaphores;
 
+        let frame = self.frames.next().index();
+        let profiler = self.profiler.as_mut().and_then(|profiler| {
+            let slot = profiler.begin_frame(frame, enabled)?;
+            Some((&*profiler, slot))
+        });
+
         for submission in self.schedule.ordered() {
             log::trace!("Run node {}", submission.node());
             let sid = submission.id();
             let qid = sid.queue();
 
-            let GraphNode { node, queue } = self
+            let GraphNode {
+                node, queue, skip, ..
+            } = self
                 .nodes
                 .get_mut(submission.node())
                 .expect("Submission references node with out of bound index");
@@ -96,40 +382,84 @@ where
                 None
             };
 
-            unsafe {
-                node.run(
-                    factory,
-                    aux,
-                    &self.frames,
-                    *queue,
-                    &submission
-                        .sync()
-                        .wait
-                        .iter()
-                        .map(|wait| {
-                            log::trace!(
-                                "Node {} waits for {}",
-                                submission.node(),
-                                *wait.semaphore()
-                            );
-                            (&semaphores[*wait.semaphore()], wait.stage())
-                        })
-                        .collect::<smallvec::SmallVec<[_; 16]>>(),
-                    &submission
-                        .sync()
-                        .signal
-                        .iter()
-                        .map(|signal| {
-                            log::trace!(
-                                "Node {} signals {}",
-                                submission.node(),
-                                *signal.semaphore()
-                            );
-                            &semaphores[*signal.semaphore()]
-                        })
-                        .collect::<smallvec::SmallVec<[_; 16]>>(),
-                    fence,
-                )
+            let waits = submission
+                .sync()
+                .wait
+                .iter()
+                .map(|wait| {
+                    log::trace!("Node {} waits for {}", submission.node(), *wait.semaphore());
+                    (&semaphores[*wait.semaphore()], wait.stage())
+                })
+                .collect::<smallvec::SmallVec<[_; 16]>>();
+            let signals = submission
+                .sync()
+                .signal
+                .iter()
+                .map(|signal| {
+                    log::trace!("Node {} signals {}", submission.node(), *signal.semaphore());
+                    &semaphores[*signal.semaphore()]
+                })
+                .collect::<smallvec::SmallVec<[_; 16]>>();
+
+            let (begin, end) = match profiler
+                .and_then(|(profiler, slot)| profiler.submits(slot, submission.node()))
+            {
+                Some((begin, end)) => (Some(begin), Some(end)),
+                None => (None, None),
+            };
+
+            if !enabled[submission.node()] {
+                log::trace!("Skip node {}", submission.node());
+                unsafe {
+                    // Barriers and semaphores are the same the node would use.
+                    factory.family_mut(queue.family()).queues_mut()[queue.index()].submit(
+                        Some(
+                            Submission::new()
+                                .submits(
+                                    begin
+                                        .into_iter()
+                                        .chain(skip.as_ref().map(|skip| &skip.submit))
+                                        .chain(end),
+                                )
+                                .wait(waits.iter().cloned())
+                                .signal(signals.iter().cloned()),
+                        ),
+                        fence,
+                    );
+                }
+            } else if let Some(submits) = recorded[submission.node()].take() {
+                unsafe {
+                    // Timestamp submits outlive the submission.
+                    factory.family_mut(queue.family()).queues_mut()[queue.index()].submit(
+                        Some(
+                            Submission::new()
+                                .submits(
+                                    begin
+                                        .map(|begin| NodeSubmit::new(begin))
+                                        .into_iter()
+                                        .chain(submits)
+                                        .chain(end.map(|end| NodeSubmit::new(end))),
+                                )
+                                .wait(waits.iter().cloned())
+                                .signal(signals.iter().cloned()),
+                        ),
+                        fence,
+                    );
+                }
+            } else if let (Some(begin), Some(end)) = (begin, end) {
+                unsafe {
+                    // Node submits its own commands.
+                    // Timestamps are submitted separately and end submission gets the fence.
+                    factory.family_mut(queue.family()).queues_mut()[queue.index()]
+                        .submit(Some(Submission::new().submits(Some(begin))), None);
+                    node.run(factory, aux, &self.frames, *queue, &waits, &signals, None);
+                    factory.family_mut(queue.family()).queues_mut()[queue.index()]
+                        .submit(Some(Submission::new().submits(Some(end))), fence);
+                }
+            } else {
+                unsafe {
+                    node.run(factory, aux, &self.frames, *queue, &waits, &signals, fence);
+                }
             }
         }
 
@@ -139,6 +469,30 @@ where
         }
     }
 
+    /// Get GPU timings of the latest profiled frame that is complete.
+    /// Returns `None` if profiling is disabled or unsupported,
+    /// or no profiled frame has completed yet.
+    pub fn profile_report(&self) -> Option<&ProfileReport> {
+        self.profiler.as_ref().and_then(Profiler::report)
+    }
+
+    /// Get statistics of the last frame submitted by `run`.
+    pub fn frame_stats(&self) -> Option<&FrameStats> {
+        self.stats.as_ref()
+    }
+
+    /// Get maximum number of frames in flight.
+    pub fn frames_in_flight(&self) -> u32 {
+        self.inflight as u32
+    }
+
+    /// Set maximum number of frames in flight.
+    /// If decreased, next `run` waits for extra frames to complete.
+    pub fn set_frames_in_flight(&mut self, count: u32) {
+        assert!(count > 0, "At least one frame must be in flight");
+        self.inflight = count as u64;
+    }
+
     /// Get queue that will exeute given node.
     pub fn node_queue(&self, node: NodeId) -> command::QueueId {
         self.nodes[node.0].queue
@@ -151,28 +505,44 @@ where
 
         unsafe {
             // Device is idle.
+            for retired in self.retired {
+                retired.dispose(factory, data);
+            }
+
             for node in self.nodes {
-                node.node.dispose(factory, data);
+                node.dispose(factory, data);
             }
 
             for semaphore in self.semaphores {
                 factory.destroy_semaphore(semaphore);
             }
+
+            for block in self.aliased_memory {
+                factory.free_memory(block);
+            }
+
+            if let Some(profiler) = self.profiler {
+                profiler.dispose(factory);
+            }
         }
     }
 }
 
 /// Build graph from nodes and resource.
-#[derive(Debug)]
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
 pub struct GraphBuilder<B: Backend, T: ?Sized> {
     nodes: Vec<Box<dyn NodeBuilder<B, T>>>,
-    buffers: Vec<(buffer::Info, MemoryUsageValue)>,
-    images: Vec<(
-        image::Info,
-        MemoryUsageValue,
-        Option<gfx_hal::command::ClearValue>,
-    )>,
+    keys: Vec<Option<u64>>,
+    #[derivative(Debug = "ignore")]
+    conditions: Vec<Option<Condition<T>>>,
+    buffers: Vec<BufferDesc>,
+    images: Vec<ImageDesc>,
+    transient_images: Vec<ImageId>,
     target_count: usize,
+    frames_in_flight: Option<u32>,
+    profiling: Option<Option<TimestampProperties>>,
+    pipeline_statistics: Option<gfx_hal::query::PipelineStatistic>,
 }
 
 impl<B, T> GraphBuilder<B, T>
@@ -184,12 +554,93 @@ where
     pub fn new() -> Self {
         GraphBuilder {
             nodes: Vec::new(),
+            keys: Vec::new(),
+            conditions: Vec::new(),
             buffers: Vec::new(),
             images: Vec::new(),
+            transient_images: Vec::new(),
             target_count: 0,
+            frames_in_flight: None,
+            profiling: None,
+            pipeline_statistics: None,
         }
     }
 
+    /// Set maximum number of frames graph renders simultaneously.
+    /// Defaults to 3, or to the value of the graph being rebuilt.
+    pub fn set_frames_in_flight(&mut self, count: u32) -> &mut Self {
+        assert!(count > 0, "At least one frame must be in flight");
+        self.frames_in_flight = Some(count);
+        self
+    }
+
+    /// Set maximum number of frames graph renders simultaneously.
+    /// Defaults to 3, or to the value of the graph being rebuilt.
+    pub fn with_frames_in_flight(mut self, count: u32) -> Self {
+        self.set_frames_in_flight(count);
+        self
+    }
+
+    /// Enable GPU profiling of the nodes with timestamp queries.
+    /// Results are available with `Graph::profile_report` when profiled frames complete.
+    /// Profiling is disabled if device doesn't support timestamp queries.
+    /// Nodes on queues without valid timestamp bits are not profiled.
+    ///
+    /// Defaults to disabled, or to the setting of the graph being rebuilt.
+    pub fn set_profiling(&mut self, properties: TimestampProperties) -> &mut Self {
+        self.profiling = Some(Some(properties));
+        self
+    }
+
+    /// Enable GPU profiling of the nodes with timestamp queries.
+    /// Results are available with `Graph::profile_report` when profiled frames complete.
+    /// Profiling is disabled if device doesn't support timestamp queries.
+    /// Nodes on queues without valid timestamp bits are not profiled.
+    ///
+    /// Defaults to disabled, or to the setting of the graph being rebuilt.
+    pub fn with_profiling(mut self, properties: TimestampProperties) -> Self {
+        self.set_profiling(properties);
+        self
+    }
+
+    /// Disable GPU profiling.
+    pub fn set_no_profiling(&mut self) -> &mut Self {
+        self.profiling = Some(None);
+        self
+    }
+
+    /// Disable GPU profiling.
+    pub fn with_no_profiling(mut self) -> Self {
+        self.set_no_profiling();
+        self
+    }
+
+    /// Collect pipeline statistics of the nodes when profiling is enabled.
+    /// Only nodes that record statistics queries into their command buffers report them.
+    /// Pass empty flags to disable statistics.
+    ///
+    /// Defaults to disabled, or to the setting of the graph being rebuilt.
+    pub fn set_pipeline_statistics(
+        &mut self,
+        statistics: gfx_hal::query::PipelineStatistic,
+    ) -> &mut Self {
+        self.pipeline_statistics = Some(statistics);
+        self
+    }
+
+    /// Collect pipeline statistics of the nodes when profiling is enabled.
+    /// Only nodes that record statistics queries into their command buffers report them.
+    /// Pass empty flags to disable statistics.
+    ///
+    /// Defaults to disabled, or to the setting of the graph being rebuilt.
+    pub fn with_pipeline_statistics(
+        mut self,
+        statistics: gfx_hal::query::PipelineStatistic,
+    ) -> Self {
+        self.set_pipeline_statistics(statistics);
+        self
+    }
+
     /// Create new buffer owned by graph.
     pub fn create_buffer(&mut self, size: u64, memory: MemoryUsageValue) -> BufferId {
         self.buffers.push((
@@ -226,12 +677,51 @@ where
         ImageId(self.images.len() - 1)
     }
 
+    /// Mark image as transient.
+    /// Content of transient image doesn't persist between frames.
+    /// Transient images used by a single queue at different times
+    /// share memory.
+    pub fn make_transient(&mut self, image: ImageId) {
+        assert!(image.0 < self.images.len());
+        if !self.transient_images.contains(&image) {
+            self.transient_images.push(image);
+        }
+    }
+
     /// Add node to the graph.
     pub fn add_node<N: NodeBuilder<B, T> + 'static>(&mut self, builder: N) -> NodeId {
-        self.nodes.push(Box::new(builder));
+        self.add_boxed_node(Box::new(builder))
+    }
+
+    fn add_boxed_node(&mut self, builder: Box<dyn NodeBuilder<B, T>>) -> NodeId {
+        self.nodes.push(builder);
+        self.keys.push(None);
+        self.conditions.push(None);
         NodeId(self.nodes.len() - 1)
     }
 
+    /// Set key of the node.
+    /// Key must identify node and all its parameters.
+    /// `rebuild` reuses node from old graph with the same key
+    /// if resources and synchronization of the node are unchanged.
+    /// Nodes without key are always rebuilt.
+    pub fn set_key(&mut self, node: NodeId, key: u64) {
+        self.keys[node.0] = Some(key);
+    }
+
+    /// Set condition of the node.
+    /// Node runs only on frames for which `condition` returns `true`.
+    /// On other frames only its barriers are submitted
+    /// and semaphores are waited and signaled as usual.
+    /// Content of resources the node writes is left unchanged.
+    pub fn set_condition(
+        &mut self,
+        node: NodeId,
+        condition: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) {
+        self.conditions[node.0] = Some(Box::new(condition));
+    }
+
     /// Build `Graph`.
     ///
     /// # Parameters
@@ -248,9 +738,112 @@ where
         factory: &mut Factory<B>,
         aux: &mut T,
     ) -> Result<Graph<B, T>, failure::Error> {
+        self.build_with(factory, aux, None)
+    }
+
+    /// Build `Graph` replacing `graph`.
+    ///
+    /// Nodes with unchanged keys, resources and synchronization are moved from `graph`.
+    /// Buffers and non-transient images with the same description and usage are reused,
+    /// as well as semaphores between the same queues.
+    /// Everything else from `graph` is disposed after frames submitted so far complete.
+    /// Frames in flight continue in the new graph without waiting.
+    ///
+    /// Objects of `graph` are leaked if rebuilding fails.
+    pub fn rebuild(
+        self,
+        graph: Graph<B, T>,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+    ) -> Result<Graph<B, T>, failure::Error> {
+        self.build_with(factory, aux, Some(graph))
+    }
+
+    fn build_with(
+        self,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        old: Option<Graph<B, T>>,
+    ) -> Result<Graph<B, T>, failure::Error> {
+        let GraphBuilder {
+            nodes,
+            keys,
+            mut conditions,
+            buffers: buffer_descs,
+            images: image_descs,
+            transient_images,
+            frames_in_flight,
+            profiling,
+            pipeline_statistics,
+            ..
+        } = self;
+
+        let (mut old, frames, fences, mut inflight, mut retired) = match old {
+            Some(graph) => {
+                let Graph {
+                    nodes,
+                    semaphores,
+                    semaphore_queues,
+                    buffers,
+                    images,
+                    aliased_memory,
+                    buffer_descs,
+                    image_descs,
+                    transient_images,
+                    retired,
+                    frames,
+                    fences,
+                    inflight,
+                    profiler,
+                    ..
+                } = graph;
+                let old = Old {
+                    nodes: nodes.into_iter().map(Some).collect(),
+                    semaphores: semaphore_queues
+                        .into_iter()
+                        .zip(semaphores)
+                        .map(Some)
+                        .collect(),
+                    buffers: buffers
+                        .into_iter()
+                        .zip(buffer_descs)
+                        .map(|(buffer, desc)| buffer.map(|buffer| (buffer, desc)))
+                        .collect(),
+                    images: images
+                        .into_iter()
+                        .zip(image_descs)
+                        .enumerate()
+                        .map(|(index, (image, desc))| {
+                            image.map(|(image, _)| {
+                                (image, desc, transient_images.contains(&ImageId(index)))
+                            })
+                        })
+                        .collect(),
+                    aliased_memory,
+                    profiler,
+                };
+                (old, frames, fences, inflight, retired)
+            }
+            None => (Old::default(), Frames::new(), Vec::new(), 3, Vec::new()),
+        };
+        if let Some(count) = frames_in_flight {
+            inflight = count as u64;
+        }
+        let timestamp_properties = profiling.unwrap_or_else(|| {
+            old.profiler
+                .as_ref()
+                .map(|profiler| profiler.properties().clone())
+        });
+        let pipeline_statistics = pipeline_statistics.unwrap_or_else(|| {
+            old.profiler
+                .as_ref()
+                .map_or(gfx_hal::query::PipelineStatistic::empty(), |profiler| {
+                    profiler.statistics()
+                })
+        });
+
         log::trace!("Schedule nodes execution");
-        let chain_nodes: Vec<chain::Node> = self
-            .nodes
+        let chain_nodes: Vec<chain::Node> = nodes
             .iter()
             .enumerate()
             .map(|(i, b)| make_chain_node(&**b, i, &factory))
@@ -260,8 +853,7 @@ where
         log::trace!("Scheduled nodes execution {:#?}", chains);
 
         log::trace!("Allocate buffers");
-        let mut buffers: Vec<Option<buffer::Buffer<B>>> = self
-            .buffers
+        let mut buffers: Vec<Option<buffer::Buffer<B>>> = buffer_descs
             .iter()
             .enumerate()
             .map(|(index, &(ref info, memory))| {
@@ -269,24 +861,45 @@ where
                     .buffers
                     .get(&chain::Id(index))
                     .map(|buffer| {
+                        let usage = buffer.usage();
+                        if let Some(buffer) = old.take_buffer(index, |existing, desc| {
+                            *desc == (*info, memory) && existing.info().usage == usage
+                        }) {
+                            log::trace!("Reuse buffer {}", index);
+                            return Ok(Some(buffer));
+                        }
                         factory
-                            .create_buffer(UNIVERSAL_ALIGNMENT, info.size, (buffer.usage(), memory))
+                            .create_buffer(UNIVERSAL_ALIGNMENT, info.size, (usage, memory))
                             .map(|buffer| Some(buffer))
                     })
                     .unwrap_or(Ok(None))
             })
             .collect::<Result<_, _>>()?;
 
+        log::trace!("Allocate aliased images");
+        let (aliasing, aliased_images, aliased_memory) =
+            alias_images(factory, &image_descs, &transient_images, &chains)?;
+
         log::trace!("Allocate images");
-        let mut images: Vec<Option<(image::Image<B>, _)>> = self
-            .images
+        let mut images: Vec<Option<GraphImage<B>>> = image_descs
             .iter()
+            .zip(aliased_images)
             .enumerate()
-            .map(|(index, (info, memory, clear))| {
+            .map(|(index, ((info, memory, clear), aliased))| {
+                if let Some(image) = aliased {
+                    return Ok(Some((Box::new(image), *clear)));
+                }
                 chains
                     .images
                     .get(&chain::Id(index))
                     .map(|image| {
+                        let usage = image.usage();
+                        if let Some(image) = old.take_image(index, |existing, desc| {
+                            (desc.0, desc.1) == (*info, *memory) && existing.info().usage == usage
+                        }) {
+                            log::trace!("Reuse image {}", index);
+                            return Ok(Some((image, *clear)));
+                        }
                         factory
                             .create_image(
                                 UNIVERSAL_ALIGNMENT,
@@ -295,17 +908,17 @@ where
                                 info.format,
                                 info.tiling,
                                 info.view_caps,
-                                (image.usage(), *memory),
+                                (usage, *memory),
                             )
-                            .map(|image| Some((image, *clear)))
+                            .map(|image| Some((Box::new(image), *clear)))
                     })
                     .unwrap_or(Ok(None))
             })
-            .collect::<Result<_, _>>()?;
+            .collect::<Result<_, failure::Error>>()?;
 
         log::trace!("Synchronize");
         let mut semaphores = 0..;
-        let mut schedule = chain::sync(&chains, || {
+        let mut schedule = chain::sync_aliased(&chains, &aliasing, || {
             let id = semaphores.next().unwrap();
             (id, id)
         });
@@ -313,8 +926,8 @@ where
         log::info!("Schedule: {:#?}", schedule);
 
         log::trace!("Build nodes");
-        let mut built_nodes: Vec<_> = (0..self.nodes.len()).map(|_| None).collect();
-        let mut node_descs: Vec<_> = self.nodes.into_iter().map(Some).collect();
+        let mut built_nodes: Vec<_> = (0..nodes.len()).map(|_| None).collect();
+        let mut node_descs: Vec<_> = nodes.into_iter().map(Some).collect();
         for family in schedule.iter() {
             log::trace!("For family {:#?}", family);
             for queue in family.iter() {
@@ -322,49 +935,478 @@ where
                 for submission in queue.iter() {
                     log::trace!("For submission {:#?}", submission.id());
                     let builder = node_descs[submission.node()].take().unwrap();
-                    log::trace!("Build node {:#?}", builder);
-                    let node = builder.build_impl(
-                        factory,
-                        aux,
-                        family.id(),
-                        &mut buffers,
-                        &mut images,
-                        &chains,
-                        &submission,
-                    )?;
+                    let name = builder.name();
+                    let qid = submission.id().queue();
+                    let queue = command::QueueId(qid.family(), qid.index());
+                    let key = keys[submission.node()];
+                    let signature = node_signature(&*builder, &chains, &submission, &images);
+
+                    let condition = conditions[submission.node()].take();
+
+                    let reused = key.and_then(|key| old.take_node(key, queue, &signature));
+                    let (node, skip) = match reused {
+                        Some(reused) => {
+                            log::debug!("Reuse node {:#?}", builder);
+                            (reused.node, reused.skip)
+                        }
+                        None => {
+                            log::trace!("Build node {:#?}", builder);
+                            let node = builder.build_impl(
+                                factory,
+                                aux,
+                                family.id(),
+                                &mut buffers,
+                                &mut images,
+                                &chains,
+                                &submission,
+                            )?;
+                            (node, None)
+                        }
+                    };
                     log::debug!("Node built: {:#?}", node);
-                    built_nodes[submission.node()] = Some((node, submission.id().queue()));
+
+                    let skip = match skip {
+                        None if condition.is_some() => Some(SkipCommands::new(
+                            factory,
+                            family.id(),
+                            &signature,
+                            &buffers,
+                            &images,
+                        )?),
+                        skip => skip,
+                    };
+
+                    built_nodes[submission.node()] = Some(GraphNode {
+                        node,
+                        name,
+                        queue,
+                        key,
+                        signature,
+                        condition,
+                        skip,
+                    });
                 }
             }
         }
 
+        let semaphore_queues = semaphore_queues(&schedule, semaphores.start);
         log::debug!("Create {} semaphores", semaphores.start);
-        let semaphores = (0..semaphores.start)
-            .map(|_| factory.create_semaphore())
+        let semaphores = semaphore_queues
+            .iter()
+            .map(|queues| match old.take_semaphore(queues) {
+                Some(semaphore) => Ok(semaphore),
+                None => factory.create_semaphore(),
+            })
             .collect::<Result<_, _>>()?;
 
+        if let Some(objects) = old.retire(frames.next().index()) {
+            retired.push(objects);
+        }
+
+        let mut nodes: Vec<_> = built_nodes.into_iter().map(Option::unwrap).collect();
+        let profiler = match timestamp_properties {
+            Some(properties) => Profiler::new(
+                factory,
+                &mut nodes,
+                inflight as usize,
+                properties,
+                pipeline_statistics,
+            )?,
+            None => None,
+        };
+        if profiler.is_none() {
+            // Reused nodes may hold queries of the retired profiler.
+            for node in &mut nodes {
+                node.node.set_statistics_query(None);
+            }
+        }
+
         Ok(Graph {
-            nodes: built_nodes
-                .into_iter()
-                .map(Option::unwrap)
-                .map(|(node, qid)| GraphNode {
-                    node,
-                    queue: command::QueueId(qid.family(), qid.index()),
-                })
-                .collect(),
+            nodes,
             schedule,
             semaphores,
-            buffers: buffers.into_iter().filter_map(|x| x).collect(),
-            images: images
+            semaphore_queues,
+            buffers,
+            images,
+            aliased_memory,
+            buffer_descs,
+            image_descs,
+            transient_images,
+            retired,
+            inflight,
+            frames,
+            fences,
+            stats: None,
+            profiler,
+        })
+    }
+}
+
+/// Parts of the old graph available for reuse during rebuild.
+#[derive(derivative::Derivative)]
+#[derivative(Default(bound = ""))]
+struct Old<B: Backend, T: ?Sized> {
+    nodes: Vec<Option<GraphNode<B, T>>>,
+    semaphores: Vec<
+        Option<(
+            (Option<chain::QueueId>, Option<chain::QueueId>),
+            B::Semaphore,
+        )>,
+    >,
+    buffers: Vec<Option<(buffer::Buffer<B>, BufferDesc)>>,
+    images: Vec<Option<(Box<image::Image<B>>, ImageDesc, bool)>>,
+    aliased_memory: Vec<MemoryBlock<B>>,
+    profiler: Option<Profiler<B>>,
+}
+
+impl<B, T> Old<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    fn take_buffer(
+        &mut self,
+        index: usize,
+        reusable: impl FnOnce(&buffer::Buffer<B>, &BufferDesc) -> bool,
+    ) -> Option<buffer::Buffer<B>> {
+        let slot = self.buffers.get_mut(index)?;
+        if slot
+            .as_ref()
+            .map_or(false, |(buffer, desc)| reusable(buffer, desc))
+        {
+            slot.take().map(|(buffer, _)| buffer)
+        } else {
+            None
+        }
+    }
+
+    /// Transient images are never reused as they may be aliased.
+    fn take_image(
+        &mut self,
+        index: usize,
+        reusable: impl FnOnce(&image::Image<B>, &ImageDesc) -> bool,
+    ) -> Option<Box<image::Image<B>>> {
+        let slot = self.images.get_mut(index)?;
+        if slot.as_ref().map_or(false, |(image, desc, transient)| {
+            !transient && reusable(image, desc)
+        }) {
+            slot.take().map(|(image, _, _)| image)
+        } else {
+            None
+        }
+    }
+
+    /// Take node with the same key, queue and signature.
+    /// Node is reusable only if all resources it was built with are reused.
+    fn take_node(
+        &mut self,
+        key: u64,
+        queue: command::QueueId,
+        signature: &NodeSignature,
+    ) -> Option<GraphNode<B, T>> {
+        let buffers = &self.buffers;
+        let images = &self.images;
+        let resources_reused = signature
+            .buffers
+            .iter()
+            .all(|(id, _, _)| buffers.get(id.0).map_or(true, Option::is_none))
+            && signature
+                .images
+                .iter()
+                .all(|(id, _, _, _, _)| images.get(id.0).map_or(true, Option::is_none));
+        if !resources_reused {
+            return None;
+        }
+
+        self.nodes
+            .iter_mut()
+            .find(|node| {
+                node.as_ref().map_or(false, |node| {
+                    node.key == Some(key) && node.queue == queue && node.signature == *signature
+                })
+            })
+            .and_then(Option::take)
+    }
+
+    fn take_semaphore(
+        &mut self,
+        queues: &(Option<chain::QueueId>, Option<chain::QueueId>),
+    ) -> Option<B::Semaphore> {
+        self.semaphores
+            .iter_mut()
+            .find(|semaphore| semaphore.as_ref().map_or(false, |(q, _)| q == queues))
+            .and_then(Option::take)
+            .map(|(_, semaphore)| semaphore)
+    }
+
+    /// Collect everything that wasn't reused.
+    fn retire(self, frame: u64) -> Option<Retired<B, T>> {
+        let retired = Retired {
+            frame,
+            nodes: self.nodes.into_iter().filter_map(|node| node).collect(),
+            semaphores: self
+                .semaphores
+                .into_iter()
+                .filter_map(|semaphore| semaphore.map(|(_, semaphore)| semaphore))
+                .collect(),
+            buffers: self
+                .buffers
                 .into_iter()
-                .filter_map(|x| x)
-                .map(|(image, _)| image)
+                .filter_map(|buffer| buffer.map(|(buffer, _)| buffer))
                 .collect(),
-            inflight: 3,
-            frames: Frames::new(),
-            fences: Vec::new(),
+            images: self
+                .images
+                .into_iter()
+                .filter_map(|image| image.map(|(image, _, _)| image))
+                .collect(),
+            aliased_memory: self.aliased_memory,
+            profiler: self.profiler,
+        };
+
+        if retired.nodes.is_empty()
+            && retired.semaphores.is_empty()
+            && retired.buffers.is_empty()
+            && retired.images.is_empty()
+            && retired.aliased_memory.is_empty()
+            && retired.profiler.is_none()
+        {
+            None
+        } else {
+            Some(retired)
+        }
+    }
+}
+
+/// Collect resources and synchronization node would be built with.
+fn node_signature<B, T>(
+    builder: &dyn NodeBuilder<B, T>,
+    chains: &chain::Chains,
+    submission: &chain::Submission<chain::SyncData<usize, usize>>,
+    images: &[Option<GraphImage<B>>],
+) -> NodeSignature
+where
+    B: Backend,
+    T: ?Sized,
+{
+    let mut buffer_ids: Vec<_> = builder.buffers().into_iter().map(|(id, _)| id).collect();
+    buffer_ids.sort();
+    buffer_ids.dedup();
+
+    let mut image_ids: Vec<_> = builder.images().into_iter().map(|(id, _)| id).collect();
+    image_ids.sort();
+    image_ids.dedup();
+
+    NodeSignature {
+        buffers: buffer_ids
+            .into_iter()
+            .map(|id| {
+                let (acquire, release) = buffer_barriers(submission, id);
+                (id, acquire, release)
+            })
+            .collect(),
+        images: image_ids
+            .into_iter()
+            .map(|id| {
+                let clear = if submission.image_link_index(chain::Id(id.0)) == 0 {
+                    images[id.0].as_ref().and_then(|&(_, clear)| clear)
+                } else {
+                    None
+                };
+                let (acquire, release) = image_barriers(submission, id);
+                (
+                    id,
+                    image_layout(chains, submission, id),
+                    clear,
+                    acquire,
+                    release,
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Get queues that signal and wait for each semaphore.
+fn semaphore_queues(
+    schedule: &chain::Schedule<chain::SyncData<usize, usize>>,
+    count: usize,
+) -> Vec<(Option<chain::QueueId>, Option<chain::QueueId>)> {
+    let mut queues = vec![(None, None); count];
+    for submission in schedule.ordered() {
+        let qid = submission.id().queue();
+        for signal in &submission.sync().signal {
+            queues[*signal.semaphore()].0 = Some(qid);
+        }
+        for wait in &submission.sync().wait {
+            queues[*wait.semaphore()].1 = Some(qid);
+        }
+    }
+    queues
+}
+
+/// Transient images sharing memory block.
+struct AliasGroup<B: Backend> {
+    queue: chain::QueueId,
+    memory: MemoryUsageValue,
+    mask: u32,
+    size: u64,
+    align: u64,
+    end: usize,
+    images: Vec<(usize, image::Info, B::Image)>,
+}
+
+/// Create transient images and bind them to shared memory blocks.
+/// Images are assigned to groups greedily in order of their first use.
+/// Image joins a group if it is used on the same queue after all images of the group
+/// and has compatible memory requirements.
+/// Images and memory created before an error are destroyed.
+fn alias_images<B>(
+    factory: &Factory<B>,
+    images: &[ImageDesc],
+    transient: &[ImageId],
+    chains: &chain::Chains,
+) -> Result<
+    (
+        chain::Aliasing,
+        Vec<Option<image::Image<B>>>,
+        Vec<MemoryBlock<B>>,
+    ),
+    failure::Error,
+>
+where
+    B: Backend,
+{
+    let mut groups = Vec::new();
+    let mut aliasing = chain::Aliasing::default();
+    let mut aliased = (0..images.len()).map(|_| None).collect::<Vec<_>>();
+    let mut blocks = Vec::new();
+
+    let result = group_aliased(factory, images, transient, chains, &mut groups).and_then(|()| {
+        bind_aliased(
+            factory,
+            &mut groups,
+            &mut aliasing,
+            &mut aliased,
+            &mut blocks,
+        )
+    });
+
+    if let Err(err) = result {
+        unsafe {
+            // Images were never used by device.
+            for group in groups {
+                for (_, _, raw) in group.images {
+                    factory.destroy_unbound_image(raw);
+                }
+            }
+            drop(aliased);
+            for block in blocks {
+                factory.free_memory(block);
+            }
+        }
+        return Err(err);
+    }
+
+    Ok((aliasing, aliased, blocks))
+}
+
+/// Create unbound transient images and assign them to groups.
+fn group_aliased<B>(
+    factory: &Factory<B>,
+    images: &[ImageDesc],
+    transient: &[ImageId],
+    chains: &chain::Chains,
+    groups: &mut Vec<AliasGroup<B>>,
+) -> Result<(), failure::Error>
+where
+    B: Backend,
+{
+    let mut candidates = transient
+        .iter()
+        .filter_map(|id| {
+            let chain = chains.images.get(&chain::Id(id.0))?;
+            let (queue, range) = chain::lifetime(chain)?;
+            Some((id.0, queue, range, chain.usage()))
         })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, _, range, _)| range.start);
+
+    for (index, queue, range, usage) in candidates {
+        let (mut info, memory, _) = images[index];
+        info.usage = usage;
+        let (raw, reqs) = factory.create_unbound_image(&info)?;
+        let mask = reqs.type_mask as u32;
+
+        let group = groups
+            .iter_mut()
+            .filter(|group| {
+                group.queue == queue
+                    && group.memory == memory
+                    && group.mask & mask != 0
+                    && group.end <= range.start
+            })
+            .min_by_key(|group| (group.size as i64 - reqs.size as i64).abs());
+
+        match group {
+            Some(group) => {
+                group.mask &= mask;
+                group.size = group.size.max(reqs.size);
+                group.align = group.align.max(reqs.alignment);
+                group.end = range.end;
+                group.images.push((index, info, raw));
+            }
+            None => groups.push(AliasGroup {
+                queue,
+                memory,
+                mask,
+                size: reqs.size,
+                align: reqs.alignment.max(UNIVERSAL_ALIGNMENT),
+                end: range.end,
+                images: vec![(index, info, raw)],
+            }),
+        }
     }
+
+    Ok(())
+}
+
+/// Allocate memory block for each group and bind images of the group to it.
+/// Images are removed from groups as they are bound.
+fn bind_aliased<B>(
+    factory: &Factory<B>,
+    groups: &mut [AliasGroup<B>],
+    aliasing: &mut chain::Aliasing,
+    aliased: &mut [Option<image::Image<B>>],
+    blocks: &mut Vec<MemoryBlock<B>>,
+) -> Result<(), failure::Error>
+where
+    B: Backend,
+{
+    for group in groups {
+        log::debug!(
+            "Alias {} images in {} bytes",
+            group.images.len(),
+            group.size
+        );
+        let block = factory.allocate_memory(group.mask, group.memory, group.size, group.align)?;
+        let offset = block.range().start;
+        blocks.push(block);
+        let block = blocks.last().unwrap();
+
+        let mut ids = Vec::with_capacity(group.images.len());
+        while !group.images.is_empty() {
+            let (index, info, raw) = group.images.remove(0);
+            let image = unsafe {
+                // Memory satisfies requirements of all images in the group
+                // and is freed after images are destroyed.
+                factory.bind_image(raw, info, block, offset)?
+            };
+            aliased[index] = Some(image);
+            ids.push(chain::Id(index));
+        }
+        aliasing.images.push(ids);
+    }
+
+    Ok(())
 }
 
 fn make_chain_node<B, T>(
@@ -412,3 +1454,69 @@ where
             .collect(),
     }
 }
+
+/// Collect acquire or release barriers from node signature.
+fn skip_barriers<'a, B>(
+    signature: &'a NodeSignature,
+    buffers: &'a [Option<buffer::Buffer<B>>],
+    images: &'a [Option<GraphImage<B>>],
+    release: bool,
+) -> (
+    std::ops::Range<gfx_hal::pso::PipelineStage>,
+    Vec<gfx_hal::memory::Barrier<'a, B>>,
+)
+where
+    B: Backend,
+{
+    let mut start = gfx_hal::pso::PipelineStage::empty();
+    let mut end = gfx_hal::pso::PipelineStage::empty();
+    let mut barriers = Vec::new();
+
+    for (id, acquire_barrier, release_barrier) in &signature.buffers {
+        let barrier = if release {
+            release_barrier
+        } else {
+            acquire_barrier
+        };
+        if let Some(barrier) = barrier {
+            let buffer = buffers[id.0]
+                .as_ref()
+                .expect("Buffer referenced from at least one node must be instantiated");
+            start |= barrier.stages.start;
+            end |= barrier.stages.end;
+            barriers.push(gfx_hal::memory::Barrier::Buffer {
+                states: barrier.states.clone(),
+                families: barrier.families.clone(),
+                target: buffer.raw(),
+                range: Some(0)..Some(buffer.size()),
+            });
+        }
+    }
+
+    for (id, _, _, acquire_barrier, release_barrier) in &signature.images {
+        let barrier = if release {
+            release_barrier
+        } else {
+            acquire_barrier
+        };
+        if let Some(barrier) = barrier {
+            let (image, _) = images[id.0]
+                .as_ref()
+                .expect("Image referenced from at least one node must be instantiated");
+            start |= barrier.stages.start;
+            end |= barrier.stages.end;
+            barriers.push(gfx_hal::memory::Barrier::Image {
+                states: barrier.states.clone(),
+                families: barrier.families.clone(),
+                target: image.raw(),
+                range: gfx_hal::image::SubresourceRange {
+                    aspects: image.format().surface_desc().aspects,
+                    levels: 0..image.levels(),
+                    layers: 0..image.layers(),
+                },
+            });
+        }
+    }
+
+    (start..end, barriers)
+}
@@ -0,0 +1,601 @@
+//! GPU profiling of graph nodes with timestamp and pipeline statistics queries.
+//!
+//! Timestamps are written by the graph around node submissions.
+//! Statistics queries must begin and end in the same command buffer,
+//! so they are collected only for nodes that record them with `StatisticsQuery`.
+
+use {
+    super::GraphNode,
+    crate::{
+        command::{
+            CommandBuffer, CommandPool, Encoder, ExecutableState, IndividualReset, MultiShot,
+            PendingState, SimultaneousUse, Submit,
+        },
+        factory::Factory,
+    },
+    gfx_hal::{query, Backend, Device},
+    std::sync::Arc,
+};
+
+/// Timestamp properties of the device required for profiling.
+/// `gfx-hal` doesn't report them, take them from the native API,
+/// e.g. `VkPhysicalDeviceLimits::timestampPeriod` and `VkQueueFamilyProperties::timestampValidBits`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampProperties {
+    /// Number of nanoseconds per timestamp tick.
+    pub period: f32,
+
+    /// Number of valid bits in timestamps written by queues of each family.
+    /// Indexed by family id.
+    /// Nodes on families with zero valid bits or missing from the list are not profiled.
+    pub valid_bits: Vec<u32>,
+}
+
+impl TimestampProperties {
+    /// Mask of valid timestamp bits of the family.
+    /// Returns `None` if family doesn't support timestamps.
+    fn mask(&self, family: usize) -> Option<u64> {
+        match self.valid_bits.get(family).cloned().unwrap_or(0) {
+            0 => None,
+            bits if bits >= 64 => Some(!0),
+            bits => Some((1 << bits) - 1),
+        }
+    }
+}
+
+/// GPU time spent by the node in profiled frame.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeProfile {
+    /// Index of the node in the graph.
+    pub node: usize,
+
+    /// Name of the node.
+    pub name: String,
+
+    /// Family and index of the queue the node was submitted to.
+    pub queue: (usize, usize),
+
+    /// Node was skipped by its condition and submitted only barriers.
+    pub skipped: bool,
+
+    /// Time between timestamps written before and after the node.
+    /// Includes time spent waiting for semaphores.
+    /// `None` if queue of the node doesn't support timestamps.
+    pub duration: Option<std::time::Duration>,
+
+    /// Pipeline statistics in the bit order of the enabled `PipelineStatistic` flags.
+    /// `None` if statistics are disabled, the node doesn't record them or it was skipped.
+    pub statistics: Option<Vec<u64>>,
+}
+
+/// GPU timings of the nodes in the frame.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileReport {
+    /// Index of the frame.
+    pub frame: u64,
+
+    /// Timings of all nodes in the order they were added to the graph.
+    pub nodes: Vec<NodeProfile>,
+}
+
+/// Pipeline statistics query of the node.
+/// Each frame in flight uses its own query.
+#[derive(derivative::Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""))]
+pub struct StatisticsQuery<B: Backend> {
+    pool: Arc<B::QueryPool>,
+    node: usize,
+    nodes: usize,
+    slots: usize,
+}
+
+impl<B> StatisticsQuery<B>
+where
+    B: Backend,
+{
+    /// Id of the query used in the frame.
+    pub fn id(&self, frame: u64) -> query::Id {
+        ((frame % self.slots as u64) as usize * self.nodes + self.node) as query::Id
+    }
+
+    /// Reset and begin the query of the frame.
+    /// Must be recorded outside of render pass.
+    pub fn begin<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>, frame: u64) {
+        let id = self.id(frame);
+        encoder.reset_query_pool(&self.pool, id..id + 1);
+        encoder.begin_query(
+            query::Query {
+                pool: &self.pool,
+                id,
+            },
+            query::ControlFlags::empty(),
+        );
+    }
+
+    /// End the query of the frame begun in the same command buffer.
+    /// Must be recorded outside of render pass.
+    pub fn end<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>, frame: u64) {
+        encoder.end_query(query::Query {
+            pool: &self.pool,
+            id: self.id(frame),
+        });
+    }
+}
+
+/// Statistics query of the node and queries recorded into its command buffers.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub(crate) struct RecordedStatistics<B: Backend> {
+    query: Option<StatisticsQuery<B>>,
+
+    /// Query recorded into command buffers by cirque index.
+    /// `None` for buffers recorded before the query was set.
+    recorded: Vec<Option<Option<query::Id>>>,
+}
+
+impl<B> RecordedStatistics<B>
+where
+    B: Backend,
+{
+    /// Replace the query.
+    /// Command buffers are recorded again before they are used.
+    pub(crate) fn set(&mut self, query: Option<StatisticsQuery<B>>) {
+        self.query = query;
+        self.recorded.clear();
+    }
+
+    /// Check if command buffer must be recorded again to use the query of the frame.
+    pub(crate) fn force_record(&self, index: usize, frame: u64) -> bool {
+        let id = self.query.as_ref().map(|query| query.id(frame));
+        self.recorded.get(index) != Some(&Some(id))
+    }
+
+    /// Begin the query of the frame in the command buffer.
+    pub(crate) fn begin<C, L>(
+        &mut self,
+        encoder: &mut Encoder<'_, B, C, L>,
+        index: usize,
+        frame: u64,
+    ) {
+        if self.recorded.len() <= index {
+            self.recorded.resize(index + 1, None);
+        }
+        self.recorded[index] = Some(self.query.as_ref().map(|query| query.id(frame)));
+        if let Some(query) = &self.query {
+            query.begin(encoder, frame);
+        }
+    }
+
+    /// End the query of the frame in the command buffer.
+    pub(crate) fn end<C, L>(&self, encoder: &mut Encoder<'_, B, C, L>, frame: u64) {
+        if let Some(query) = &self.query {
+            query.end(encoder, frame);
+        }
+    }
+}
+
+/// Pipeline statistics queries of the nodes.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
+struct Statistics<B: Backend> {
+    pool: Arc<B::QueryPool>,
+    flags: query::PipelineStatistic,
+
+    /// Nodes that record statistics queries.
+    nodes: Vec<bool>,
+}
+
+type TimestampBuffer<B> =
+    CommandBuffer<B, gfx_hal::QueueType, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>;
+
+/// Commands that write timestamps before and after the node.
+#[derive(Debug)]
+struct NodeTimestamps<B: Backend> {
+    pool: CommandPool<B, gfx_hal::QueueType, IndividualReset>,
+
+    /// Mask of valid timestamp bits of the queue.
+    mask: u64,
+
+    /// Begin and end submits for each slot.
+    submits: Vec<(Submit<B, SimultaneousUse>, Submit<B, SimultaneousUse>)>,
+    buffers: Vec<TimestampBuffer<B>>,
+}
+
+/// Profiler of the graph.
+/// Each frame in flight uses its own slot of two timestamp queries
+/// and one statistics query per node.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
+pub(super) struct Profiler<B: Backend> {
+    query_pool: B::QueryPool,
+    properties: TimestampProperties,
+    statistics: Option<Statistics<B>>,
+
+    /// Timestamps of nodes on queues that support them.
+    nodes: Vec<Option<NodeTimestamps<B>>>,
+    names: Vec<String>,
+    queues: Vec<(usize, usize)>,
+
+    /// Frame recorded into the slot and skipped nodes of that frame.
+    /// Slot is busy until results are read.
+    pending: Vec<Option<(u64, Vec<bool>)>>,
+    report: Option<ProfileReport>,
+}
+
+impl<B> Profiler<B>
+where
+    B: Backend,
+{
+    /// Create profiler for graph nodes and set their statistics queries.
+    /// Returns `None` if device doesn't support timestamp queries
+    /// or none of the nodes' queues write valid timestamps
+    /// and no pipeline statistics are requested.
+    pub(super) fn new<T: ?Sized>(
+        factory: &Factory<B>,
+        nodes: &mut [GraphNode<B, T>],
+        slots: usize,
+        properties: TimestampProperties,
+        statistics: query::PipelineStatistic,
+    ) -> Result<Option<Self>, failure::Error> {
+        if statistics.is_empty()
+            && nodes
+                .iter()
+                .all(|node| properties.mask(node.queue.family().0).is_none())
+        {
+            log::warn!("Profiling is disabled. Queues of the nodes don't support timestamps");
+            return Ok(None);
+        }
+
+        let count = nodes.len() * slots * 2;
+        let query_pool = match factory.create_query_pool(query::Type::Timestamp, count as query::Id)
+        {
+            Ok(query_pool) => query_pool,
+            Err(err) => {
+                log::warn!(
+                    "Profiling is disabled. Failed to create query pool: {:?}",
+                    err
+                );
+                return Ok(None);
+            }
+        };
+
+        let timestamps = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let mask = match properties.mask(node.queue.family().0) {
+                    Some(mask) => mask,
+                    None => {
+                        log::warn!(
+                            "Node '{}' is not profiled. Queue family {:?} doesn't support timestamps",
+                            node.name,
+                            node.queue.family()
+                        );
+                        return Ok(None);
+                    }
+                };
+
+                let mut pool = factory.create_command_pool(node.queue.family())?;
+                let mut buffers = Vec::new();
+                let submits = (0..slots)
+                    .map(|slot| {
+                        let first = ((slot * nodes.len() + index) * 2) as query::Id;
+
+                        let initial = pool.allocate_buffers(1).pop().unwrap();
+                        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+                        let mut encoder = recording.encoder();
+                        encoder.reset_query_pool(&query_pool, first..first + 2);
+                        encoder.write_timestamp(
+                            gfx_hal::pso::PipelineStage::TOP_OF_PIPE,
+                            query::Query {
+                                pool: &query_pool,
+                                id: first,
+                            },
+                        );
+                        let (begin, buffer) = recording.finish().submit();
+                        buffers.push(buffer);
+
+                        let initial = pool.allocate_buffers(1).pop().unwrap();
+                        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+                        recording.encoder().write_timestamp(
+                            gfx_hal::pso::PipelineStage::BOTTOM_OF_PIPE,
+                            query::Query {
+                                pool: &query_pool,
+                                id: first + 1,
+                            },
+                        );
+                        let (end, buffer) = recording.finish().submit();
+                        buffers.push(buffer);
+
+                        (begin, end)
+                    })
+                    .collect();
+
+                Ok(Some(NodeTimestamps {
+                    pool,
+                    mask,
+                    submits,
+                    buffers,
+                }))
+            })
+            .collect::<Result<_, failure::Error>>()?;
+
+        let statistics = if statistics.is_empty() {
+            None
+        } else {
+            let count = nodes.len() * slots;
+            match factory.create_query_pool(
+                query::Type::PipelineStatistics(statistics),
+                count as query::Id,
+            ) {
+                Ok(pool) => Some((Arc::new(pool), statistics)),
+                Err(err) => {
+                    log::warn!(
+                        "Pipeline statistics are disabled. Failed to create query pool: {:?}",
+                        err
+                    );
+                    None
+                }
+            }
+        };
+
+        let node_count = nodes.len();
+        let statistics = statistics.map(|(pool, flags)| {
+            let nodes = nodes
+                .iter_mut()
+                .enumerate()
+                .map(|(index, node)| {
+                    let supported = node.node.set_statistics_query(Some(StatisticsQuery {
+                        pool: pool.clone(),
+                        node: index,
+                        nodes: node_count,
+                        slots,
+                    }));
+                    if !supported {
+                        log::debug!("Node '{}' doesn't record pipeline statistics", node.name);
+                    }
+                    supported
+                })
+                .collect();
+            Statistics { pool, flags, nodes }
+        });
+        if statistics.is_none() {
+            for node in nodes.iter_mut() {
+                node.node.set_statistics_query(None);
+            }
+        }
+
+        Ok(Some(Profiler {
+            query_pool,
+            properties,
+            statistics,
+            nodes: timestamps,
+            names: nodes.iter().map(|node| node.name.clone()).collect(),
+            queues: nodes
+                .iter()
+                .map(|node| (node.queue.family().0, node.queue.index()))
+                .collect(),
+            pending: (0..slots).map(|_| None).collect(),
+            report: None,
+        }))
+    }
+
+    /// Timestamp properties profiler was created with.
+    pub(super) fn properties(&self) -> &TimestampProperties {
+        &self.properties
+    }
+
+    /// Pipeline statistics profiler collects.
+    pub(super) fn statistics(&self) -> query::PipelineStatistic {
+        self.statistics
+            .as_ref()
+            .map_or(query::PipelineStatistic::empty(), |statistics| {
+                statistics.flags
+            })
+    }
+
+    /// Latest report.
+    pub(super) fn report(&self) -> Option<&ProfileReport> {
+        self.report.as_ref()
+    }
+
+    /// Reserve slot for the frame.
+    /// Returns `None` if results of the previous frame in the slot are not read yet,
+    /// in which case the frame is not profiled.
+    pub(super) fn begin_frame(&mut self, frame: u64, enabled: &[bool]) -> Option<usize> {
+        let slot = (frame % self.pending.len() as u64) as usize;
+        if self.pending[slot].is_some() {
+            log::debug!("Frame {} is not profiled. Slot {} is busy", frame, slot);
+            return None;
+        }
+        self.pending[slot] = Some((frame, enabled.iter().map(|&enabled| !enabled).collect()));
+        Some(slot)
+    }
+
+    /// Get commands that write timestamps before and after the node.
+    /// Returns `None` if the node is not profiled.
+    pub(super) fn submits(
+        &self,
+        slot: usize,
+        node: usize,
+    ) -> Option<(&Submit<B, SimultaneousUse>, &Submit<B, SimultaneousUse>)> {
+        let (ref begin, ref end) = self.nodes[node].as_ref()?.submits[slot];
+        Some((begin, end))
+    }
+
+    /// Read results of complete frames.
+    pub(super) fn collect(&mut self, factory: &Factory<B>, complete: u64) {
+        for slot in 0..self.pending.len() {
+            match self.pending[slot] {
+                Some((frame, _)) if frame < complete => {}
+                _ => continue,
+            }
+            let (frame, skipped) = self.pending[slot].take().unwrap();
+
+            let ticks = match self.read_ticks(factory, slot) {
+                Ok(Some(ticks)) => ticks,
+                Ok(None) => {
+                    log::warn!("Profiling results of frame {} are not available", frame);
+                    continue;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Failed to read profiling results of frame {}: {}",
+                        frame,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            if self
+                .report
+                .as_ref()
+                .map_or(false, |report| report.frame > frame)
+            {
+                continue;
+            }
+
+            let statistics = self.read_statistics(factory, slot, &skipped);
+
+            let timestamp_period = self.properties.period as f64;
+            self.report = Some(ProfileReport {
+                frame,
+                nodes: ticks
+                    .into_iter()
+                    .zip(statistics)
+                    .zip(skipped)
+                    .enumerate()
+                    .map(|(node, ((ticks, statistics), skipped))| NodeProfile {
+                        node,
+                        name: self.names[node].clone(),
+                        queue: self.queues[node],
+                        skipped,
+                        duration: ticks.map(|ticks| {
+                            std::time::Duration::from_nanos(
+                                (ticks as f64 * timestamp_period) as u64,
+                            )
+                        }),
+                        statistics,
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    /// Read ticks between timestamps of each profiled node in the slot.
+    /// Returns `None` if results are not available.
+    fn read_ticks(
+        &self,
+        factory: &Factory<B>,
+        slot: usize,
+    ) -> Result<Option<Vec<Option<u64>>>, failure::Error> {
+        let mut ticks = Vec::with_capacity(self.nodes.len());
+        for (node, timestamps) in self.nodes.iter().enumerate() {
+            let timestamps = match timestamps {
+                Some(timestamps) => timestamps,
+                None => {
+                    ticks.push(None);
+                    continue;
+                }
+            };
+
+            let first = ((slot * self.nodes.len() + node) * 2) as query::Id;
+            let mut data = [0u64; 2];
+            let available = unsafe {
+                // Frame is complete. Queries are written and not used by the device.
+                factory.device().get_query_pool_results(
+                    &self.query_pool,
+                    first..first + 2,
+                    std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, 16),
+                    8,
+                    query::ResultFlags::BITS_64,
+                )?
+            };
+            if !available {
+                return Ok(None);
+            }
+
+            // Timestamps wrap at valid bits.
+            ticks.push(Some(data[1].wrapping_sub(data[0]) & timestamps.mask));
+        }
+        Ok(Some(ticks))
+    }
+
+    /// Read pipeline statistics of each node that recorded them in the slot.
+    /// Statistics that are not available are `None`.
+    fn read_statistics(
+        &self,
+        factory: &Factory<B>,
+        slot: usize,
+        skipped: &[bool],
+    ) -> Vec<Option<Vec<u64>>> {
+        let statistics = match &self.statistics {
+            Some(statistics) => statistics,
+            None => return vec![None; self.nodes.len()],
+        };
+        let count = statistics.flags.bits().count_ones() as usize;
+
+        (0..self.nodes.len())
+            .map(|node| {
+                if !statistics.nodes[node] || skipped[node] {
+                    return None;
+                }
+
+                let id = (slot * self.nodes.len() + node) as query::Id;
+                let mut data = vec![0u64; count];
+                let result = unsafe {
+                    // Frame is complete. Query is written and not used by the device.
+                    factory.device().get_query_pool_results(
+                        &statistics.pool,
+                        id..id + 1,
+                        std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, count * 8),
+                        (count * 8) as _,
+                        query::ResultFlags::BITS_64,
+                    )
+                };
+                match result {
+                    Ok(true) => Some(data),
+                    Ok(false) => None,
+                    Err(err) => {
+                        log::warn!(
+                            "Failed to read pipeline statistics of node '{}': {}",
+                            self.names[node],
+                            err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Dispose of the profiler.
+    ///
+    /// # Safety
+    ///
+    /// All frames profiled must be complete.
+    /// Nodes must not hold statistics queries of the profiler.
+    pub(super) unsafe fn dispose(self, factory: &Factory<B>) {
+        for timestamps in self.nodes.into_iter().flatten() {
+            drop(timestamps.submits);
+            let mut pool = timestamps.pool;
+            pool.free_buffers(
+                timestamps
+                    .buffers
+                    .into_iter()
+                    .map(|buffer| buffer.mark_complete()),
+            );
+            factory.destroy_command_pool(pool);
+        }
+        factory.destroy_query_pool(self.query_pool);
+        if let Some(statistics) = self.statistics {
+            match Arc::try_unwrap(statistics.pool) {
+                Ok(pool) => factory.destroy_query_pool(pool),
+                Err(_) => log::error!("Statistics query pool is still used by nodes and leaked"),
+            }
+        }
+    }
+}
@@ -16,6 +16,8 @@ use rendy_factory as factory;
 use rendy_frame as frame;
 use rendy_memory as memory;
 use rendy_resource as resource;
+use rendy_shader as shader;
+use rendy_texture as texture;
 use rendy_wsi as wsi;
 
 /// Id of the buffer in graph.
@@ -0,0 +1,407 @@
+//!
+//! Compute node that runs single compute pipeline.
+//!
+
+use {
+    crate::{
+        command::{
+            CommandBuffer, CommandPool, Compute, Encoder, ExecutableState, FamilyId,
+            IndividualReset, MultiShot, NoSimultaneousUse, PendingState, PrimaryLevel,
+            SecondaryLevel, SimultaneousUse, Submit,
+        },
+        factory::Factory,
+        frame::{
+            cirque::{CirqueRef, CommandCirque},
+            Frames,
+        },
+        graph::{RecordedStatistics, StatisticsQuery},
+        node::{
+            gfx_acquire_barriers, gfx_release_barriers,
+            render::{Layout, PrepareResult},
+            BufferAccess, DescBuilder, ImageAccess, Node, NodeBuffer, NodeDesc, NodeImage,
+            NodeSubmittable,
+        },
+    },
+    gfx_hal::{Backend, Device},
+    std::marker::PhantomData,
+};
+
+/// Builder for compute node that runs `SimpleComputePipeline`.
+pub type ComputeNodeBuilder<B, T, P> = DescBuilder<B, T, ComputeNodeDesc<P>>;
+
+/// Simple compute pipeline.
+/// Node created from it handles pipeline creation, command pools,
+/// barriers for buffers and images and per-frame command buffers.
+pub trait SimpleComputePipeline<B: Backend, T: ?Sized>:
+    std::fmt::Debug + Send + Sync + 'static
+{
+    /// Compute pipeline name.
+    fn name() -> &'static str
+    where
+        Self: Sized;
+
+    /// Make compute node builder.
+    fn builder() -> ComputeNodeBuilder<B, T, Self>
+    where
+        Self: Sized,
+    {
+        NodeDesc::<B, T>::builder(ComputeNodeDesc(PhantomData))
+    }
+
+    /// Get set or buffer resources the node uses.
+    /// Buffers with indirect dispatch commands must be listed with
+    /// `INDIRECT_COMMAND_READ` access at `DRAW_INDIRECT` stage.
+    fn buffers() -> Vec<BufferAccess>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// Get set or image resources the node uses.
+    fn images() -> Vec<ImageAccess>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// Layout for compute pipeline.
+    fn layout() -> Layout
+    where
+        Self: Sized,
+    {
+        Layout {
+            sets: Vec::new(),
+            push_constants: Vec::new(),
+        }
+    }
+
+    /// Load compute shader module.
+    fn load_shader(
+        factory: &mut Factory<B>,
+        aux: &mut T,
+    ) -> Result<B::ShaderModule, failure::Error>
+    where
+        Self: Sized;
+
+    /// Shader entry point.
+    fn entry() -> &'static str
+    where
+        Self: Sized,
+    {
+        "main"
+    }
+
+    /// Build pipeline instance.
+    fn build<'a>(
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        buffers: Vec<NodeBuffer<'a, B>>,
+        images: Vec<NodeImage<'a, B>>,
+        set_layouts: &[B::DescriptorSetLayout],
+    ) -> Result<Self, failure::Error>
+    where
+        Self: Sized;
+
+    /// Prepare to record dispatch commands.
+    ///
+    /// Should return `DrawRecord` if commands must be re-recorded.
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _set_layouts: &[B::DescriptorSetLayout],
+        _index: usize,
+        _aux: &T,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    /// Record dispatch commands to the command buffer provided.
+    /// Pipeline is already bound.
+    /// Either `dispatch` or `dispatch_indirect` can be used.
+    fn dispatch(
+        &mut self,
+        layout: &B::PipelineLayout,
+        encoder: Encoder<'_, B, Compute, PrimaryLevel>,
+        index: usize,
+        aux: &T,
+    );
+
+    /// Dispose of the pipeline.
+    fn dispose(self, factory: &mut Factory<B>, aux: &mut T);
+}
+
+/// Description of the compute node that runs `SimpleComputePipeline`.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub struct ComputeNodeDesc<P>(PhantomData<P>);
+
+#[derive(Debug)]
+struct BarriersCommands<B: Backend> {
+    submit: Submit<B, SimultaneousUse, SecondaryLevel>,
+    buffer: CommandBuffer<
+        B,
+        Compute,
+        PendingState<ExecutableState<MultiShot<SimultaneousUse>>>,
+        SecondaryLevel,
+        IndividualReset,
+    >,
+}
+
+/// Compute node that runs `SimpleComputePipeline`.
+#[derive(Debug)]
+pub struct ComputeNode<B: Backend, P> {
+    set_layouts: Vec<B::DescriptorSetLayout>,
+    pipeline_layout: B::PipelineLayout,
+    compute_pipeline: B::ComputePipeline,
+    pipeline: P,
+
+    command_pool: CommandPool<B, Compute, IndividualReset>,
+    command_cirque: CommandCirque<B, Compute>,
+
+    acquire: Option<BarriersCommands<B>>,
+    release: Option<BarriersCommands<B>>,
+    statistics: RecordedStatistics<B>,
+}
+
+impl<'a, B, P> NodeSubmittable<'a, B> for ComputeNode<B, P>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, P> ComputeNode<B, P>
+where
+    B: Backend,
+{
+    fn encode<T>(
+        &mut self,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &Frames<B>,
+    ) -> Submit<B, NoSimultaneousUse>
+    where
+        T: ?Sized,
+        P: SimpleComputePipeline<B, T>,
+    {
+        let ComputeNode {
+            set_layouts,
+            pipeline_layout,
+            compute_pipeline,
+            pipeline,
+            command_pool,
+            command_cirque,
+            acquire,
+            release,
+            statistics,
+        } = self;
+
+        let frame = frames.next().index();
+        command_cirque.encode(frames.range(), command_pool, |mut cbuf| {
+            let index = cbuf.index();
+
+            let force_record = pipeline
+                .prepare(factory, set_layouts, index, aux)
+                .force_record();
+            if force_record || statistics.force_record(index, frame) {
+                cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
+            }
+
+            cbuf.or_init(|cbuf| {
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                if let Some(barriers) = &acquire {
+                    encoder.execute_commands(std::iter::once(&barriers.submit));
+                }
+
+                statistics.begin(&mut encoder, index, frame);
+                encoder.bind_compute_pipeline(compute_pipeline);
+                pipeline.dispatch(pipeline_layout, encoder.reborrow(), index, aux);
+                statistics.end(&mut encoder, frame);
+
+                if let Some(barriers) = &release {
+                    encoder.execute_commands(std::iter::once(&barriers.submit));
+                }
+                cbuf.finish()
+            })
+        })
+    }
+}
+
+impl<B, T, P> Node<B, T> for ComputeNode<B, P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleComputePipeline<B, T>,
+{
+    type Capability = Compute;
+    type Desc = ComputeNodeDesc<P>;
+
+    fn run<'a>(
+        &'a mut self,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        Some(self.encode(factory, aux, frames))
+    }
+
+    fn record<'a>(
+        &'a mut self,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Option<Submit<B, NoSimultaneousUse>>> {
+        Some(Some(self.encode(factory, aux, frames)))
+    }
+
+    fn set_statistics_query(&mut self, query: Option<StatisticsQuery<B>>) -> bool {
+        self.statistics.set(query);
+        true
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, aux: &mut T) {
+        self.pipeline.dispose(factory, aux);
+
+        let pool = &mut self.command_pool;
+        self.command_cirque.dispose(|buffer| {
+            buffer.either_with(
+                &mut *pool,
+                |pool, executable| pool.free_buffers(Some(executable)),
+                |pool, pending| {
+                    let executable = pending.mark_complete();
+                    pool.free_buffers(Some(executable))
+                },
+            );
+        });
+        for barriers in self.acquire.into_iter().chain(self.release) {
+            drop(barriers.submit);
+            self.command_pool
+                .free_buffers(Some(barriers.buffer.mark_complete()));
+        }
+        factory.destroy_command_pool(self.command_pool.with_queue_type());
+
+        factory
+            .device()
+            .destroy_compute_pipeline(self.compute_pipeline);
+        factory
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout);
+        for set_layout in self.set_layouts.into_iter() {
+            factory.device().destroy_descriptor_set_layout(set_layout);
+        }
+    }
+}
+
+impl<B, T, P> NodeDesc<B, T> for ComputeNodeDesc<P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleComputePipeline<B, T>,
+{
+    type Node = ComputeNode<B, P>;
+
+    fn name(&self) -> String {
+        P::name().to_owned()
+    }
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        P::buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        P::images()
+    }
+
+    fn build<'a>(
+        &self,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        family: FamilyId,
+        buffers: Vec<NodeBuffer<'a, B>>,
+        images: Vec<NodeImage<'a, B>>,
+    ) -> Result<ComputeNode<B, P>, failure::Error> {
+        log::trace!("Load shader module for '{}'", P::name());
+        let module = P::load_shader(factory, aux)?;
+
+        let layout = P::layout();
+
+        let set_layouts = layout
+            .sets
+            .into_iter()
+            .map(|set| unsafe {
+                factory
+                    .device()
+                    .create_descriptor_set_layout(set.bindings, std::iter::empty::<B::Sampler>())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(&set_layouts, layout.push_constants)
+        }?;
+
+        let compute_pipeline = unsafe {
+            let result = factory.device().create_compute_pipeline(
+                &gfx_hal::pso::ComputePipelineDesc {
+                    shader: gfx_hal::pso::EntryPoint {
+                        entry: P::entry(),
+                        module: &module,
+                        specialization: gfx_hal::pso::Specialization::default(),
+                    },
+                    layout: &pipeline_layout,
+                    flags: gfx_hal::pso::PipelineCreationFlags::empty(),
+                    parent: gfx_hal::pso::BasePipeline::None,
+                },
+                None,
+            );
+            factory.device().destroy_shader_module(module);
+            result
+        }?;
+
+        let mut command_pool = factory
+            .create_command_pool(family)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Compute`");
+
+        let mut record_barriers = |stages, barriers: Vec<_>| {
+            if barriers.is_empty() {
+                return None;
+            }
+            let initial = command_pool.allocate_buffers(1).pop().unwrap();
+            let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+            log::info!("Barriers {:?} : {:#?}", stages, barriers);
+            recording.encoder().pipeline_barrier(
+                stages,
+                gfx_hal::memory::Dependencies::empty(),
+                barriers,
+            );
+            let (submit, buffer) = recording.finish().submit();
+            Some(BarriersCommands { submit, buffer })
+        };
+
+        let (stages, barriers) = gfx_acquire_barriers(&buffers, &images);
+        let acquire = record_barriers(stages, barriers);
+        let (stages, barriers) = gfx_release_barriers(&buffers, &images);
+        let release = record_barriers(stages, barriers);
+
+        let pipeline = P::build(factory, aux, buffers, images, &set_layouts)?;
+
+        Ok(ComputeNode {
+            set_layouts,
+            pipeline_layout,
+            compute_pipeline,
+            pipeline,
+            command_pool,
+            command_cirque: CommandCirque::new(),
+            acquire,
+            release,
+            statistics: RecordedStatistics::default(),
+        })
+    }
+}
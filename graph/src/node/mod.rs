@@ -1,7 +1,9 @@
 //! Defines node - building block for framegraph.
 //!
 
+pub mod compute;
 pub mod present;
+pub mod readback;
 pub mod render;
 
 use crate::{
@@ -9,6 +11,7 @@ use crate::{
     command::{Capability, Family, FamilyId, Fence, QueueId, Submission, Submittable, Supports},
     factory::Factory,
     frame::Frames,
+    graph::StatisticsQuery,
     resource::{Buffer, Image},
     BufferId, ImageId, NodeId,
 };
@@ -30,7 +33,7 @@ pub struct BufferAccess {
 }
 
 /// Buffer pipeline barrier.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BufferBarrier {
     /// State transition for the buffer.
     pub states: std::ops::Range<gfx_hal::buffer::State>,
@@ -106,7 +109,7 @@ pub struct ImageAccess {
 /// Image pipeline barrier.
 /// Node implementation must insert it before first command that uses the image.
 /// Barrier must be inserted even if this node doesn't use the image.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ImageBarrier {
     /// State transition for the image.
     pub states: std::ops::Range<gfx_hal::image::State>,
@@ -125,6 +128,7 @@ pub struct NodeImage<'a, B: gfx_hal::Backend> {
     pub id: ImageId,
 
     /// Image reference.
+    /// Image stays at the same address until graph is disposed.
     pub image: &'a mut Image<B>,
 
     /// Region of the image that is the transient resource.
@@ -164,6 +168,63 @@ where
     }
 }
 
+/// Command buffer recorded by the node.
+/// Type-erased `Submittable` that can be sent to the thread that submits it.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct NodeSubmit<B: gfx_hal::Backend> {
+    family: FamilyId,
+    raw: std::ptr::NonNull<B::CommandBuffer>,
+}
+
+unsafe impl<B> Send for NodeSubmit<B>
+where
+    B: gfx_hal::Backend,
+    B::CommandBuffer: Send + Sync,
+{
+}
+
+unsafe impl<B> Sync for NodeSubmit<B>
+where
+    B: gfx_hal::Backend,
+    B::CommandBuffer: Send + Sync,
+{
+}
+
+impl<B> NodeSubmit<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Erase type of the submittable.
+    ///
+    /// # Safety
+    ///
+    /// Command buffer must stay alive until submission is complete,
+    /// same as required by `Submittable::raw`.
+    pub unsafe fn new(submittable: impl Submittable<B>) -> Self {
+        NodeSubmit {
+            family: submittable.family(),
+            raw: std::ptr::NonNull::from(submittable.raw()),
+        }
+    }
+}
+
+unsafe impl<B> Submittable<B> for NodeSubmit<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn family(&self) -> FamilyId {
+        self.family
+    }
+
+    unsafe fn raw<'a>(self) -> &'a B::CommandBuffer {
+        &*self.raw.as_ptr()
+    }
+}
+
+/// Command buffers recorded by the node.
+pub type NodeSubmits<B> = smallvec::SmallVec<[NodeSubmit<B>; 4]>;
+
 /// NodeSubmittable
 pub trait NodeSubmittable<'a, B: gfx_hal::Backend> {
     /// Submittable type returned from `Node`.
@@ -218,6 +279,29 @@ pub trait Node<B: gfx_hal::Backend, T: ?Sized>:
         frames: &'a Frames<B>,
     ) -> <Self as NodeSubmittable<'a, B>>::Submittables;
 
+    /// Record commands with shared access to factory and aux.
+    /// Graph calls this method from worker threads when it records in parallel.
+    /// Returned submits are guaranteed to be submitted within specified frame.
+    ///
+    /// Nodes that need exclusive access return `None` without recording anything.
+    /// `run` is called on the graph thread for them.
+    fn record<'a>(
+        &'a mut self,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &'a Frames<B>,
+    ) -> Option<<Self as NodeSubmittable<'a, B>>::Submittables> {
+        None
+    }
+
+    /// Set pipeline statistics query the node records into its command buffers.
+    /// Returns `false` if the node doesn't record statistics queries.
+    ///
+    /// Command buffers recorded with the previous query must not be submitted again.
+    fn set_statistics_query(&mut self, _query: Option<StatisticsQuery<B>>) -> bool {
+        false
+    }
+
     /// Dispose of the node.
     ///
     /// # Safety
@@ -244,6 +328,12 @@ pub trait NodeDesc<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug + Sized + 's
         }
     }
 
+    /// Name of the node.
+    /// Used in logs and profiling reports.
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
     /// Get set or buffer resources the node uses.
     fn buffers(&self) -> Vec<BufferAccess> {
         Vec::new()
@@ -289,6 +379,26 @@ pub trait DynNode<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug + Sync + Send
         fence: Option<&mut Fence<B>>,
     );
 
+    /// Record commands with shared access to factory and aux.
+    /// Returns `None` if node must be run with `run` instead.
+    /// Recorded buffers must be submitted to node's queue within specified frame.
+    unsafe fn record(
+        &mut self,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &Frames<B>,
+    ) -> Option<NodeSubmits<B>> {
+        None
+    }
+
+    /// Set pipeline statistics query the node records into its command buffers.
+    /// Returns `false` if the node doesn't record statistics queries.
+    ///
+    /// Command buffers recorded with the previous query must not be submitted again.
+    fn set_statistics_query(&mut self, _query: Option<StatisticsQuery<B>>) -> bool {
+        false
+    }
+
     /// Dispose of the node.
     ///
     /// # Safety
@@ -325,6 +435,25 @@ where
         )
     }
 
+    unsafe fn record(
+        &mut self,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &Frames<B>,
+    ) -> Option<NodeSubmits<B>> {
+        let submittables = Node::record(&mut self.0, factory, aux, frames)?;
+        Some(
+            submittables
+                .into_iter()
+                .map(|submittable| NodeSubmit::new(submittable))
+                .collect(),
+        )
+    }
+
+    fn set_statistics_query(&mut self, query: Option<StatisticsQuery<B>>) -> bool {
+        Node::set_statistics_query(&mut self.0, query)
+    }
+
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &mut T) {
         N::dispose(self.0, factory, aux);
     }
@@ -332,6 +461,12 @@ where
 
 /// Dynamic ode builder that emits `DynNode`.
 pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
+    /// Name of the node.
+    /// Used in logs and profiling reports.
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
     fn family(&self, families: &[Family<B>]) -> Option<FamilyId>;
 
     /// Get buffer accessed by the node.
@@ -360,7 +495,7 @@ pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
         aux: &mut T,
         family: FamilyId,
         buffers: &'a mut [Option<Buffer<B>>],
-        images: &'a mut [Option<(Image<B>, Option<gfx_hal::command::ClearValue>)>],
+        images: &'a mut [Option<(Box<Image<B>>, Option<gfx_hal::command::ClearValue>)>],
         chains: &chain::Chains,
         submission: &chain::Submission<chain::SyncData<usize, usize>>,
     ) -> Result<Box<dyn DynNode<B, T>>, failure::Error> {
@@ -371,29 +506,16 @@ pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
         let buffers: Vec<_> = buffer_ids
             .into_iter()
             .map(|id| {
-                let chain_id = chain::Id(id.0);
-                let sync = submission.sync();
                 let buffer = buffers
                     .get_mut(id.0)
                     .and_then(Option::as_mut)
                     .expect("Buffer referenced from at least one node must be instantiated");
+                let (acquire, release) = buffer_barriers(submission, id);
                 NodeBuffer {
                     id,
                     range: 0..buffer.size(),
-                    acquire: sync.acquire.buffers.get(&chain_id).map(
-                        |chain::Barrier { states, families }| BufferBarrier {
-                            states: states.start.0..states.end.0,
-                            stages: states.start.2..states.end.2,
-                            families: families.clone(),
-                        },
-                    ),
-                    release: sync.release.buffers.get(&chain_id).map(
-                        |chain::Barrier { states, families }| BufferBarrier {
-                            states: states.start.0..states.end.0,
-                            stages: states.start.2..states.end.2,
-                            families: families.clone(),
-                        },
-                    ),
+                    acquire,
+                    release,
                     buffer: unsafe {
                         // ids are unique.
                         // Hence mutable references to different buffers will be acquired.
@@ -410,13 +532,12 @@ pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
         let images: Vec<_> = image_ids
             .into_iter()
             .map(|id| {
-                let chain_id = chain::Id(id.0);
-                let sync = submission.sync();
-                let link = submission.image_link_index(chain_id);
+                let link = submission.image_link_index(chain::Id(id.0));
                 let (image, clear) = images
                     .get_mut(id.0)
                     .and_then(Option::as_mut)
                     .expect("Image referenced from at least one node must be instantiated");
+                let (acquire, release) = image_barriers(submission, id);
                 NodeImage {
                     id,
                     range: gfx_hal::image::SubresourceRange {
@@ -424,28 +545,14 @@ pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
                         levels: 0..image.levels(),
                         layers: 0..image.layers(),
                     },
-                    layout: chains.images[&chain_id].links()[link]
-                        .submission_state(submission.id())
-                        .layout,
+                    layout: image_layout(chains, submission, id),
                     clear: if link == 0 { *clear } else { None },
-                    acquire: sync.acquire.images.get(&chain_id).map(
-                        |chain::Barrier { states, families }| ImageBarrier {
-                            states: (states.start.0, states.start.1)..(states.end.0, states.end.1),
-                            stages: states.start.2..states.end.2,
-                            families: families.clone(),
-                        },
-                    ),
-                    release: sync.release.images.get(&chain_id).map(
-                        |chain::Barrier { states, families }| ImageBarrier {
-                            states: (states.start.0, states.start.1)..(states.end.0, states.end.1),
-                            stages: states.start.2..states.end.2,
-                            families: families.clone(),
-                        },
-                    ),
+                    acquire,
+                    release,
                     image: unsafe {
                         // ids are unique.
                         // Hence mutable references to different images will be acquired.
-                        std::mem::transmute::<_, &'a mut Image<B>>(image)
+                        std::mem::transmute::<_, &'a mut Image<B>>(&mut **image)
                     },
                 }
             })
@@ -454,6 +561,55 @@ pub trait NodeBuilder<B: gfx_hal::Backend, T: ?Sized>: std::fmt::Debug {
     }
 }
 
+/// Get barriers for the buffer from submission synchronization.
+pub(crate) fn buffer_barriers(
+    submission: &chain::Submission<chain::SyncData<usize, usize>>,
+    id: BufferId,
+) -> (Option<BufferBarrier>, Option<BufferBarrier>) {
+    let chain_id = chain::Id(id.0);
+    let sync = submission.sync();
+    let barrier = |chain::Barrier { states, families }: &chain::Barrier<_>| BufferBarrier {
+        states: states.start.0..states.end.0,
+        stages: states.start.2..states.end.2,
+        families: families.clone(),
+    };
+    (
+        sync.acquire.buffers.get(&chain_id).map(barrier),
+        sync.release.buffers.get(&chain_id).map(barrier),
+    )
+}
+
+/// Get barriers for the image from submission synchronization.
+pub(crate) fn image_barriers(
+    submission: &chain::Submission<chain::SyncData<usize, usize>>,
+    id: ImageId,
+) -> (Option<ImageBarrier>, Option<ImageBarrier>) {
+    let chain_id = chain::Id(id.0);
+    let sync = submission.sync();
+    let barrier = |chain::Barrier { states, families }: &chain::Barrier<_>| ImageBarrier {
+        states: (states.start.0, states.start.1)..(states.end.0, states.end.1),
+        stages: states.start.2..states.end.2,
+        families: families.clone(),
+    };
+    (
+        sync.acquire.images.get(&chain_id).map(barrier),
+        sync.release.images.get(&chain_id).map(barrier),
+    )
+}
+
+/// Get layout of the image for the submission.
+pub(crate) fn image_layout(
+    chains: &chain::Chains,
+    submission: &chain::Submission<chain::SyncData<usize, usize>>,
+    id: ImageId,
+) -> gfx_hal::image::Layout {
+    let chain_id = chain::Id(id.0);
+    let link = submission.image_link_index(chain_id);
+    chains.images[&chain_id].links()[link]
+        .submission_state(submission.id())
+        .layout
+}
+
 /// Builder for the node.
 #[derive(derivative::Derivative)]
 #[derivative(Debug(bound = "N: std::fmt::Debug"))]
@@ -519,6 +675,10 @@ where
     T: ?Sized,
     N: NodeDesc<B, T>,
 {
+    fn name(&self) -> String {
+        self.desc.name()
+    }
+
     fn family(&self, families: &[Family<B>]) -> Option<FamilyId> {
         families
             .iter()
@@ -7,11 +7,9 @@ use crate::{
     },
     factory::Factory,
     frame::Frames,
-    node::{
-        gfx_acquire_barriers, gfx_release_barriers, BufferAccess, DynNode, ImageAccess, NodeBuffer,
-        NodeBuilder, NodeImage,
-    },
-    wsi::{Backbuffer, Surface, Target},
+    node::{BufferAccess, DynNode, ImageAccess, ImageBarrier, NodeBuffer, NodeBuilder, NodeImage},
+    resource::Image,
+    wsi::{Backbuffer, OutOfDate, Surface, Target},
     BufferId, ImageId, NodeId,
 };
 
@@ -27,13 +25,128 @@ struct ForImage<B: gfx_hal::Backend> {
     >,
 }
 
-/// Node that presents images to the surface.
+/// State of the input image required to record copy commands.
 #[derive(Debug)]
+struct InputImage<B: gfx_hal::Backend> {
+    /// Image is owned by the graph and outlives the node.
+    image: *const Image<B>,
+    range: gfx_hal::image::SubresourceRange,
+    layout: gfx_hal::image::Layout,
+    acquire: Option<ImageBarrier>,
+    release: Option<ImageBarrier>,
+}
+
+unsafe impl<B> Send for InputImage<B> where B: gfx_hal::Backend {}
+unsafe impl<B> Sync for InputImage<B> where B: gfx_hal::Backend {}
+
+impl<B> InputImage<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn new(image: &NodeImage<'_, B>) -> Self {
+        InputImage {
+            image: &*image.image,
+            range: image.range.clone(),
+            layout: image.layout,
+            acquire: image.acquire.clone(),
+            release: image.release.clone(),
+        }
+    }
+
+    fn image(&self) -> &Image<B> {
+        // Graph doesn't move or destroy images until nodes are disposed.
+        unsafe { &*self.image }
+    }
+
+    fn barrier<'a>(&'a self, barrier: &ImageBarrier) -> gfx_hal::memory::Barrier<'a, B> {
+        gfx_hal::memory::Barrier::Image {
+            states: barrier.states.clone(),
+            families: barrier.families.clone(),
+            target: self.image().raw(),
+            range: self.range.clone(),
+        }
+    }
+}
+
+/// Step of swapchain recreation performed by the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RecreationStep {
+    /// Wait until device is idle and free per-image resources.
+    Wait,
+
+    /// Recreate swapchain and record per-image resources.
+    Recreate,
+}
+
+/// Decides when swapchain is recreated.
+#[derive(Clone, Copy, Debug)]
+struct Recreation {
+    /// Swapchain must be recreated before next image is acquired.
+    outdated: bool,
+
+    /// Per-image resources may be in use by the device.
+    in_use: bool,
+}
+
+impl Recreation {
+    fn new() -> Self {
+        Recreation {
+            outdated: false,
+            in_use: true,
+        }
+    }
+
+    /// Attempt to recreate out of date swapchain.
+    /// Device is waited for only once per swapchain, not on every failed attempt.
+    /// Nothing is done while surface extent is zero.
+    /// Returns `true` if swapchain is up to date.
+    fn attempt(
+        &mut self,
+        extent: gfx_hal::window::Extent2D,
+        mut step: impl FnMut(RecreationStep) -> bool,
+    ) -> bool {
+        if !self.outdated {
+            return true;
+        }
+
+        if extent.width == 0 || extent.height == 0 {
+            // Window is minimized.
+            return false;
+        }
+
+        if self.in_use {
+            if !step(RecreationStep::Wait) {
+                return false;
+            }
+            self.in_use = false;
+        }
+
+        if step(RecreationStep::Recreate) {
+            self.outdated = false;
+            self.in_use = true;
+        }
+
+        !self.outdated
+    }
+}
+
+/// Callback invoked with new extent after swapchain is recreated.
+pub type ResizeHook = Box<dyn FnMut(gfx_hal::window::Extent2D) + Send + Sync>;
+
+/// Node that presents images to the surface.
+/// Swapchain is recreated when it becomes out of date or suboptimal,
+/// e.g. after window resize.
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
 pub struct PresentNode<B: gfx_hal::Backend> {
     per_image: Vec<ForImage<B>>,
     free: Option<B::Semaphore>,
     target: Target<B>,
     pool: CommandPool<B, gfx_hal::QueueType>,
+    input: InputImage<B>,
+    recreation: Recreation,
+    #[derivative(Debug = "ignore")]
+    resize: Option<ResizeHook>,
 }
 
 impl<B> PresentNode<B>
@@ -46,16 +159,163 @@ where
             surface,
             image,
             dependencies: Vec::new(),
+            resize: None,
+        }
+    }
+
+    /// Get extent of the swapchain images.
+    pub fn extent(&self) -> gfx_hal::window::Extent2D {
+        self.target.extent()
+    }
+
+    /// Record copy commands for every target image.
+    fn record(&mut self, factory: &Factory<B>) -> Result<(), failure::Error> {
+        let ref input = self.input;
+        let ref mut pool = self.pool;
+        let input_extent = input.image().kind().extent();
+
+        self.per_image = match self.target.backbuffer() {
+            Backbuffer::Images(target_images) => {
+                let buffers = pool.allocate_buffers(target_images.len());
+                target_images
+                    .iter()
+                    .zip(buffers)
+                    .map(|(target_image, buf_initial)| -> Result<_, failure::Error> {
+                        let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
+                        let mut encoder = buf_recording.encoder();
+                        if let Some(acquire) = &input.acquire {
+                            log::info!("Acquire {:#?}", acquire);
+                            encoder.pipeline_barrier(
+                                acquire.stages.clone(),
+                                gfx_hal::memory::Dependencies::empty(),
+                                Some(input.barrier(acquire)),
+                            );
+                        }
+                        let target_extent = target_image.kind().extent();
+                        encoder.copy_image(
+                            input.image().raw(),
+                            input.layout,
+                            target_image.raw(),
+                            gfx_hal::image::Layout::TransferDstOptimal,
+                            Some(gfx_hal::command::ImageCopy {
+                                src_subresource: gfx_hal::image::SubresourceLayers {
+                                    aspects: gfx_hal::format::Aspects::COLOR,
+                                    level: 0,
+                                    layers: 0..1,
+                                },
+                                src_offset: gfx_hal::image::Offset::ZERO,
+                                dst_subresource: gfx_hal::image::SubresourceLayers {
+                                    aspects: gfx_hal::format::Aspects::COLOR,
+                                    level: 0,
+                                    layers: 0..1,
+                                },
+                                dst_offset: gfx_hal::image::Offset::ZERO,
+                                extent: gfx_hal::image::Extent {
+                                    width: target_extent.width.min(input_extent.width),
+                                    height: target_extent.height.min(input_extent.height),
+                                    depth: 1,
+                                },
+                            }),
+                        );
+                        if let Some(release) = &input.release {
+                            log::info!("Release {:#?}", release);
+                            encoder.pipeline_barrier(
+                                release.stages.clone(),
+                                gfx_hal::memory::Dependencies::empty(),
+                                Some(input.barrier(release)),
+                            );
+                        }
+
+                        let (submit, buffer) = buf_recording.finish().submit();
+
+                        Ok(ForImage {
+                            submit,
+                            buffer,
+                            acquire: factory.create_semaphore()?,
+                            release: factory.create_semaphore()?,
+                        })
+                    })
+                    .collect::<Result<_, failure::Error>>()?
+            }
+            _ => unimplemented!(),
+        };
+        Ok(())
+    }
+
+    /// Destroy per-image resources.
+    ///
+    /// # Safety
+    ///
+    /// Command buffers and semaphores must be not in use.
+    unsafe fn free_per_image(&mut self, factory: &Factory<B>) {
+        for for_image in self.per_image.drain(..) {
+            drop(for_image.submit);
+            factory.destroy_semaphore(for_image.acquire);
+            factory.destroy_semaphore(for_image.release);
+            self.pool
+                .free_buffers(Some(for_image.buffer.mark_complete()));
+        }
+    }
+
+    /// Mark swapchain out of date and attempt to recreate it.
+    unsafe fn outdate(&mut self, factory: &Factory<B>) {
+        self.recreation.outdated = true;
+        self.try_recreate(factory);
+    }
+
+    /// Attempt to recreate swapchain if it is out of date.
+    unsafe fn try_recreate(&mut self, factory: &Factory<B>) {
+        let mut recreation = self.recreation;
+        recreation.attempt(self.target.surface_extent(), |step| match step {
+            RecreationStep::Wait => self.wait_idle(factory),
+            RecreationStep::Recreate => self.recreate(factory),
+        });
+        self.recreation = recreation;
+    }
+
+    /// Wait for device idle and free per-image resources.
+    unsafe fn wait_idle(&mut self, factory: &Factory<B>) -> bool {
+        if let Err(err) = factory.wait_idle() {
+            log::error!("Failed to wait for device idle: {:?}", err);
+            return false;
         }
+
+        // Device is idle.
+        self.free_per_image(factory);
+        true
+    }
+
+    /// Recreate swapchain and re-record copy commands.
+    /// Per-image resources must be freed.
+    /// Returns `false` if swapchain can't be recreated now.
+    unsafe fn recreate(&mut self, factory: &Factory<B>) -> bool {
+        let result = factory
+            .recreate_target(&mut self.target)
+            .and_then(|()| self.record(factory));
+
+        if let Err(err) = result {
+            log::warn!("Failed to recreate swapchain: {}", err);
+            return false;
+        }
+
+        let extent = self.target.extent();
+        log::debug!("Swapchain recreated: {:?}", extent);
+        if let Some(resize) = &mut self.resize {
+            resize(extent);
+        }
+        true
     }
 }
 
 /// Presentation node description.
-#[derive(Debug)]
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
 pub struct PresentBuilder<B: gfx_hal::Backend> {
     surface: Surface<B>,
     image: ImageId,
     dependencies: Vec<NodeId>,
+    #[derivative(Debug = "ignore")]
+    resize: Option<ResizeHook>,
 }
 
 impl<B> PresentBuilder<B>
@@ -75,6 +335,26 @@ where
         self.add_dependency(dependency);
         self
     }
+
+    /// Set callback invoked with new extent each time swapchain is recreated.
+    /// Can be used to resize images of the graph.
+    pub fn set_resize_hook(
+        &mut self,
+        hook: impl FnMut(gfx_hal::window::Extent2D) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.resize = Some(Box::new(hook));
+        self
+    }
+
+    /// Set callback invoked with new extent each time swapchain is recreated.
+    /// Can be used to resize images of the graph.
+    pub fn with_resize_hook(
+        mut self,
+        hook: impl FnMut(gfx_hal::window::Extent2D) + Send + Sync + 'static,
+    ) -> Self {
+        self.set_resize_hook(hook);
+        self
+    }
 }
 
 impl<B, T> NodeBuilder<B, T> for PresentBuilder<B>
@@ -82,6 +362,10 @@ where
     B: gfx_hal::Backend,
     T: ?Sized,
 {
+    fn name(&self) -> String {
+        "Present".to_owned()
+    }
+
     fn family(&self, families: &[Family<B>]) -> Option<FamilyId> {
         // Find correct queue family.
         families.get(0).map(Family::id)
@@ -118,83 +402,20 @@ where
         assert_eq!(buffers.len(), 0);
         assert_eq!(images.len(), 1);
 
-        let ref input_image = images[0];
         let target = factory.create_target(self.surface, 3, gfx_hal::image::Usage::TRANSFER_DST)?;
-        let mut pool = factory.create_command_pool(family)?;
-
-        let per_image = match target.backbuffer() {
-            Backbuffer::Images(target_images) => {
-                let buffers = pool.allocate_buffers(target_images.len());
-                target_images
-                    .iter()
-                    .zip(buffers)
-                    .map(|(target_image, buf_initial)| {
-                        let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
-                        let mut encoder = buf_recording.encoder();
-                        {
-                            let (stages, barriers) = gfx_acquire_barriers(None, Some(input_image));
-                            log::info!("Acquire {:?} : {:#?}", stages, barriers);
-                            encoder.pipeline_barrier(
-                                stages,
-                                gfx_hal::memory::Dependencies::empty(),
-                                barriers,
-                            );
-                        }
-                        encoder.copy_image(
-                            input_image.image.raw(),
-                            input_image.layout,
-                            target_image.raw(),
-                            gfx_hal::image::Layout::TransferDstOptimal,
-                            Some(gfx_hal::command::ImageCopy {
-                                src_subresource: gfx_hal::image::SubresourceLayers {
-                                    aspects: gfx_hal::format::Aspects::COLOR,
-                                    level: 0,
-                                    layers: 0..1,
-                                },
-                                src_offset: gfx_hal::image::Offset::ZERO,
-                                dst_subresource: gfx_hal::image::SubresourceLayers {
-                                    aspects: gfx_hal::format::Aspects::COLOR,
-                                    level: 0,
-                                    layers: 0..1,
-                                },
-                                dst_offset: gfx_hal::image::Offset::ZERO,
-                                extent: gfx_hal::image::Extent {
-                                    width: target_image.kind().extent().width,
-                                    height: target_image.kind().extent().height,
-                                    depth: 1,
-                                },
-                            }),
-                        );
-                        {
-                            let (stages, barriers) = gfx_release_barriers(None, Some(input_image));
-                            log::info!("Release {:?} : {:#?}", stages, barriers);
-                            encoder.pipeline_barrier(
-                                stages,
-                                gfx_hal::memory::Dependencies::empty(),
-                                barriers,
-                            );
-                        }
-
-                        let (submit, buffer) = buf_recording.finish().submit();
-
-                        ForImage {
-                            submit,
-                            buffer,
-                            acquire: factory.create_semaphore().unwrap(),
-                            release: factory.create_semaphore().unwrap(),
-                        }
-                    })
-                    .collect()
-            }
-            _ => unimplemented!(),
-        };
+        let pool = factory.create_command_pool(family)?;
 
-        Ok(Box::new(PresentNode {
-            free: Some(factory.create_semaphore().unwrap()),
+        let mut node = PresentNode {
+            free: Some(factory.create_semaphore()?),
             target,
             pool,
-            per_image,
-        }))
+            per_image: Vec::new(),
+            input: InputImage::new(&images[0]),
+            recreation: Recreation::new(),
+            resize: self.resize,
+        };
+        node.record(factory)?;
+        Ok(Box::new(node))
     }
 }
 
@@ -211,47 +432,172 @@ where
         qid: QueueId,
         waits: &[(&'a B::Semaphore, gfx_hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
-        fence: Option<&mut Fence<B>>,
+        mut fence: Option<&mut Fence<B>>,
     ) {
-        let acquire = self.free.take().unwrap();
-        let next = self.target.next_image(&acquire).unwrap();
-        log::trace!("Present: {:#?}", next);
-        let ref mut for_image = self.per_image[next[0] as usize];
-        self.free = Some(std::mem::replace(&mut for_image.acquire, acquire));
-
-        let family = factory.family_mut(qid.family());
-
-        family.queues_mut()[qid.index()].submit(
-            Some(
-                Submission::new()
-                    .submits(Some(&for_image.submit))
-                    .wait(waits.iter().cloned().chain(Some((
-                        &for_image.acquire,
-                        gfx_hal::pso::PipelineStage::TRANSFER,
-                    ))))
-                    .signal(signals.iter().cloned().chain(Some(&for_image.release))),
-            ),
-            fence,
-        );
+        self.try_recreate(factory);
 
-        next.present(
-            family.queues_mut()[qid.index()].raw(),
-            Some(&for_image.release),
-        )
-        .expect("Fix swapchain error");
-    }
+        let presented = if self.recreation.outdated {
+            None
+        } else {
+            let acquire = self.free.take().unwrap();
+            match self.target.next_image(&acquire) {
+                Ok(next) => {
+                    log::trace!("Present: {:#?}", next);
+                    let ref mut for_image = self.per_image[next[0] as usize];
+                    self.free = Some(std::mem::replace(&mut for_image.acquire, acquire));
 
-    unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, _aux: &mut T) {
-        for for_image in self.per_image {
-            drop(for_image.submit);
-            factory.destroy_semaphore(for_image.acquire);
-            factory.destroy_semaphore(for_image.release);
-            self.pool
-                .free_buffers(Some(for_image.buffer.mark_complete()));
+                    let family = factory.family_mut(qid.family());
+
+                    family.queues_mut()[qid.index()].submit(
+                        Some(
+                            Submission::new()
+                                .submits(Some(&for_image.submit))
+                                .wait(waits.iter().cloned().chain(Some((
+                                    &for_image.acquire,
+                                    gfx_hal::pso::PipelineStage::TRANSFER,
+                                ))))
+                                .signal(signals.iter().cloned().chain(Some(&for_image.release))),
+                        ),
+                        fence.take(),
+                    );
+
+                    Some(next.present(
+                        family.queues_mut()[qid.index()].raw(),
+                        Some(&for_image.release),
+                    ))
+                }
+                Err(gfx_hal::AcquireError::OutOfDate) => {
+                    // Semaphore is not signaled on failure.
+                    self.free = Some(acquire);
+                    None
+                }
+                Err(err) => panic!("Failed to acquire swapchain image: {:?}", err),
+            }
+        };
+
+        match presented {
+            Some(Ok(())) => {}
+            Some(Err(OutOfDate)) => {
+                log::debug!("Swapchain is out of date after present");
+                self.outdate(factory);
+            }
+            None => {
+                // Nothing is presented this frame.
+                // Still wait and signal semaphores to keep graph synchronization valid.
+                factory.family_mut(qid.family()).queues_mut()[qid.index()].submit(
+                    Some(
+                        Submission::new()
+                            .wait(waits.iter().cloned())
+                            .signal(signals.iter().cloned()),
+                    ),
+                    fence.take(),
+                );
+                if !self.recreation.outdated {
+                    log::debug!("Swapchain is out of date on acquire");
+                    self.outdate(factory);
+                }
+            }
         }
+    }
 
-        factory.destroy_semaphore(self.free.unwrap());
+    unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, _aux: &mut T) {
+        self.free_per_image(factory);
+        factory.destroy_semaphore(self.free.take().unwrap());
         factory.destroy_command_pool(self.pool);
         factory.destroy_target(self.target);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Recreation, RecreationStep};
+
+    const MINIMIZED: gfx_hal::window::Extent2D = gfx_hal::window::Extent2D {
+        width: 0,
+        height: 0,
+    };
+
+    const WINDOW: gfx_hal::window::Extent2D = gfx_hal::window::Extent2D {
+        width: 800,
+        height: 600,
+    };
+
+    /// Run attempt with scripted recreation result.
+    /// Returns result of the attempt and steps performed.
+    fn attempt(
+        recreation: &mut Recreation,
+        extent: gfx_hal::window::Extent2D,
+        recreated: bool,
+    ) -> (bool, Vec<RecreationStep>) {
+        let mut steps = Vec::new();
+        let result = recreation.attempt(extent, |step| {
+            steps.push(step);
+            match step {
+                RecreationStep::Wait => true,
+                RecreationStep::Recreate => recreated,
+            }
+        });
+        (result, steps)
+    }
+
+    #[test]
+    fn up_to_date_does_nothing() {
+        let mut recreation = Recreation::new();
+        assert_eq!(attempt(&mut recreation, WINDOW, true), (true, vec![]));
+    }
+
+    #[test]
+    fn out_of_date_script() {
+        use super::RecreationStep::{Recreate, Wait};
+
+        let mut recreation = Recreation::new();
+        recreation.outdated = true;
+
+        // Window is minimized. Device is not waited for.
+        assert_eq!(attempt(&mut recreation, MINIMIZED, true), (false, vec![]));
+        assert_eq!(attempt(&mut recreation, MINIMIZED, true), (false, vec![]));
+
+        // Recreation fails. Device is waited for only on first attempt.
+        assert_eq!(
+            attempt(&mut recreation, WINDOW, false),
+            (false, vec![Wait, Recreate])
+        );
+        assert_eq!(
+            attempt(&mut recreation, WINDOW, false),
+            (false, vec![Recreate])
+        );
+        assert_eq!(attempt(&mut recreation, MINIMIZED, true), (false, vec![]));
+
+        // Recreated.
+        assert_eq!(
+            attempt(&mut recreation, WINDOW, true),
+            (true, vec![Recreate])
+        );
+        assert_eq!(attempt(&mut recreation, WINDOW, true), (true, vec![]));
+
+        // New swapchain is in use and out of date again.
+        recreation.outdated = true;
+        assert_eq!(
+            attempt(&mut recreation, WINDOW, true),
+            (true, vec![Wait, Recreate])
+        );
+    }
+
+    #[test]
+    fn failed_wait_is_retried() {
+        let mut recreation = Recreation::new();
+        recreation.outdated = true;
+
+        let mut waits = 0;
+        assert!(!recreation.attempt(WINDOW, |step| {
+            assert_eq!(step, RecreationStep::Wait);
+            waits += 1;
+            false
+        }));
+        assert_eq!(
+            attempt(&mut recreation, WINDOW, true),
+            (true, vec![RecreationStep::Wait, RecreationStep::Recreate])
+        );
+        assert_eq!(waits, 1);
+    }
+}
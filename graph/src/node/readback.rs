@@ -0,0 +1,546 @@
+//! Defines readback node.
+
+use {
+    crate::{
+        command::{
+            CommandBuffer, CommandPool, ExecutableState, Family, FamilyId, Fence, Graphics,
+            MultiShot, PendingState, QueueId, SimultaneousUse, Submission, Submit, Supports,
+        },
+        factory::Factory,
+        frame::Frames,
+        node::{
+            BufferAccess, DynNode, ImageAccess, ImageBarrier, NodeBuffer, NodeBuilder, NodeImage,
+        },
+        resource::{
+            buffer::{Buffer, DownloadBuffer},
+            image::Image,
+        },
+        texture::pixel::AsPixel,
+        BufferId, ImageId, NodeId,
+    },
+    std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Image read back from device.
+#[derive(Clone, Debug)]
+pub struct ReadbackImage<P> {
+    frame: u64,
+    extent: gfx_hal::image::Extent,
+    pixels: Vec<P>,
+}
+
+impl<P> ReadbackImage<P>
+where
+    P: AsPixel,
+{
+    /// Get index of the frame image was read in.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Get extent of the image.
+    pub fn extent(&self) -> gfx_hal::image::Extent {
+        self.extent
+    }
+
+    /// Get pixels of the image.
+    /// Rows are tightly packed.
+    pub fn pixels(&self) -> &[P] {
+        &self.pixels
+    }
+
+    /// Get pixels of the image.
+    pub fn into_pixels(self) -> Vec<P> {
+        self.pixels
+    }
+
+    /// Get raw bytes of the image.
+    pub fn bytes(&self) -> &[u8] {
+        unsafe {
+            // Pixels are plain old data.
+            std::slice::from_raw_parts(
+                self.pixels.as_ptr() as *const u8,
+                self.pixels.len() * std::mem::size_of::<P>(),
+            )
+        }
+    }
+
+    /// Write image to PNG file.
+    /// Only 8-bit `R`, `Rg`, `Rgb` and `Rgba` unorm and srgb pixels are supported.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, path: impl AsRef<std::path::Path>) -> Result<(), failure::Error> {
+        use gfx_hal::format::Format;
+
+        let color = match P::FORMAT {
+            Format::R8Unorm | Format::R8Srgb => png::ColorType::Grayscale,
+            Format::Rg8Unorm | Format::Rg8Srgb => png::ColorType::GrayscaleAlpha,
+            Format::Rgb8Unorm | Format::Rgb8Srgb => png::ColorType::RGB,
+            Format::Rgba8Unorm | Format::Rgba8Srgb => png::ColorType::RGBA,
+            _ => failure::bail!("Pixel format {} can't be written to PNG", P::NAME),
+        };
+
+        let file = std::fs::File::create(path)?;
+        let mut encoder = png::Encoder::new(
+            std::io::BufWriter::new(file),
+            self.extent.width,
+            self.extent.height,
+        );
+        encoder.set(color).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.bytes())?;
+        Ok(())
+    }
+}
+
+/// Handle to receive images read back by `ReadbackNode`.
+/// Images become available once frames they were read in are complete.
+#[derive(derivative::Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = "P: std::fmt::Debug"))]
+pub struct Readback<P> {
+    complete: Arc<Mutex<VecDeque<ReadbackImage<P>>>>,
+}
+
+impl<P> Readback<P> {
+    /// Take oldest image read back.
+    pub fn try_recv(&self) -> Option<ReadbackImage<P>> {
+        self.complete.lock().unwrap().pop_front()
+    }
+
+    /// Take all images read back so far.
+    pub fn drain(&self) -> Vec<ReadbackImage<P>> {
+        self.complete.lock().unwrap().drain(..).collect()
+    }
+
+    fn push(&self, image: ReadbackImage<P>) {
+        self.complete.lock().unwrap().push_back(image);
+    }
+}
+
+#[derive(Debug)]
+struct ForFrame<B: gfx_hal::Backend> {
+    /// Index of the frame pending readback.
+    frame: Option<u64>,
+    buffer: Buffer<B>,
+    submit: Submit<B, SimultaneousUse>,
+    command: CommandBuffer<
+        B,
+        gfx_hal::QueueType,
+        PendingState<ExecutableState<MultiShot<SimultaneousUse>>>,
+    >,
+}
+
+/// Node that copies image to host-visible buffer each frame.
+/// Can be used to render without surface.
+///
+/// Image is converted to pixel format `P` with blit if its format differs.
+#[derive(Debug)]
+pub struct ReadbackNode<B: gfx_hal::Backend, P> {
+    per_frame: Vec<ForFrame<B>>,
+    pool: CommandPool<B, gfx_hal::QueueType>,
+
+    /// Image is owned by the graph and outlives the node.
+    image: *const Image<B>,
+    range: gfx_hal::image::SubresourceRange,
+    layout: gfx_hal::image::Layout,
+    acquire: Option<ImageBarrier>,
+    release: Option<ImageBarrier>,
+
+    /// Image with pixel format if conversion is required.
+    converted: Option<Image<B>>,
+    readback: Readback<P>,
+}
+
+unsafe impl<B, P> Send for ReadbackNode<B, P>
+where
+    B: gfx_hal::Backend,
+    P: Send,
+{
+}
+unsafe impl<B, P> Sync for ReadbackNode<B, P>
+where
+    B: gfx_hal::Backend,
+    P: Send,
+{
+}
+
+impl<B, P> ReadbackNode<B, P>
+where
+    B: gfx_hal::Backend,
+    P: AsPixel,
+{
+    /// Node builder.
+    pub fn builder(image: ImageId) -> ReadbackBuilder<B, P> {
+        ReadbackBuilder {
+            image,
+            dependencies: Vec::new(),
+            readback: Readback {
+                complete: Arc::new(Mutex::new(VecDeque::new())),
+            },
+            marker: std::marker::PhantomData,
+        }
+    }
+
+    fn image(&self) -> &Image<B> {
+        // Graph doesn't move or destroy images until nodes are disposed.
+        unsafe { &*self.image }
+    }
+
+    fn extent(&self) -> gfx_hal::image::Extent {
+        self.image().kind().extent()
+    }
+
+    fn size(&self) -> u64 {
+        let extent = self.extent();
+        extent.width as u64 * extent.height as u64 * extent.depth as u64 * P::SIZE as u64
+    }
+
+    fn barrier<'a>(&'a self, barrier: &ImageBarrier) -> gfx_hal::memory::Barrier<'a, B> {
+        gfx_hal::memory::Barrier::Image {
+            states: barrier.states.clone(),
+            families: barrier.families.clone(),
+            target: self.image().raw(),
+            range: self.range.clone(),
+        }
+    }
+
+    /// Create buffer and record commands for one more frame in flight.
+    fn for_frame(&mut self, factory: &Factory<B>) -> Result<ForFrame<B>, failure::Error> {
+        let buffer = factory.create_buffer(4, self.size(), DownloadBuffer)?;
+        let extent = self.extent();
+        let bounds = gfx_hal::image::Offset::ZERO..gfx_hal::image::Offset {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: extent.depth as i32,
+        };
+        let layers = gfx_hal::image::SubresourceLayers {
+            aspects: gfx_hal::format::Aspects::COLOR,
+            level: 0,
+            layers: 0..1,
+        };
+        let converted_range = gfx_hal::image::SubresourceRange {
+            aspects: gfx_hal::format::Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..1,
+        };
+
+        let buf_initial = self.pool.allocate_buffers(1).pop().unwrap();
+        let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
+        let mut encoder = buf_recording.encoder();
+
+        if let Some(acquire) = &self.acquire {
+            encoder.pipeline_barrier(
+                acquire.stages.clone(),
+                gfx_hal::memory::Dependencies::empty(),
+                Some(self.barrier(acquire)),
+            );
+        }
+
+        let (src, src_layout) = match &self.converted {
+            Some(converted) => {
+                // Previous frame reads converted image with the same stage.
+                encoder.pipeline_barrier(
+                    gfx_hal::pso::PipelineStage::TRANSFER..gfx_hal::pso::PipelineStage::TRANSFER,
+                    gfx_hal::memory::Dependencies::empty(),
+                    Some(gfx_hal::memory::Barrier::Image {
+                        states: (
+                            gfx_hal::image::Access::TRANSFER_READ,
+                            gfx_hal::image::Layout::Undefined,
+                        )
+                            ..(
+                                gfx_hal::image::Access::TRANSFER_WRITE,
+                                gfx_hal::image::Layout::TransferDstOptimal,
+                            ),
+                        families: None,
+                        target: converted.raw(),
+                        range: converted_range.clone(),
+                    }),
+                );
+                encoder.blit_image(
+                    self.image().raw(),
+                    self.layout,
+                    converted.raw(),
+                    gfx_hal::image::Layout::TransferDstOptimal,
+                    gfx_hal::image::Filter::Nearest,
+                    Some(gfx_hal::command::ImageBlit {
+                        src_subresource: layers.clone(),
+                        src_bounds: bounds.clone(),
+                        dst_subresource: layers.clone(),
+                        dst_bounds: bounds,
+                    }),
+                );
+                encoder.pipeline_barrier(
+                    gfx_hal::pso::PipelineStage::TRANSFER..gfx_hal::pso::PipelineStage::TRANSFER,
+                    gfx_hal::memory::Dependencies::empty(),
+                    Some(gfx_hal::memory::Barrier::Image {
+                        states: (
+                            gfx_hal::image::Access::TRANSFER_WRITE,
+                            gfx_hal::image::Layout::TransferDstOptimal,
+                        )
+                            ..(
+                                gfx_hal::image::Access::TRANSFER_READ,
+                                gfx_hal::image::Layout::TransferSrcOptimal,
+                            ),
+                        families: None,
+                        target: converted.raw(),
+                        range: converted_range.clone(),
+                    }),
+                );
+                (converted.raw(), gfx_hal::image::Layout::TransferSrcOptimal)
+            }
+            None => (self.image().raw(), self.layout),
+        };
+
+        encoder.copy_image_to_buffer(
+            src,
+            src_layout,
+            buffer.raw(),
+            Some(gfx_hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers: layers,
+                image_offset: gfx_hal::image::Offset::ZERO,
+                image_extent: extent,
+            }),
+        );
+
+        // Make copied data visible to the host.
+        encoder.pipeline_barrier(
+            gfx_hal::pso::PipelineStage::TRANSFER..gfx_hal::pso::PipelineStage::HOST,
+            gfx_hal::memory::Dependencies::empty(),
+            Some(gfx_hal::memory::Barrier::Buffer {
+                states: gfx_hal::buffer::Access::TRANSFER_WRITE..gfx_hal::buffer::Access::HOST_READ,
+                families: None,
+                target: buffer.raw(),
+                range: None..None,
+            }),
+        );
+
+        if let Some(release) = &self.release {
+            encoder.pipeline_barrier(
+                release.stages.clone(),
+                gfx_hal::memory::Dependencies::empty(),
+                Some(self.barrier(release)),
+            );
+        }
+
+        let (submit, command) = buf_recording.finish().submit();
+        Ok(ForFrame {
+            frame: None,
+            buffer,
+            submit,
+            command,
+        })
+    }
+
+    /// Read buffers of complete frames.
+    fn collect(&mut self, factory: &Factory<B>, frames: &Frames<B>) {
+        let extent = self.extent();
+        let size = self.size();
+        for for_frame in &mut self.per_frame {
+            let frame = match for_frame.frame {
+                Some(frame) if frames.complete(frame).is_some() => frame,
+                _ => continue,
+            };
+            for_frame.frame = None;
+
+            let pixels = unsafe {
+                // Frame is complete. Device doesn't write to the buffer.
+                for_frame
+                    .buffer
+                    .map(factory, 0..size)
+                    .and_then(|mut mapped| {
+                        mapped
+                            .read::<P>(factory.device(), 0..size)
+                            .map(|pixels| pixels.to_vec())
+                    })
+            };
+
+            match pixels {
+                Ok(pixels) => self.readback.push(ReadbackImage {
+                    frame,
+                    extent,
+                    pixels,
+                }),
+                Err(err) => log::error!("Failed to read back frame {}: {:?}", frame, err),
+            }
+        }
+    }
+}
+
+/// Readback node description.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = "P: std::fmt::Debug"))]
+pub struct ReadbackBuilder<B: gfx_hal::Backend, P> {
+    image: ImageId,
+    dependencies: Vec<NodeId>,
+    readback: Readback<P>,
+    marker: std::marker::PhantomData<fn() -> B>,
+}
+
+impl<B, P> ReadbackBuilder<B, P>
+where
+    B: gfx_hal::Backend,
+{
+    /// Get handle to receive images read back by the node.
+    pub fn readback(&self) -> Readback<P> {
+        self.readback.clone()
+    }
+
+    /// Add dependency.
+    /// Node will be placed after its dependencies.
+    pub fn add_dependency(&mut self, dependency: NodeId) -> &mut Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    /// Add dependency.
+    /// Node will be placed after its dependencies.
+    pub fn with_dependency(mut self, dependency: NodeId) -> Self {
+        self.add_dependency(dependency);
+        self
+    }
+}
+
+impl<B, T, P> NodeBuilder<B, T> for ReadbackBuilder<B, P>
+where
+    B: gfx_hal::Backend,
+    T: ?Sized,
+    P: AsPixel,
+{
+    fn name(&self) -> String {
+        "Readback".to_owned()
+    }
+
+    fn family(&self, families: &[Family<B>]) -> Option<FamilyId> {
+        // Blit requires graphics queue.
+        families
+            .iter()
+            .find(|family| Supports::<Graphics>::supports(&family.capability()).is_some())
+            .map(Family::id)
+    }
+
+    fn buffers(&self) -> Vec<(BufferId, BufferAccess)> {
+        Vec::new()
+    }
+
+    fn images(&self) -> Vec<(ImageId, ImageAccess)> {
+        vec![(
+            self.image,
+            ImageAccess {
+                access: gfx_hal::image::Access::TRANSFER_READ,
+                layout: gfx_hal::image::Layout::TransferSrcOptimal,
+                usage: gfx_hal::image::Usage::TRANSFER_SRC,
+                stages: gfx_hal::pso::PipelineStage::TRANSFER,
+            },
+        )]
+    }
+
+    fn dependencies(&self) -> Vec<NodeId> {
+        self.dependencies.clone()
+    }
+
+    fn build<'a>(
+        self: Box<Self>,
+        factory: &mut Factory<B>,
+        _aux: &mut T,
+        family: FamilyId,
+        buffers: Vec<NodeBuffer<'a, B>>,
+        images: Vec<NodeImage<'a, B>>,
+    ) -> Result<Box<dyn DynNode<B, T>>, failure::Error> {
+        assert_eq!(buffers.len(), 0);
+        assert_eq!(images.len(), 1);
+
+        let ref image = images[0];
+        let converted = if image.image.format() == P::FORMAT {
+            None
+        } else {
+            log::debug!(
+                "Readback converts image from {:?} to {}",
+                image.image.format(),
+                P::NAME
+            );
+            let extent = image.image.kind().extent();
+            Some(factory.create_image(
+                1,
+                gfx_hal::image::Kind::D2(extent.width, extent.height, 1, 1),
+                1,
+                P::FORMAT,
+                gfx_hal::image::Tiling::Optimal,
+                gfx_hal::image::ViewCapabilities::empty(),
+                (
+                    gfx_hal::image::Usage::TRANSFER_DST | gfx_hal::image::Usage::TRANSFER_SRC,
+                    crate::memory::Data,
+                ),
+            )?)
+        };
+
+        Ok(Box::new(ReadbackNode {
+            per_frame: Vec::new(),
+            pool: factory.create_command_pool(family)?,
+            image: &*image.image,
+            range: image.range.clone(),
+            layout: image.layout,
+            acquire: image.acquire.clone(),
+            release: image.release.clone(),
+            converted,
+            readback: self.readback,
+        }))
+    }
+}
+
+impl<B, T, P> DynNode<B, T> for ReadbackNode<B, P>
+where
+    B: gfx_hal::Backend,
+    T: ?Sized,
+    P: AsPixel,
+{
+    unsafe fn run<'a>(
+        &mut self,
+        factory: &mut Factory<B>,
+        _aux: &mut T,
+        frames: &Frames<B>,
+        qid: QueueId,
+        waits: &[(&'a B::Semaphore, gfx_hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        self.collect(factory, frames);
+
+        let index = match self.per_frame.iter().position(|f| f.frame.is_none()) {
+            Some(index) => index,
+            None => {
+                let for_frame = self
+                    .for_frame(factory)
+                    .expect("Failed to create readback buffer");
+                self.per_frame.push(for_frame);
+                self.per_frame.len() - 1
+            }
+        };
+
+        let ref mut for_frame = self.per_frame[index];
+        for_frame.frame = Some(frames.next().index());
+
+        factory.family_mut(qid.family()).queues_mut()[qid.index()].submit(
+            Some(
+                Submission::new()
+                    .submits(Some(&for_frame.submit))
+                    .wait(waits.iter().cloned())
+                    .signal(signals.iter().cloned()),
+            ),
+            fence,
+        );
+    }
+
+    unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, _aux: &mut T) {
+        for for_frame in self.per_frame.drain(..) {
+            drop(for_frame.submit);
+            self.pool
+                .free_buffers(Some(for_frame.command.mark_complete()));
+        }
+        factory.destroy_command_pool(self.pool);
+    }
+}
@@ -6,6 +6,7 @@ use {
     crate::{
         command::RenderPassEncoder,
         factory::Factory,
+        frame::Frames,
         node::{
             render::{pass::SubpassBuilder, PrepareResult},
             BufferAccess, DescBuilder, ImageAccess, NodeBuffer, NodeImage,
@@ -59,10 +60,27 @@ pub trait RenderGroupDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
 }
 
 pub trait RenderGroup<B: Backend, T: ?Sized>: std::fmt::Debug + Send + Sync {
-    fn prepare(&mut self, factory: &mut Factory<B>, index: usize, aux: &T) -> PrepareResult;
+    /// Prepare to record drawing commands.
+    /// `subpass` is the subpass group was built for.
+    /// `frames` can be used to find out when resources replaced by the group are no longer in use.
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        frames: &Frames<B>,
+        subpass: gfx_hal::pass::Subpass<'_, B>,
+        index: usize,
+        aux: &T,
+    ) -> PrepareResult;
 
     fn draw_inline(&mut self, encoder: RenderPassEncoder<'_, B>, index: usize, aux: &T);
 
+    /// Record drawing commands into secondary command buffer of the group.
+    /// Used instead of `draw_inline` in subpasses recorded with secondary command buffers.
+    /// Buffer is re-recorded only when `prepare` returns `PrepareResult::DrawRecord`.
+    fn draw_secondary(&mut self, encoder: RenderPassEncoder<'_, B>, index: usize, aux: &T) {
+        self.draw_inline(encoder, index, aux)
+    }
+
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &mut T);
 }
 
@@ -75,6 +93,12 @@ pub trait RenderGroupBuilder<B: Backend, T: ?Sized>: std::fmt::Debug {
         SubpassBuilder::new().with_group(self)
     }
 
+    /// RenderGroup name.
+    /// Used in logs and profiling reports.
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
     /// Number of color output images.
     fn colors(&self) -> usize;
 
@@ -108,6 +132,10 @@ where
     T: ?Sized,
     D: RenderGroupDesc<B, T>,
 {
+    fn name(&self) -> String {
+        self.desc.name().to_owned()
+    }
+
     fn colors(&self) -> usize {
         self.desc.colors()
     }
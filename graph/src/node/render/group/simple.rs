@@ -3,9 +3,11 @@ use {
     crate::{
         command::RenderPassEncoder,
         factory::Factory,
+        frame::Frames,
         node::{
             render::PrepareResult, BufferAccess, DescBuilder, ImageAccess, NodeBuffer, NodeImage,
         },
+        shader::Reflection,
     },
     gfx_hal::{Backend, Device},
     std::marker::PhantomData,
@@ -28,6 +30,23 @@ pub struct Layout {
     pub push_constants: Vec<(gfx_hal::pso::ShaderStageFlags, std::ops::Range<u32>)>,
 }
 
+impl Layout {
+    /// Make pipeline layout from reflection of the pipeline shaders.
+    /// See [`Reflection::merge`] to combine reflections of several stages.
+    ///
+    /// [`Reflection::merge`]: ../../../rendy_shader/struct.Reflection.html#method.merge
+    pub fn from_reflection(reflection: &Reflection) -> Self {
+        Layout {
+            sets: reflection
+                .set_bindings()
+                .into_iter()
+                .map(|bindings| SetLayout { bindings })
+                .collect(),
+            push_constants: reflection.push_constants.clone(),
+        }
+    }
+}
+
 /// Pipeline info
 #[derive(Clone, Debug)]
 pub struct Pipeline {
@@ -168,6 +187,28 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
     where
         Self: Sized;
 
+    /// Reload shader set if shaders were changed since they were loaded.
+    /// Returns `None` if shaders are up to date.
+    /// Graphics pipeline is recreated with the returned shader set.
+    /// Old pipeline is kept if `Some(Err(_))` is returned or pipeline creation fails.
+    ///
+    /// # Parameters
+    ///
+    /// `storage`   - vector where this function can store loaded modules to give them required lifetime.
+    ///
+    /// `factory`   - factory to create shader modules.
+    ///
+    /// `aux`       - auxiliary data container. May be anything the implementation desires.
+    ///
+    fn reload_shader_set<'a>(
+        &self,
+        _storage: &'a mut Vec<B::ShaderModule>,
+        _factory: &Factory<B>,
+        _aux: &T,
+    ) -> Option<Result<gfx_hal::pso::GraphicsShaderSet<'a, B>, failure::Error>> {
+        None
+    }
+
     /// Build pass instance.
     fn build<'a>(
         factory: &mut Factory<B>,
@@ -184,7 +225,7 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
     /// Should return true if commands must be re-recorded.
     fn prepare(
         &mut self,
-        _factory: &mut Factory<B>,
+        _factory: &Factory<B>,
         _set_layouts: &[B::DescriptorSetLayout],
         _index: usize,
         _aux: &T,
@@ -210,6 +251,111 @@ pub struct SimpleRenderGroup<B: Backend, P> {
     pipeline_layout: B::PipelineLayout,
     graphics_pipeline: B::GraphicsPipeline,
     pipeline: P,
+    rect: gfx_hal::pso::Rect,
+
+    /// Pipelines replaced by reload.
+    /// Commands recorded before reload may still use them.
+    retired: Vec<B::GraphicsPipeline>,
+
+    /// Index of the first frame which can't use retired pipelines.
+    retired_until: u64,
+
+    /// Flag per command buffer index.
+    /// Set for buffers with commands recorded before last reload.
+    stale: Vec<bool>,
+}
+
+impl<B, P> SimpleRenderGroup<B, P>
+where
+    B: Backend,
+{
+    /// Recreate graphics pipeline if pipeline reloaded shaders.
+    /// Old pipeline is retired until all command buffers are re-recorded
+    /// and frames that used them complete.
+    fn reload<T>(
+        &mut self,
+        factory: &Factory<B>,
+        frames: &Frames<B>,
+        subpass: gfx_hal::pass::Subpass<'_, B>,
+        aux: &T,
+    ) where
+        T: ?Sized,
+        P: SimpleGraphicsPipeline<B, T>,
+    {
+        let mut shaders = Vec::new();
+        let result = match self.pipeline.reload_shader_set(&mut shaders, factory, aux) {
+            None => return,
+            Some(result) => result.and_then(|shader_set| {
+                create_graphics_pipeline(
+                    factory,
+                    shader_set,
+                    &P::pipeline(),
+                    &self.pipeline_layout,
+                    subpass,
+                    self.rect,
+                )
+            }),
+        };
+
+        for module in shaders {
+            unsafe {
+                factory.device().destroy_shader_module(module);
+            }
+        }
+
+        match result {
+            Ok(graphics_pipeline) => {
+                log::info!("Graphics pipeline for '{}' is recreated", P::name());
+                let old = std::mem::replace(&mut self.graphics_pipeline, graphics_pipeline);
+
+                // Old pipeline is used by all commands recorded so far.
+                self.retired.push(old);
+                self.retired_until = frames.next().index();
+                for stale in &mut self.stale {
+                    *stale = true;
+                }
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to reload shaders for '{}'. Old pipeline is kept: {}",
+                    P::name(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Destroy retired pipelines if no command buffer can use them anymore.
+    fn destroy_retired(&mut self, factory: &Factory<B>, frames: &Frames<B>) {
+        if self.stale.iter().any(|&stale| stale)
+            || self.retired_until > frames.complete_upper_bound()
+        {
+            return;
+        }
+
+        for pipeline in self.retired.drain(..) {
+            unsafe {
+                factory.device().destroy_graphics_pipeline(pipeline);
+            }
+        }
+    }
+
+    /// Check if commands for buffer with specified index must be re-recorded
+    /// to stop using retired pipelines.
+    fn take_stale(&mut self, frames: &Frames<B>, index: usize) -> bool {
+        if self.stale.len() <= index {
+            // New buffers are recorded from scratch.
+            self.stale.resize(index + 1, false);
+        }
+
+        if std::mem::replace(&mut self.stale[index], false) {
+            // Previous submission of this buffer may still be pending.
+            self.retired_until = frames.next().index();
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl<B, T, P> RenderGroupDesc<B, T> for PhantomData<P>
@@ -258,29 +404,22 @@ where
         let set_layouts = pipeline
             .layout
             .sets
-            .into_iter()
+            .iter()
             .map(|set| unsafe {
                 factory
                     .device()
-                    .create_descriptor_set_layout(set.bindings, std::iter::empty::<B::Sampler>())
+                    .create_descriptor_set_layout(&set.bindings, std::iter::empty::<B::Sampler>())
             })
             .collect::<Result<Vec<_>, _>>()?;
 
         let pipeline_layout = unsafe {
             factory
                 .device()
-                .create_pipeline_layout(&set_layouts, pipeline.layout.push_constants)
+                .create_pipeline_layout(&set_layouts, &pipeline.layout.push_constants)
         }?;
 
         assert_eq!(pipeline.colors.len(), P::colors());
 
-        let mut vertex_buffers = Vec::new();
-        let mut attributes = Vec::new();
-
-        for &(ref elemets, stride, rate) in &pipeline.vertices {
-            push_vertex_desc(elemets, stride, rate, &mut vertex_buffers, &mut attributes);
-        }
-
         let rect = gfx_hal::pso::Rect {
             x: 0,
             y: 0,
@@ -288,41 +427,14 @@ where
             h: framebuffer_height as i16,
         };
 
-        let graphics_pipeline = unsafe {
-            factory.device().create_graphics_pipelines(
-                Some(gfx_hal::pso::GraphicsPipelineDesc {
-                    shaders: shader_set,
-                    rasterizer: gfx_hal::pso::Rasterizer::FILL,
-                    vertex_buffers,
-                    attributes,
-                    input_assembler: gfx_hal::pso::InputAssemblerDesc {
-                        primitive: gfx_hal::Primitive::TriangleList,
-                        primitive_restart: gfx_hal::pso::PrimitiveRestart::Disabled,
-                    },
-                    blender: gfx_hal::pso::BlendDesc {
-                        logic_op: None,
-                        targets: pipeline.colors.clone(),
-                    },
-                    depth_stencil: pipeline.depth_stencil,
-                    multisampling: None,
-                    baked_states: gfx_hal::pso::BakedStates {
-                        viewport: Some(gfx_hal::pso::Viewport {
-                            rect,
-                            depth: 0.0..1.0,
-                        }),
-                        scissor: Some(rect),
-                        blend_color: None,
-                        depth_bounds: None,
-                    },
-                    layout: &pipeline_layout,
-                    subpass,
-                    flags: gfx_hal::pso::PipelineCreationFlags::empty(),
-                    parent: gfx_hal::pso::BasePipeline::None,
-                }),
-                None,
-            )
-        }
-        .remove(0)?;
+        let graphics_pipeline = create_graphics_pipeline(
+            factory,
+            shader_set,
+            &pipeline,
+            &pipeline_layout,
+            subpass,
+            rect,
+        )?;
 
         let pipeline = P::build(factory, aux, buffers, images, &set_layouts)?;
 
@@ -331,6 +443,10 @@ where
             pipeline_layout,
             graphics_pipeline,
             pipeline,
+            rect,
+            retired: Vec::new(),
+            retired_until: 0,
+            stale: Vec::new(),
         }))
     }
 }
@@ -341,9 +457,26 @@ where
     T: ?Sized,
     P: SimpleGraphicsPipeline<B, T>,
 {
-    fn prepare(&mut self, factory: &mut Factory<B>, index: usize, aux: &T) -> PrepareResult {
-        self.pipeline
-            .prepare(factory, &self.set_layouts, index, aux)
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        frames: &Frames<B>,
+        subpass: gfx_hal::pass::Subpass<'_, B>,
+        index: usize,
+        aux: &T,
+    ) -> PrepareResult {
+        self.destroy_retired(factory, frames);
+        self.reload(factory, frames, subpass, aux);
+        let result = self
+            .pipeline
+            .prepare(factory, &self.set_layouts, index, aux);
+
+        // Commands recorded before reload bind retired pipeline.
+        if self.take_stale(frames, index) {
+            PrepareResult::DrawRecord
+        } else {
+            result
+        }
     }
 
     fn draw_inline(&mut self, mut encoder: RenderPassEncoder<'_, B>, index: usize, aux: &T) {
@@ -359,6 +492,9 @@ where
             factory
                 .device()
                 .destroy_graphics_pipeline(self.graphics_pipeline);
+            for pipeline in self.retired {
+                factory.device().destroy_graphics_pipeline(pipeline);
+            }
             factory
                 .device()
                 .destroy_pipeline_layout(self.pipeline_layout);
@@ -369,6 +505,60 @@ where
     }
 }
 
+fn create_graphics_pipeline<B: Backend>(
+    factory: &Factory<B>,
+    shader_set: gfx_hal::pso::GraphicsShaderSet<'_, B>,
+    pipeline: &Pipeline,
+    pipeline_layout: &B::PipelineLayout,
+    subpass: gfx_hal::pass::Subpass<'_, B>,
+    rect: gfx_hal::pso::Rect,
+) -> Result<B::GraphicsPipeline, failure::Error> {
+    let mut vertex_buffers = Vec::new();
+    let mut attributes = Vec::new();
+
+    for &(ref elemets, stride, rate) in &pipeline.vertices {
+        push_vertex_desc(elemets, stride, rate, &mut vertex_buffers, &mut attributes);
+    }
+
+    let graphics_pipeline = unsafe {
+        factory.device().create_graphics_pipelines(
+            Some(gfx_hal::pso::GraphicsPipelineDesc {
+                shaders: shader_set,
+                rasterizer: gfx_hal::pso::Rasterizer::FILL,
+                vertex_buffers,
+                attributes,
+                input_assembler: gfx_hal::pso::InputAssemblerDesc {
+                    primitive: gfx_hal::Primitive::TriangleList,
+                    primitive_restart: gfx_hal::pso::PrimitiveRestart::Disabled,
+                },
+                blender: gfx_hal::pso::BlendDesc {
+                    logic_op: None,
+                    targets: pipeline.colors.clone(),
+                },
+                depth_stencil: pipeline.depth_stencil,
+                multisampling: None,
+                baked_states: gfx_hal::pso::BakedStates {
+                    viewport: Some(gfx_hal::pso::Viewport {
+                        rect,
+                        depth: 0.0..1.0,
+                    }),
+                    scissor: Some(rect),
+                    blend_color: None,
+                    depth_bounds: None,
+                },
+                layout: pipeline_layout,
+                subpass,
+                flags: gfx_hal::pso::PipelineCreationFlags::empty(),
+                parent: gfx_hal::pso::BasePipeline::None,
+            }),
+            None,
+        )
+    }
+    .remove(0)?;
+
+    Ok(graphics_pipeline)
+}
+
 fn push_vertex_desc(
     elements: &[gfx_hal::pso::Element<gfx_hal::format::Format>],
     stride: gfx_hal::pso::ElemStride,
@@ -20,7 +20,7 @@ pub enum PrepareResult {
 }
 
 impl PrepareResult {
-    fn force_record(&self) -> bool {
+    pub(crate) fn force_record(&self) -> bool {
         match self {
             PrepareResult::DrawRecord => true,
             PrepareResult::DrawReuse => false,
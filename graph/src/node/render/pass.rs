@@ -2,18 +2,21 @@ use {
     crate::{
         command::{
             CommandBuffer, CommandPool, ExecutableState, Family, FamilyId, Fence, Graphics,
-            IndividualReset, MultiShot, NoSimultaneousUse, PendingState, QueueId, SecondaryLevel,
-            SimultaneousUse, Submission, Submit, Supports,
+            IndividualReset, MultiShot, NoSimultaneousUse, PendingState, QueueId,
+            RenderPassContinue, RenderPassInlineEncoder, RenderPassSecondaryEncoder,
+            SecondaryLevel, SimultaneousUse, Submission, Submit, Supports,
         },
         factory::Factory,
         frame::{
             cirque::{CirqueRef, CommandCirque},
             Frames,
         },
+        graph::{RecordedStatistics, StatisticsQuery},
         node::{
             gfx_acquire_barriers, gfx_release_barriers,
             render::group::{RenderGroup, RenderGroupBuilder},
-            BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuilder, NodeImage,
+            BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuilder, NodeImage, NodeSubmit,
+            NodeSubmits,
         },
         BufferId, ImageId, NodeId,
     },
@@ -29,6 +32,7 @@ pub struct SubpassBuilder<B: Backend, T: ?Sized> {
     colors: Vec<ImageId>,
     depth_stencil: Option<ImageId>,
     dependencies: Vec<NodeId>,
+    secondary: bool,
 }
 
 impl<B, T> SubpassBuilder<B, T>
@@ -100,6 +104,22 @@ where
         self
     }
 
+    /// Record render groups of this subpass into secondary command buffers.
+    /// Each group gets its own command pool and buffers,
+    /// which are kept between frames while group returns `PrepareResult::DrawReuse`.
+    pub fn set_secondary(&mut self) -> &mut Self {
+        self.secondary = true;
+        self
+    }
+
+    /// Record render groups of this subpass into secondary command buffers.
+    /// Each group gets its own command pool and buffers,
+    /// which are kept between frames while group returns `PrepareResult::DrawReuse`.
+    pub fn with_secondary(mut self) -> Self {
+        self.set_secondary();
+        self
+    }
+
     /// Make render pass from subpass.
     pub fn into_pass(self) -> RenderPassNodeBuilder<B, T> {
         RenderPassNodeBuilder::new().with_subpass(self)
@@ -110,6 +130,8 @@ where
 #[derivative(Default(bound = ""), Debug(bound = ""))]
 pub struct RenderPassNodeBuilder<B: Backend, T: ?Sized> {
     subpasses: Vec<SubpassBuilder<B, T>>,
+    #[derivative(Debug = "ignore")]
+    record_secondary: Option<RecordSecondary<B, T>>,
 }
 
 impl<B, T> RenderPassNodeBuilder<B, T>
@@ -131,6 +153,28 @@ where
         self.add_subpass(subpass);
         self
     }
+
+    /// Record secondary command buffers of render groups in parallel.
+    /// Affects only subpasses that use secondary command buffers.
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel_secondary(&mut self) -> &mut Self
+    where
+        T: Sync,
+    {
+        self.record_secondary = Some(record_secondary_parallel::<B, T>);
+        self
+    }
+
+    /// Record secondary command buffers of render groups in parallel.
+    /// Affects only subpasses that use secondary command buffers.
+    #[cfg(feature = "parallel")]
+    pub fn with_parallel_secondary(mut self) -> Self
+    where
+        T: Sync,
+    {
+        self.set_parallel_secondary();
+        self
+    }
 }
 
 impl<B, T> NodeBuilder<B, T> for RenderPassNodeBuilder<B, T>
@@ -138,6 +182,15 @@ where
     B: Backend,
     T: ?Sized + 'static,
 {
+    fn name(&self) -> String {
+        let names: Vec<_> = self
+            .subpasses
+            .iter()
+            .flat_map(|subpass| subpass.groups.iter().map(|group| group.name()))
+            .collect();
+        names.join(", ")
+    }
+
     fn family(&self, families: &[Family<B>]) -> Option<FamilyId> {
         families
             .iter()
@@ -488,6 +541,28 @@ where
                 let subpass_colors = subpass.colors.len();
                 let subpass_depth = subpass.depth_stencil.is_some();
 
+                let secondary = if subpass.secondary {
+                    Some(
+                        subpass
+                            .groups
+                            .iter()
+                            .map(|_| {
+                                Ok(SecondaryCommands {
+                                    pool: factory
+                                        .create_command_pool(family)?
+                                        .with_capability()
+                                        .expect(
+                                            "Graph must specify family that supports `Graphics`",
+                                        ),
+                                    buffers: Vec::new(),
+                                })
+                            })
+                            .collect::<Result<Vec<_>, failure::Error>>()?,
+                    )
+                } else {
+                    None
+                };
+
                 subpass
                     .groups
                     .into_iter()
@@ -533,7 +608,7 @@ where
                         )
                     })
                     .collect::<Result<Vec<_>, _>>()
-                    .map(|groups| SubpassNode { groups })
+                    .map(|groups| SubpassNode { groups, secondary })
             })
             .collect::<Result<Vec<_>, _>>()?;
 
@@ -554,6 +629,9 @@ where
 
             acquire,
             release,
+            statistics: RecordedStatistics::default(),
+
+            record_secondary: self.record_secondary.unwrap_or(record_secondary::<B, T>),
 
             relevant: relevant::Relevant,
         };
@@ -568,6 +646,198 @@ where
 struct SubpassNode<B: Backend, T: ?Sized> {
     /// RenderGroups of pipelines to exeucte withing subpass.
     groups: Vec<Box<dyn RenderGroup<B, T>>>,
+
+    /// Secondary commands for each group
+    /// if subpass is recorded with secondary command buffers.
+    secondary: Option<Vec<SecondaryCommands<B>>>,
+}
+
+type SecondarySubmit<B> = Submit<B, NoSimultaneousUse, SecondaryLevel, RenderPassContinue>;
+
+type RecordSecondary<B, T> = for<'a> fn(
+    Vec<SecondaryRecord<'a, B, T>>,
+    &'a <B as Backend>::RenderPass,
+    usize,
+    &'a <B as Backend>::Framebuffer,
+    usize,
+    &'a T,
+) -> Vec<SecondarySubmit<B>>;
+
+/// Secondary command buffers of one render group.
+#[derive(derivative::Derivative)]
+#[derivative(Debug(bound = ""))]
+struct SecondaryCommands<B: Backend> {
+    pool: CommandPool<B, Graphics, IndividualReset>,
+
+    /// Buffers indexed by primary buffer they are executed from.
+    /// Buffer is complete whenever primary buffer with the same index is.
+    buffers: Vec<
+        Option<
+            CommandBuffer<
+                B,
+                Graphics,
+                PendingState<ExecutableState<MultiShot, RenderPassContinue>>,
+                SecondaryLevel,
+                IndividualReset,
+            >,
+        >,
+    >,
+}
+
+impl<B> SecondaryCommands<B>
+where
+    B: Backend,
+{
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        let mut pool = self.pool;
+        pool.free_buffers(
+            self.buffers
+                .into_iter()
+                .filter_map(|buffer| buffer)
+                .map(|buffer| buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(pool.with_queue_type());
+    }
+}
+
+/// Render group with its secondary commands.
+struct SecondaryRecord<'a, B: Backend, T: ?Sized> {
+    group: &'a mut Box<dyn RenderGroup<B, T>>,
+    commands: &'a mut SecondaryCommands<B>,
+    force: bool,
+}
+
+impl<'a, B, T> SecondaryRecord<'a, B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    /// Get secondary buffer for primary buffer with specified index.
+    /// Buffer is re-recorded only if forced or new.
+    fn record(
+        self,
+        render_pass: &B::RenderPass,
+        subpass: usize,
+        framebuffer: &B::Framebuffer,
+        index: usize,
+        aux: &T,
+    ) -> SecondarySubmit<B> {
+        let SecondaryRecord {
+            group,
+            commands,
+            force,
+        } = self;
+
+        while commands.buffers.len() <= index {
+            commands.buffers.push(None);
+        }
+
+        let initial = match commands.buffers[index].take() {
+            Some(pending) => {
+                // Primary buffer with the same index is complete.
+                let executable = unsafe { pending.mark_complete() };
+                if force {
+                    Err(executable.reset())
+                } else {
+                    Ok(executable)
+                }
+            }
+            None => Err(commands
+                .pool
+                .allocate_buffers::<SecondaryLevel>(1)
+                .pop()
+                .unwrap()),
+        };
+
+        let executable = match initial {
+            Ok(executable) => executable,
+            Err(initial) => {
+                let mut recording = initial.begin(
+                    MultiShot(NoSimultaneousUse),
+                    (
+                        gfx_hal::pass::Subpass {
+                            index: subpass,
+                            main_pass: render_pass,
+                        },
+                        framebuffer,
+                    ),
+                );
+                group.draw_secondary(recording.render_pass_encoder(), index, aux);
+                recording.finish()
+            }
+        };
+
+        let (submit, pending) = executable.submit();
+        commands.buffers[index] = Some(pending);
+        submit
+    }
+}
+
+fn record_secondary<'a, B, T>(
+    records: Vec<SecondaryRecord<'a, B, T>>,
+    render_pass: &'a B::RenderPass,
+    subpass: usize,
+    framebuffer: &'a B::Framebuffer,
+    index: usize,
+    aux: &'a T,
+) -> Vec<SecondarySubmit<B>>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    records
+        .into_iter()
+        .map(|record| record.record(render_pass, subpass, framebuffer, index, aux))
+        .collect()
+}
+
+#[cfg(feature = "parallel")]
+fn record_secondary_parallel<'a, B, T>(
+    records: Vec<SecondaryRecord<'a, B, T>>,
+    render_pass: &'a B::RenderPass,
+    subpass: usize,
+    framebuffer: &'a B::Framebuffer,
+    index: usize,
+    aux: &'a T,
+) -> Vec<SecondarySubmit<B>>
+where
+    B: Backend,
+    T: Sync + ?Sized,
+{
+    use rayon::prelude::*;
+
+    records
+        .into_par_iter()
+        .map(|record| record.record(render_pass, subpass, framebuffer, index, aux))
+        .collect()
+}
+
+/// Render pass encoder for either kind of subpass contents.
+enum PassEncoder<'a, B: Backend> {
+    Inline(RenderPassInlineEncoder<'a, B>),
+    Secondary(RenderPassSecondaryEncoder<'a, B>),
+}
+
+impl<'a, B> PassEncoder<'a, B>
+where
+    B: Backend,
+{
+    fn next_subpass(self, secondary: bool) -> Self {
+        match (self, secondary) {
+            (PassEncoder::Inline(encoder), false) => {
+                PassEncoder::Inline(encoder.next_subpass_inline())
+            }
+            (PassEncoder::Inline(encoder), true) => {
+                PassEncoder::Secondary(encoder.next_subpass_secondary())
+            }
+            (PassEncoder::Secondary(encoder), false) => {
+                PassEncoder::Inline(encoder.next_subpass_inline())
+            }
+            (PassEncoder::Secondary(encoder), true) => {
+                PassEncoder::Secondary(encoder.next_subpass_secondary())
+            }
+        }
+    }
 }
 
 #[derive(derivative::Derivative)]
@@ -602,25 +872,25 @@ pub struct RenderPassNode<B: Backend, T: ?Sized> {
 
     acquire: Option<BarriersCommands<B>>,
     release: Option<BarriersCommands<B>>,
+    statistics: RecordedStatistics<B>,
+
+    #[derivative(Debug = "ignore")]
+    record_secondary: RecordSecondary<B, T>,
 
     relevant: relevant::Relevant,
 }
 
-impl<B, T> DynNode<B, T> for RenderPassNode<B, T>
+impl<B, T> RenderPassNode<B, T>
 where
     B: Backend,
     T: ?Sized,
 {
-    unsafe fn run<'a>(
+    fn encode(
         &mut self,
-        factory: &mut Factory<B>,
-        aux: &mut T,
+        factory: &Factory<B>,
+        aux: &T,
         frames: &Frames<B>,
-        qid: QueueId,
-        waits: &[(&'a B::Semaphore, gfx_hal::pso::PipelineStage)],
-        signals: &[&'a B::Semaphore],
-        fence: Option<&mut Fence<B>>,
-    ) {
+    ) -> Submit<B, NoSimultaneousUse> {
         let RenderPassNode {
             subpasses,
 
@@ -636,30 +906,82 @@ where
 
             acquire,
             release,
+            statistics,
+            record_secondary,
             ..
         } = self;
 
-        let submit = command_cirque.encode(frames.range(), command_pool, |mut cbuf| {
+        let frame = frames.next().index();
+        command_cirque.encode(frames.range(), command_pool, |mut cbuf| {
             let index = cbuf.index();
 
-            let force_record = subpasses.iter_mut().any(|subpass| {
-                subpass
-                    .groups
-                    .iter_mut()
-                    .any(|group| group.prepare(factory, index, aux).force_record())
-            });
+            let forces: Vec<Vec<bool>> = subpasses
+                .iter_mut()
+                .enumerate()
+                .map(|(subpass_index, subpass)| {
+                    subpass
+                        .groups
+                        .iter_mut()
+                        .map(|group| {
+                            group
+                                .prepare(
+                                    factory,
+                                    frames,
+                                    gfx_hal::pass::Subpass {
+                                        index: subpass_index,
+                                        main_pass: &*render_pass,
+                                    },
+                                    index,
+                                    aux,
+                                )
+                                .force_record()
+                        })
+                        .collect()
+                })
+                .collect();
+            let force_record = forces.iter().flatten().any(|&force| force)
+                || statistics.force_record(index, frame);
 
             if force_record {
                 cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
             }
 
             cbuf.or_init(|cbuf| {
+                let secondaries: Vec<Option<Vec<_>>> = subpasses
+                    .iter_mut()
+                    .zip(&forces)
+                    .enumerate()
+                    .map(|(subpass_index, (subpass, forces))| {
+                        let secondary = subpass.secondary.as_mut()?;
+                        let records = subpass
+                            .groups
+                            .iter_mut()
+                            .zip(secondary.iter_mut())
+                            .zip(forces)
+                            .map(|((group, commands), &force)| SecondaryRecord {
+                                group,
+                                commands,
+                                force,
+                            })
+                            .collect();
+                        Some(record_secondary(
+                            records,
+                            render_pass,
+                            subpass_index,
+                            framebuffer,
+                            index,
+                            aux,
+                        ))
+                    })
+                    .collect();
+
                 let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
                 let mut encoder = cbuf.encoder();
 
                 if let Some(barriers) = &acquire {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
+                statistics.begin(&mut encoder, index, frame);
 
                 let area = gfx_hal::pso::Rect {
                     x: 0,
@@ -668,25 +990,70 @@ where
                     h: *framebuffer_height as _,
                 };
 
-                let mut pass_encoder =
-                    { encoder.begin_render_pass_inline(&render_pass, &framebuffer, area, &clears) };
+                let mut pass_encoder = if secondaries.first().map_or(false, Option::is_some) {
+                    PassEncoder::Secondary(encoder.begin_render_pass_secondary(
+                        &render_pass,
+                        &framebuffer,
+                        area,
+                        &clears,
+                    ))
+                } else {
+                    PassEncoder::Inline(encoder.begin_render_pass_inline(
+                        &render_pass,
+                        &framebuffer,
+                        area,
+                        &clears,
+                    ))
+                };
 
-                subpasses.iter_mut().for_each(|subpass| {
-                    subpass
-                        .groups
-                        .iter_mut()
-                        .for_each(|group| group.draw_inline(pass_encoder.reborrow(), index, aux))
-                });
+                for (subpass_index, (subpass, secondary)) in
+                    subpasses.iter_mut().zip(secondaries).enumerate()
+                {
+                    if subpass_index > 0 {
+                        pass_encoder = pass_encoder.next_subpass(secondary.is_some());
+                    }
+
+                    match (&mut pass_encoder, secondary) {
+                        (PassEncoder::Inline(pass_encoder), None) => {
+                            subpass.groups.iter_mut().for_each(|group| {
+                                group.draw_inline(pass_encoder.reborrow(), index, aux)
+                            })
+                        }
+                        (PassEncoder::Secondary(pass_encoder), Some(submits)) => {
+                            pass_encoder.execute_commands(submits)
+                        }
+                        _ => unreachable!("Encoder matches subpass contents"),
+                    }
+                }
 
                 drop(pass_encoder);
+                statistics.end(&mut encoder, frame);
 
                 if let Some(barriers) = &release {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
                 }
                 cbuf.finish()
             })
-        });
+        })
+    }
+}
 
+impl<B, T> DynNode<B, T> for RenderPassNode<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    unsafe fn run<'a>(
+        &mut self,
+        factory: &mut Factory<B>,
+        aux: &mut T,
+        frames: &Frames<B>,
+        qid: QueueId,
+        waits: &[(&'a B::Semaphore, gfx_hal::pso::PipelineStage)],
+        signals: &[&'a B::Semaphore],
+        fence: Option<&mut Fence<B>>,
+    ) {
+        let submit = self.encode(factory, aux, frames);
         factory.family_mut(qid.family()).queues_mut()[qid.index()].submit(
             Some(
                 Submission::new()
@@ -698,12 +1065,30 @@ where
         )
     }
 
+    unsafe fn record(
+        &mut self,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &Frames<B>,
+    ) -> Option<NodeSubmits<B>> {
+        let submit = self.encode(factory, aux, frames);
+        Some(std::iter::once(NodeSubmit::new(submit)).collect())
+    }
+
+    fn set_statistics_query(&mut self, query: Option<StatisticsQuery<B>>) -> bool {
+        self.statistics.set(query);
+        true
+    }
+
     unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, aux: &mut T) {
         self.relevant.dispose();
         for subpass in self.subpasses {
             for group in subpass.groups {
                 group.dispose(factory, aux)
             }
+            for commands in subpass.secondary.into_iter().flatten() {
+                commands.dispose(factory);
+            }
         }
         let pool = &mut self.command_pool;
         self.command_cirque.dispose(|buffer| {
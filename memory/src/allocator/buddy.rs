@@ -0,0 +1,570 @@
+use std::{collections::BTreeSet, ops::Range, ptr::NonNull};
+
+use crate::{
+    allocator::{Allocator, Kind},
+    block::Block,
+    mapping::*,
+    memory::*,
+    util::*,
+    utilization::AllocatorUtilization,
+};
+
+/// Memory block allocated from `BuddyAllocator`
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
+pub struct BuddyBlock<B: gfx_hal::Backend> {
+    chunk: u32,
+    order: u32,
+    memory: *const Memory<B>,
+    ptr: Option<NonNull<u8>>,
+    range: Range<u64>,
+    #[derivative(Debug = "ignore")]
+    relevant: relevant::Relevant,
+}
+
+unsafe impl<B> Send for BuddyBlock<B> where B: gfx_hal::Backend {}
+unsafe impl<B> Sync for BuddyBlock<B> where B: gfx_hal::Backend {}
+
+impl<B> BuddyBlock<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn shared_memory(&self) -> &Memory<B> {
+        // Memory won't be freed until last block created from it deallocated.
+        unsafe { &*self.memory }
+    }
+
+    fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+
+    /// Get mapping for the range of the block.
+    /// Blocks allocated from host-visible memory are always mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        assert!(
+            range.start <= range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.shared_memory().host_visible() {
+            return Err(gfx_hal::mapping::Error::InvalidAccess);
+        }
+
+        if let Some(ptr) = self.ptr {
+            if let Some((ptr, range)) = mapped_sub_range(ptr, self.range.clone(), range) {
+                let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
+                Ok(mapping)
+            } else {
+                Err(gfx_hal::mapping::Error::OutOfBounds)
+            }
+        } else {
+            Err(gfx_hal::mapping::Error::MappingFailed)
+        }
+    }
+}
+
+impl<B> Block<B> for BuddyBlock<B>
+where
+    B: gfx_hal::Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.shared_memory().properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.shared_memory().raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &impl gfx_hal::Device<B>,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        self.persistent_map(range)
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &impl gfx_hal::Device<B>) {}
+}
+
+/// Config for `BuddyAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BuddyConfig {
+    /// Size of the smallest block.
+    /// All requests are rounded up to power of two multiple of this value.
+    /// Must be power of two.
+    pub min_block_size: u64,
+
+    /// Size of memory objects allocated from device.
+    /// Must be power of two multiple of `min_block_size`.
+    pub chunk_size: u64,
+}
+
+/// Buddy allocator.
+/// Splits memory objects into power of two sized blocks
+/// and merges freed blocks with their buddies.
+///
+/// This allocator suites best mid-sized allocations of varying size
+/// such as render targets and mesh pools.
+/// Have up to `size - 1` memory overhead for requests just above power of two.
+/// Memory objects are returned to the system when whole memory object become unused.
+#[derive(Debug)]
+pub struct BuddyAllocator<B: gfx_hal::Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of the smallest block.
+    min_block_size: u64,
+
+    /// Order of the whole chunk.
+    max_order: u32,
+
+    /// Memory objects allocated from device.
+    chunks: veclist::VecList<BuddyChunk<B>>,
+
+    /// Total chunks count.
+    total_chunks: u32,
+
+    /// Free blocks of each order.
+    /// Each block is identified by chunk index and offset in the chunk.
+    free: Vec<BTreeSet<(u32, u64)>>,
+
+    /// Number of blocks allocated by user.
+    blocks: u64,
+
+    /// Memory occupied by blocks allocated by user.
+    used: u64,
+}
+
+/// Memory object allocated from device.
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
+struct BuddyChunk<B: gfx_hal::Backend> {
+    #[derivative(Debug = "ignore")]
+    memory: Box<Memory<B>>,
+    ptr: Option<NonNull<u8>>,
+}
+
+unsafe impl<B> Send for BuddyChunk<B> where B: gfx_hal::Backend {}
+unsafe impl<B> Sync for BuddyChunk<B> where B: gfx_hal::Backend {}
+
+impl<B> BuddyAllocator<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Create new `BuddyAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `BuddyConfig` provided.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: BuddyConfig,
+    ) -> Self {
+        log::info!(
+            "Create new 'buddy' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+        assert!(
+            config.min_block_size.is_power_of_two(),
+            "Minimal block size must be power of two"
+        );
+        assert!(
+            config.chunk_size.is_power_of_two(),
+            "Chunk size must be power of two"
+        );
+        assert!(
+            config.chunk_size >= config.min_block_size,
+            "Chunk size can't be less than minimal block size"
+        );
+        if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
+            assert!(
+                fits_usize(config.chunk_size),
+                "Chunk size must fit usize for mapping"
+            );
+        }
+
+        let max_order = config.chunk_size.trailing_zeros() - config.min_block_size.trailing_zeros();
+
+        BuddyAllocator {
+            memory_type,
+            memory_properties,
+            min_block_size: config.min_block_size,
+            max_order,
+            chunks: Default::default(),
+            total_chunks: 0,
+            free: (0..=max_order).map(|_| BTreeSet::new()).collect(),
+            blocks: 0,
+            used: 0,
+        }
+    }
+
+    /// Maximum allocation size.
+    /// Bigger requests would occupy whole chunk and better be served by dedicated allocation.
+    pub fn max_allocation(&self) -> u64 {
+        self.chunk_size() / 2
+    }
+
+    /// Check if request can be served by the allocator.
+    /// Blocks are aligned to their size, so both `size` and `align` must fit in a chunk.
+    pub fn fits(&self, size: u64, align: u64) -> bool {
+        size.max(align) <= self.chunk_size()
+    }
+
+    /// Get amount of memory allocation would take from device.
+    /// Returns 0 if there is free block big enough.
+    pub fn allocation_size(&self, size: u64, align: u64) -> u64 {
+        let order = self.order(size.max(align));
+        if (order..=self.max_order).any(|order| !self.free[order as usize].is_empty()) {
+            0
+        } else {
+            self.chunk_size()
+        }
+    }
+
+    /// Get utilization of the allocator.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        let chunks = self.total_chunks as u64;
+        AllocatorUtilization::new(self.blocks, chunks, chunks * self.chunk_size(), self.used)
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(self) {
+        assert_eq!(
+            self.total_chunks, 0,
+            "Not all allocation from BuddyAllocator was freed"
+        );
+    }
+
+    fn chunk_size(&self) -> u64 {
+        self.block_size(self.max_order)
+    }
+
+    /// Get block size for the order.
+    fn block_size(&self, order: u32) -> u64 {
+        self.min_block_size << order
+    }
+
+    /// Get smallest order of block that can fit `size` bytes.
+    fn order(&self, size: u64) -> u32 {
+        let size = size.max(self.min_block_size).next_power_of_two();
+        size.trailing_zeros() - self.min_block_size.trailing_zeros()
+    }
+
+    /// Allocate memory object from device.
+    fn alloc_chunk(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+    ) -> Result<u32, gfx_hal::device::AllocationError> {
+        let size = self.chunk_size();
+        log::trace!("Allocate new chunk: size: {}", size);
+        let (memory, ptr) = unsafe {
+            // Valid memory type specified.
+            let raw = device.allocate_memory(self.memory_type, size)?;
+
+            let ptr = if self
+                .memory_properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+            {
+                log::trace!("Map new memory object");
+                match device.map_memory(&raw, 0..size) {
+                    Ok(ptr) => Some(NonNull::new_unchecked(ptr)),
+                    Err(gfx_hal::mapping::Error::OutOfMemory(error)) => {
+                        device.free_memory(raw);
+                        return Err(error.into());
+                    }
+                    Err(_) => panic!("Unexpected mapping failure"),
+                }
+            } else {
+                None
+            };
+            let memory = Memory::from_raw(raw, size, self.memory_properties);
+            (memory, ptr)
+        };
+
+        let index = self.chunks.push(BuddyChunk {
+            memory: Box::new(memory),
+            ptr,
+        });
+        assert!(fits_u32(index), "Number of chunks must fit in u32 limit");
+        self.total_chunks += 1;
+        Ok(index as u32)
+    }
+
+    /// Free memory object.
+    fn free_chunk(&mut self, device: &impl gfx_hal::Device<B>, index: u32) -> u64 {
+        let chunk = self.chunks.pop(index as usize).expect("Chunk must exist");
+        self.total_chunks -= 1;
+        log::trace!("Free chunk: {:#?}", chunk);
+        let size = chunk.memory.size();
+        unsafe {
+            if chunk.ptr.is_some() {
+                device.unmap_memory(chunk.memory.raw());
+            }
+            device.free_memory(chunk.memory.into_raw());
+        }
+        size
+    }
+}
+
+impl<B> Allocator<B> for BuddyAllocator<B>
+where
+    B: gfx_hal::Backend,
+{
+    type Block = BuddyBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Buddy
+    }
+
+    fn alloc(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        size: u64,
+        align: u64,
+    ) -> Result<(BuddyBlock<B>, u64), gfx_hal::device::AllocationError> {
+        if !self.fits(size, align) {
+            return Err(gfx_hal::device::OutOfMemory::OutOfDeviceMemory.into());
+        }
+
+        // Blocks are aligned to their size.
+        let order = self.order(size.max(align));
+
+        let found = (order..=self.max_order).find_map(|from| {
+            self.free[from as usize]
+                .iter()
+                .next()
+                .cloned()
+                .map(|block| (from, block))
+        });
+
+        let (mut from, (chunk, offset), allocated) = match found {
+            Some((from, block)) => {
+                self.free[from as usize].remove(&block);
+                (from, block, 0)
+            }
+            None => {
+                let chunk = self.alloc_chunk(device)?;
+                (self.max_order, (chunk, 0), self.chunk_size())
+            }
+        };
+
+        // Split until block of requested order left.
+        while from > order {
+            from -= 1;
+            let buddy = offset + self.block_size(from);
+            self.free[from as usize].insert((chunk, buddy));
+        }
+
+        let block_size = self.block_size(order);
+        let block_range = offset..offset + block_size;
+        let ref chunk_entry = self.chunks[chunk as usize];
+
+        self.blocks += 1;
+        self.used += block_size;
+
+        Ok((
+            BuddyBlock {
+                chunk,
+                order,
+                memory: &*chunk_entry.memory,
+                ptr: chunk_entry.ptr.map(|ptr| {
+                    mapped_fitting_range(ptr, 0..chunk_entry.memory.size(), block_range.clone())
+                        .expect("Block must be in sub-range of chunk")
+                }),
+                range: block_range,
+                relevant: relevant::Relevant,
+            },
+            allocated,
+        ))
+    }
+
+    fn free(&mut self, device: &impl gfx_hal::Device<B>, block: BuddyBlock<B>) -> u64 {
+        log::trace!("Free block: {:#?}", block);
+        let chunk = block.chunk;
+        let mut order = block.order;
+        let mut offset = block.range.start;
+        self.blocks -= 1;
+        self.used -= block.size();
+        block.dispose();
+
+        // Merge with free buddies.
+        while order < self.max_order {
+            let buddy = offset ^ self.block_size(order);
+            if !self.free[order as usize].remove(&(chunk, buddy)) {
+                break;
+            }
+            offset = offset.min(buddy);
+            order += 1;
+        }
+
+        if order == self.max_order {
+            self.free_chunk(device, chunk)
+        } else {
+            self.free[order as usize].insert((chunk, offset));
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::allocator::mock::MockDevice, gfx_backend_empty::Backend};
+
+    const MIN_BLOCK: u64 = 256;
+    const CHUNK: u64 = 4096;
+
+    fn allocator() -> BuddyAllocator<Backend> {
+        BuddyAllocator::new(
+            gfx_hal::MemoryTypeId(0),
+            gfx_hal::memory::Properties::DEVICE_LOCAL,
+            BuddyConfig {
+                min_block_size: MIN_BLOCK,
+                chunk_size: CHUNK,
+            },
+        )
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+
+        let (first, allocated) = allocator.alloc(&device, MIN_BLOCK, 1).unwrap();
+        assert_eq!(allocated, CHUNK);
+        assert_eq!(first.range(), 0..MIN_BLOCK);
+
+        // Buddies of every order are left free after the split.
+        for order in 0..allocator.max_order {
+            assert_eq!(allocator.free[order as usize].len(), 1);
+        }
+
+        let (second, allocated) = allocator.alloc(&device, MIN_BLOCK, 1).unwrap();
+        assert_eq!(allocated, 0);
+        assert_eq!(second.range(), MIN_BLOCK..2 * MIN_BLOCK);
+        assert!(allocator.free[0].is_empty());
+
+        let (third, allocated) = allocator.alloc(&device, 3 * MIN_BLOCK, 1).unwrap();
+        assert_eq!(allocated, 0);
+        assert_eq!(third.range(), 4 * MIN_BLOCK..8 * MIN_BLOCK);
+
+        assert_eq!(allocator.free(&device, second), 0);
+        assert_eq!(allocator.free[0].len(), 1);
+        assert_eq!(allocator.free(&device, first), 0);
+        assert!(allocator.free[0].is_empty());
+        assert_eq!(allocator.free(&device, third), CHUNK);
+
+        assert_eq!(device.allocations(), 1);
+        assert_eq!(device.live(), 0);
+        assert!(allocator.free.iter().all(BTreeSet::is_empty));
+        allocator.dispose();
+    }
+
+    #[test]
+    fn free_in_any_order() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+        let count = (CHUNK / MIN_BLOCK) as usize;
+
+        let mut blocks: Vec<_> = (0..count)
+            .map(|_| allocator.alloc(&device, MIN_BLOCK, 1).unwrap().0)
+            .map(Some)
+            .collect();
+        assert_eq!(device.allocations(), 1);
+
+        let mut ranges: Vec<_> = blocks
+            .iter()
+            .map(|block| block.as_ref().unwrap().range())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "Blocks must not overlap");
+        }
+
+        // 7 is coprime with 16 so every block is visited once.
+        for step in 0..count {
+            let index = step * 7 % count;
+            let freed = allocator.free(&device, blocks[index].take().unwrap());
+            if step + 1 == count {
+                assert_eq!(freed, CHUNK);
+            } else {
+                assert_eq!(freed, 0);
+                assert_eq!(device.live(), 1);
+            }
+        }
+
+        assert_eq!(device.live(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn alignment_above_min_block() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+
+        let (small, _) = allocator.alloc(&device, MIN_BLOCK, 1).unwrap();
+        let (aligned, allocated) = allocator.alloc(&device, MIN_BLOCK, 1024).unwrap();
+        assert_eq!(allocated, 0);
+        assert_eq!(aligned.range().start % 1024, 0);
+        assert!(aligned.range().end - aligned.range().start >= 1024);
+        assert!(small.range().end <= aligned.range().start);
+
+        allocator.free(&device, aligned);
+        allocator.free(&device, small);
+        assert_eq!(device.live(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn request_above_chunk_fails() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+
+        assert!(!allocator.fits(MIN_BLOCK, 2 * CHUNK));
+        assert!(allocator.alloc(&device, MIN_BLOCK, 2 * CHUNK).is_err());
+        assert!(allocator.alloc(&device, 2 * CHUNK, 1).is_err());
+        assert_eq!(device.allocations(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn chunks_released_when_empty() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+        let half = allocator.max_allocation();
+
+        let blocks: Vec<_> = (0..4)
+            .map(|_| allocator.alloc(&device, half, 1).unwrap().0)
+            .collect();
+        assert_eq!(device.allocations(), 2);
+        assert_eq!(allocator.utilization().chunks, 2);
+
+        let freed: u64 = blocks
+            .into_iter()
+            .map(|block| allocator.free(&device, block))
+            .sum();
+        assert_eq!(freed, 2 * CHUNK);
+        assert_eq!(device.frees(), 2);
+        assert_eq!(allocator.total_chunks, 0);
+        allocator.dispose();
+    }
+}
@@ -5,6 +5,7 @@ use crate::{
     block::Block,
     mapping::{mapped_fitting_range, MappedRange},
     memory::*,
+    utilization::AllocatorUtilization,
 };
 
 /// Memory block allocated from `DedicatedAllocator`
@@ -12,6 +13,7 @@ use crate::{
 pub struct DedicatedBlock<B: gfx_hal::Backend> {
     memory: Memory<B>,
     mapping: Option<(NonNull<u8>, Range<u64>)>,
+    persistent: bool,
 }
 
 unsafe impl<B> Send for DedicatedBlock<B> where B: gfx_hal::Backend {}
@@ -33,8 +35,40 @@ where
         DedicatedBlock {
             memory,
             mapping: None,
+            persistent: false,
         }
     }
+
+    /// Map whole memory and keep it mapped until block is freed.
+    pub fn map_persistent(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+    ) -> Result<(), gfx_hal::mapping::Error> {
+        let size = self.memory.size();
+        self.map(device, 0..size)?;
+        self.persistent = true;
+        Ok(())
+    }
+
+    /// Get mapping for the range of persistently mapped block.
+    /// Fails with `MappingFailed` if the block isn't persistently mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        assert!(
+            range.start <= range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.persistent {
+            return Err(gfx_hal::mapping::Error::MappingFailed);
+        }
+
+        let (ptr, mapping_range) = self.mapping.clone().expect("Persistent block is mapped");
+        let ptr = mapped_fitting_range(ptr, mapping_range, range.clone())
+            .ok_or(gfx_hal::mapping::Error::OutOfBounds)?;
+        Ok(unsafe { MappedRange::from_raw(&self.memory, ptr, range) })
+    }
 }
 
 impl<B> Block<B> for DedicatedBlock<B>
@@ -87,6 +121,11 @@ where
     }
 
     fn unmap(&mut self, device: &impl gfx_hal::Device<B>) {
+        if self.persistent {
+            // Stays mapped until freed.
+            return;
+        }
+
         if self.mapping.take().is_some() {
             unsafe {
                 // trace!("Unmap memory: {:#?}", self.memory);
@@ -108,6 +147,7 @@ where
 pub struct DedicatedAllocator {
     memory_type: gfx_hal::MemoryTypeId,
     memory_properties: gfx_hal::memory::Properties,
+    blocks: u64,
     used: u64,
 }
 
@@ -126,9 +166,16 @@ impl DedicatedAllocator {
         DedicatedAllocator {
             memory_type,
             memory_properties,
+            blocks: 0,
             used: 0,
         }
     }
+
+    /// Get utilization of the allocator.
+    /// Each block occupies whole memory object.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization::new(self.blocks, self.blocks, self.used, self.used)
+    }
 }
 
 impl<B> Allocator<B> for DedicatedAllocator
@@ -156,6 +203,7 @@ where
             )
         };
 
+        self.blocks += 1;
         self.used += size;
 
         Ok((DedicatedBlock::from_memory(memory), size))
@@ -163,8 +211,10 @@ where
 
     #[inline]
     fn free(&mut self, device: &impl gfx_hal::Device<B>, mut block: DedicatedBlock<B>) -> u64 {
+        block.persistent = false;
         block.unmap(device);
         let size = block.memory.size();
+        self.blocks -= 1;
         self.used -= size;
         unsafe {
             device.free_memory(block.memory.into_raw());
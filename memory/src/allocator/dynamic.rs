@@ -6,6 +6,7 @@ use crate::{
     mapping::*,
     memory::*,
     util::*,
+    utilization::AllocatorUtilization,
 };
 
 /// Memory block allocated from `DynamicAllocator`
@@ -40,6 +41,32 @@ where
     fn dispose(self) {
         self.relevant.dispose();
     }
+
+    /// Get mapping for the range of the block.
+    /// Blocks allocated from host-visible memory are always mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        assert!(
+            range.start <= range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.shared_memory().host_visible() {
+            return Err(gfx_hal::mapping::Error::InvalidAccess);
+        }
+
+        if let Some(ptr) = self.ptr {
+            if let Some((ptr, range)) = mapped_sub_range(ptr, self.range.clone(), range) {
+                let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
+                Ok(mapping)
+            } else {
+                Err(gfx_hal::mapping::Error::OutOfBounds)
+            }
+        } else {
+            Err(gfx_hal::mapping::Error::MappingFailed)
+        }
+    }
 }
 
 impl<B> Block<B> for DynamicBlock<B>
@@ -67,24 +94,7 @@ where
         _device: &impl gfx_hal::Device<B>,
         range: Range<u64>,
     ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
-        assert!(
-            range.start <= range.end,
-            "Memory mapping region must have valid size"
-        );
-        if !self.shared_memory().host_visible() {
-            return Err(gfx_hal::mapping::Error::InvalidAccess);
-        }
-
-        if let Some(ptr) = self.ptr {
-            if let Some((ptr, range)) = mapped_sub_range(ptr, self.range.clone(), range) {
-                let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
-                Ok(mapping)
-            } else {
-                Err(gfx_hal::mapping::Error::OutOfBounds)
-            }
-        } else {
-            Err(gfx_hal::mapping::Error::MappingFailed)
-        }
+        self.persistent_map(range)
     }
 
     #[inline]
@@ -105,13 +115,19 @@ pub struct DynamicConfig {
     /// Maximum block size.
     /// For any request larger than this won't be allocated with this allocator.
     pub max_block_size: u64,
+
+    /// Maximum size of memory object allocated from device.
+    /// Requests are limited to `max_chunk_size / blocks_per_chunk`,
+    /// so that chunks of big blocks don't take huge memory objects.
+    pub max_chunk_size: u64,
 }
 
 /// Low-fragmentation allocator.
 /// Suitable for any type of small allocations.
 /// Have up to `block_size_granularity - 1` memory overhead.
 /// Every freed block can be recycled independently.
-/// Memory objects can be returned to the system if whole memory object become unused (not implemented yet).
+/// Memory objects are returned to the system when whole memory object become unused.
+/// Blocks from sparse chunks can be relocated to denser chunks. See `plan_defragmentation`.
 #[derive(Debug)]
 pub struct DynamicAllocator<B: gfx_hal::Backend> {
     /// Memory type that this allocator allocates.
@@ -126,9 +142,27 @@ pub struct DynamicAllocator<B: gfx_hal::Backend> {
     /// All requests are rounded up to multiple of this value.
     block_size_granularity: u64,
 
+    /// Maximum block size.
+    max_block_size: u64,
+
+    /// Maximum size of memory object allocated from device.
+    max_chunk_size: u64,
+
     /// List of chunk lists.
     /// Each index corresponds to `block_size_granularity * index` size.
     sizes: HashMap<usize, Size<B>>,
+
+    /// Number of blocks allocated by user.
+    blocks: u64,
+
+    /// Memory occupied by blocks allocated by user.
+    used: u64,
+
+    /// Number of memory objects allocated from device.
+    chunks: u64,
+
+    /// Memory allocated from device.
+    allocated: u64,
 }
 
 /// List of chunks
@@ -142,6 +176,17 @@ struct Size<B: gfx_hal::Backend> {
 
     /// Bits per free blocks.
     blocks: hibitset::BitSet,
+
+    /// Bits per existing chunks.
+    chunk_indices: hibitset::BitSet,
+
+    /// Bits per blocks used as chunks for smaller blocks.
+    /// Those blocks can't be relocated.
+    pinned: hibitset::BitSet,
+
+    /// Chunks being evacuated with bits of blocks still living in them.
+    /// Blocks are not allocated from those chunks.
+    evacuating: HashMap<u32, usize>,
 }
 
 impl<B> Default for Size<B>
@@ -153,6 +198,9 @@ where
             chunks: Default::default(),
             total_chunks: 0,
             blocks: Default::default(),
+            chunk_indices: Default::default(),
+            pinned: Default::default(),
+            evacuating: HashMap::new(),
         }
     }
 }
@@ -162,8 +210,13 @@ where
     B: gfx_hal::Backend,
 {
     /// Maximum allocation size.
+    /// Chunk for blocks of this size fits `max_chunk_size`.
     pub fn max_allocation(&self) -> u64 {
-        self.max_block_size()
+        let max_chunk_block = self.max_chunk_size / self.blocks_per_chunk as u64;
+        std::cmp::min(
+            self.max_block_size(),
+            max_chunk_block - max_chunk_block % self.block_size_granularity,
+        )
     }
 
     /// Create new `LinearAllocator`
@@ -192,6 +245,7 @@ where
             .max_block_size
             .checked_mul(config.blocks_per_chunk.into())
             .expect("Max chunk size must fit u64 to allocate it from Vulkan");
+        let max_chunk_size = std::cmp::min(max_chunk_size, config.max_chunk_size);
         if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
             assert!(
                 fits_usize(max_chunk_size),
@@ -208,15 +262,65 @@ where
             memory_type,
             memory_properties,
             block_size_granularity: config.block_size_granularity,
+            max_block_size: config.max_block_size,
+            max_chunk_size: config.max_chunk_size,
             blocks_per_chunk: config.blocks_per_chunk,
             sizes: HashMap::new(),
+            blocks: 0,
+            used: 0,
+            chunks: 0,
+            allocated: 0,
         }
     }
 
+    /// Get amount of memory allocation would take from device.
+    /// Returns 0 if request can be served from chunks allocated already.
+    pub fn allocation_size(&self, size: u64, align: u64) -> u64 {
+        self.block_allocation_size(std::cmp::max(size, align))
+    }
+
+    /// Get amount of memory allocation of the block would take from device.
+    /// Mirrors `alloc_from_chunk` and `alloc_chunk`.
+    fn block_allocation_size(&self, size: u64) -> u64 {
+        let has_free = self
+            .sizes
+            .get(&self.size_index(size))
+            .map_or(false, |size_entry| {
+                hibitset::BitSetLike::iter(&size_entry.blocks)
+                    .next()
+                    .is_some()
+            });
+        if has_free {
+            return 0;
+        }
+
+        let chunk_size = size * self.blocks_per_chunk as u64;
+        if self.chunk_from_device(chunk_size) {
+            chunk_size
+        } else {
+            self.block_allocation_size(chunk_size)
+        }
+    }
+
+    /// Check if chunk must be allocated from device.
+    /// Otherwise it is allocated as a block of bigger size,
+    /// which is only done while chunk of that block fits `max_chunk_size`.
+    fn chunk_from_device(&self, chunk_size: u64) -> bool {
+        chunk_size > self.max_block_size()
+            || chunk_size * self.blocks_per_chunk as u64 > self.max_chunk_size
+    }
+
+    /// Get utilization of the allocator.
+    /// Chunks sub-allocated from chunks of bigger blocks are not counted
+    /// as they don't consume memory from device.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        AllocatorUtilization::new(self.blocks, self.chunks, self.allocated, self.used)
+    }
+
     /// Maximum block size.
     /// Any request bigger will result in panic.
     pub fn max_block_size(&self) -> u64 {
-        (self.block_size_granularity * self.sizes.len() as u64)
+        self.max_block_size
     }
 
     fn max_chunks_per_size(&self) -> u32 {
@@ -242,7 +346,7 @@ where
         size: u64,
     ) -> Result<(Chunk<B>, u64), gfx_hal::device::AllocationError> {
         log::trace!("Allocate new chunk: size: {}", size);
-        if size > self.max_block_size() {
+        if self.chunk_from_device(size) {
             // Allocate from device.
             let (memory, mapping) = unsafe {
                 // Valid memory type specified.
@@ -267,10 +371,18 @@ where
                 let memory = Memory::from_raw(raw, size, self.memory_properties);
                 (memory, mapping)
             };
+            self.chunks += 1;
+            self.allocated += size;
             Ok((Chunk::Dedicated(Box::new(memory), mapping), size))
         } else {
             // Allocate from larger chunks.
             let (dynamic_block, allocated) = self.alloc_from_chunk(device, size)?;
+            let size_index = self.size_index(dynamic_block.size());
+            self.sizes
+                .entry(size_index)
+                .or_default()
+                .pinned
+                .add(dynamic_block.index);
             Ok((Chunk::Dynamic(dynamic_block), allocated))
         }
     }
@@ -292,9 +404,19 @@ where
                     }
                     device.free_memory(boxed.into_raw());
                 }
+                self.chunks -= 1;
+                self.allocated -= size;
                 size
             }
-            Chunk::Dynamic(dynamic_block) => self.free(device, dynamic_block),
+            Chunk::Dynamic(dynamic_block) => {
+                let size_index = self.size_index(dynamic_block.size());
+                self.sizes
+                    .entry(size_index)
+                    .or_default()
+                    .pinned
+                    .remove(dynamic_block.index);
+                self.free_block(device, dynamic_block)
+            }
         }
     }
 
@@ -328,6 +450,7 @@ where
                     let (chunk, allocated) = self.alloc_chunk(device, chunk_size)?;
                     let size_entry = self.sizes.entry(size_index).or_default();
                     let chunk_index = size_entry.chunks.push(chunk) as u32;
+                    size_entry.chunk_indices.add(chunk_index);
                     size_entry.total_chunks += 1;
                     let block_index_start = chunk_index * self.blocks_per_chunk;
                     let block_index_end = block_index_start + self.blocks_per_chunk;
@@ -338,28 +461,225 @@ where
                 }
             };
 
+        Ok((self.make_block(size_index, block_index), allocated))
+    }
+
+    /// Create block object for block index.
+    /// Block must be already taken from the free list.
+    fn make_block(&self, size_index: usize, block_index: u32) -> DynamicBlock<B> {
         let chunk_index = block_index / self.blocks_per_chunk;
 
         let block_size = self.block_size(size_index);
-        let ref chunk = self.sizes.entry(size_index).or_default().chunks[chunk_index as usize];
+        let ref chunk = self.sizes[&size_index].chunks[chunk_index as usize];
         let chunk_range = chunk.range();
         let block_offset =
             chunk_range.start + (block_index % self.blocks_per_chunk) as u64 * block_size;
         let block_range = block_offset..block_offset + block_size;
 
-        Ok((
-            DynamicBlock {
-                range: block_range.clone(),
-                memory: chunk.shared_memory(),
-                index: block_index,
-                ptr: chunk.ptr().map(|ptr| {
-                    mapped_fitting_range(ptr, chunk.range(), block_range)
-                        .expect("Block must be in sub-range of chunk")
-                }),
-                relevant: relevant::Relevant,
-            },
-            allocated,
-        ))
+        DynamicBlock {
+            range: block_range.clone(),
+            memory: chunk.shared_memory(),
+            index: block_index,
+            ptr: chunk.ptr().map(|ptr| {
+                mapped_fitting_range(ptr, chunk.range(), block_range)
+                    .expect("Block must be in sub-range of chunk")
+            }),
+            relevant: relevant::Relevant,
+        }
+    }
+
+    /// Return block to the chunk it was allocated from.
+    /// Frees the chunk if all its blocks are free.
+    fn free_block(&mut self, device: &impl gfx_hal::Device<B>, block: DynamicBlock<B>) -> u64 {
+        log::trace!("Free block: {:#?}", block);
+        let size_index = self.size_index(block.size());
+        let block_index = block.index;
+        block.dispose();
+
+        let blocks_per_chunk = self.blocks_per_chunk;
+        let chunk_index = block_index / blocks_per_chunk;
+
+        let size_entry = self.sizes.entry(size_index).or_default();
+        if let Some(living) = size_entry.evacuating.get_mut(&chunk_index) {
+            // Blocks of evacuating chunk are never returned to the free list.
+            *living &= !(1usize << (block_index % blocks_per_chunk));
+            if *living != 0 {
+                return 0;
+            }
+            size_entry.evacuating.remove(&chunk_index);
+            return self.release_chunk(device, size_index, chunk_index);
+        }
+
+        let old = size_entry.blocks.add(block_index);
+        debug_assert!(!old);
+
+        let chunk_start = chunk_index * self.blocks_per_chunk;
+        let chunk_end = chunk_start + self.blocks_per_chunk;
+
+        if check_bit_range_set(
+            &self.sizes.entry(size_index).or_default().blocks,
+            chunk_start..chunk_end,
+        ) {
+            for index in chunk_start..chunk_end {
+                let old = self
+                    .sizes
+                    .entry(size_index)
+                    .or_default()
+                    .blocks
+                    .remove(index);
+                debug_assert!(old);
+            }
+            self.release_chunk(device, size_index, chunk_index)
+        } else {
+            0
+        }
+    }
+
+    /// Remove chunk from the list and free it.
+    /// Blocks of the chunk must be removed from free list.
+    fn release_chunk(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        size_index: usize,
+        chunk_index: u32,
+    ) -> u64 {
+        let size_entry = self.sizes.entry(size_index).or_default();
+        let chunk = size_entry
+            .chunks
+            .pop(chunk_index as usize)
+            .expect("Chunk must exist");
+        size_entry.chunk_indices.remove(chunk_index);
+        size_entry.total_chunks -= 1;
+        self.free_chunk(device, chunk)
+    }
+
+    /// Plan relocation of blocks from sparse chunks into free blocks of denser chunks
+    /// of the same size.
+    /// Chunk is evacuated only if all its blocks can be relocated.
+    ///
+    /// Target blocks are reserved and no new blocks are allocated from evacuated chunks
+    /// until each relocation is either committed with `commit_relocation`
+    /// or canceled with `cancel_relocation`.
+    /// Evacuated chunks are freed once all their blocks are relocated or freed.
+    pub fn plan_defragmentation(&mut self) -> Vec<DynamicRelocation<B>> {
+        let blocks_per_chunk = self.blocks_per_chunk;
+        let mut planned = Vec::new();
+
+        for (&size_index, size_entry) in self.sizes.iter_mut() {
+            let mut chunks = hibitset::BitSetLike::iter(&size_entry.chunk_indices)
+                .filter(|chunk_index| !size_entry.evacuating.contains_key(chunk_index))
+                .map(|chunk_index| DefragmentationChunk {
+                    index: chunk_index,
+                    free: hibitset::BitSetLike::layer0(&size_entry.blocks, chunk_index as usize),
+                    pinned: hibitset::BitSetLike::layer0(&size_entry.pinned, chunk_index as usize),
+                    target: false,
+                })
+                .collect::<Vec<_>>();
+
+            // Sparse chunks first.
+            chunks.sort_by_key(|chunk| chunk.living());
+
+            for index in 0..chunks.len() {
+                let (sources, targets) = chunks.split_at_mut(index + 1);
+                let source = &sources[index];
+                if source.target {
+                    // The rest of the chunks are filled.
+                    break;
+                }
+                if source.pinned != 0 {
+                    continue;
+                }
+                let capacity = targets
+                    .iter()
+                    .map(|chunk| chunk.free.count_ones())
+                    .sum::<u32>();
+                if capacity < source.living() {
+                    // The rest of the chunks are denser.
+                    break;
+                }
+
+                for bit in (0..blocks_per_chunk).filter(|bit| source.free & (1usize << bit) == 0) {
+                    // Densest chunks first.
+                    let target = targets
+                        .iter_mut()
+                        .rev()
+                        .find(|chunk| chunk.free != 0)
+                        .expect("Capacity checked above");
+                    let target_bit = target.free.trailing_zeros();
+                    target.free &= !(1usize << target_bit);
+                    target.target = true;
+
+                    let target_index = target.index * blocks_per_chunk + target_bit;
+                    assert!(size_entry.blocks.remove(target_index));
+                    planned.push((
+                        size_index,
+                        source.index * blocks_per_chunk + bit,
+                        target_index,
+                    ));
+                }
+
+                for bit in (0..blocks_per_chunk).filter(|bit| source.free & (1usize << bit) != 0) {
+                    assert!(size_entry
+                        .blocks
+                        .remove(source.index * blocks_per_chunk + bit));
+                }
+                size_entry.evacuating.insert(source.index, !source.free);
+            }
+        }
+
+        log::trace!("Planned {} relocations", planned.len());
+
+        planned
+            .into_iter()
+            .map(|(size_index, source, target)| DynamicRelocation {
+                source,
+                target: self.make_block(size_index, target),
+            })
+            .collect()
+    }
+
+    /// Commit relocation planned by `plan_defragmentation`.
+    /// Caller must copy content of the source block to the target block
+    /// and rebind resources before committing.
+    /// Returns target block and amount of memory returned to the device.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `block` is not the source of the relocation.
+    pub fn commit_relocation(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        relocation: DynamicRelocation<B>,
+        block: DynamicBlock<B>,
+    ) -> (DynamicBlock<B>, u64) {
+        assert!(
+            relocation.is_source(&block),
+            "Block is not the source of the relocation"
+        );
+        let freed = self.free_block(device, block);
+        (relocation.target, freed)
+    }
+
+    /// Cancel relocation planned by `plan_defragmentation`.
+    /// Chunk of the source block won't be evacuated anymore.
+    /// Returns amount of memory returned to the device.
+    pub fn cancel_relocation(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        relocation: DynamicRelocation<B>,
+    ) -> u64 {
+        let blocks_per_chunk = self.blocks_per_chunk;
+        let size_index = self.size_index(relocation.target.size());
+        let chunk_index = relocation.source / blocks_per_chunk;
+
+        let size_entry = self.sizes.entry(size_index).or_default();
+        if let Some(living) = size_entry.evacuating.remove(&chunk_index) {
+            for bit in (0..blocks_per_chunk).filter(|bit| living & (1usize << bit) == 0) {
+                assert!(!size_entry.blocks.add(chunk_index * blocks_per_chunk + bit));
+            }
+        }
+
+        self.free_block(device, relocation.target)
     }
 
     /// Perform full cleanup of the memory allocated.
@@ -389,53 +709,56 @@ where
         use std::cmp::max;
         let size = max(size, align);
 
-        assert!(size <= self.max_block_size());
-        self.alloc_from_chunk(device, size)
+        assert!(size <= self.max_allocation());
+        let (block, allocated) = self.alloc_from_chunk(device, size)?;
+        self.blocks += 1;
+        self.used += block.size();
+        Ok((block, allocated))
     }
 
     fn free(&mut self, device: &impl gfx_hal::Device<B>, block: DynamicBlock<B>) -> u64 {
-        log::trace!("Free block: {:#?}", block);
-        let size_index = self.size_index(block.size());
-        let block_index = block.index;
-        block.dispose();
+        self.blocks -= 1;
+        self.used -= block.size();
+        self.free_block(device, block)
+    }
+}
 
-        let old = self
-            .sizes
-            .entry(size_index)
-            .or_default()
-            .blocks
-            .add(block_index);
-        debug_assert!(!old);
+/// Relocation of the `DynamicBlock` planned by `DynamicAllocator::plan_defragmentation`.
+/// Must be either committed or canceled.
+#[derive(Debug)]
+pub struct DynamicRelocation<B: gfx_hal::Backend> {
+    source: u32,
+    target: DynamicBlock<B>,
+}
 
-        let chunk_index = block_index / self.blocks_per_chunk;
-        let chunk_start = chunk_index * self.blocks_per_chunk;
-        let chunk_end = chunk_start + self.blocks_per_chunk;
+impl<B> DynamicRelocation<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Check if `block` is the block to be relocated.
+    pub fn is_source(&self, block: &DynamicBlock<B>) -> bool {
+        block.index == self.source && block.size() == self.target.size()
+    }
 
-        if check_bit_range_set(
-            &self.sizes.entry(size_index).or_default().blocks,
-            chunk_start..chunk_end,
-        ) {
-            for index in chunk_start..chunk_end {
-                let old = self
-                    .sizes
-                    .entry(size_index)
-                    .or_default()
-                    .blocks
-                    .remove(index);
-                debug_assert!(old);
-            }
-            let chunk = self
-                .sizes
-                .entry(size_index)
-                .or_default()
-                .chunks
-                .pop(chunk_index as usize)
-                .expect("Chunk must exist");
-            self.sizes.entry(size_index).or_default().total_chunks -= 1;
-            self.free_chunk(device, chunk)
-        } else {
-            0
-        }
+    /// Get block reserved for relocation.
+    /// Content of the source block must be copied here.
+    pub fn target(&self) -> &DynamicBlock<B> {
+        &self.target
+    }
+}
+
+/// Chunk state used while planning defragmentation.
+#[derive(Debug)]
+struct DefragmentationChunk {
+    index: u32,
+    free: usize,
+    pinned: usize,
+    target: bool,
+}
+
+impl DefragmentationChunk {
+    fn living(&self) -> u32 {
+        (!self.free).count_ones()
     }
 }
 
@@ -501,3 +824,171 @@ fn check_bit_range_set(bitset: &hibitset::BitSet, range: Range<u32>) -> bool {
 
     hibitset::BitSetLike::layer0(&bitset, (range.start / layer_size) as usize) == !0
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::allocator::mock::MockDevice, gfx_backend_empty::Backend};
+
+    const BLOCK: u64 = 256;
+
+    fn allocator() -> DynamicAllocator<Backend> {
+        DynamicAllocator::new(
+            gfx_hal::MemoryTypeId(0),
+            gfx_hal::memory::Properties::DEVICE_LOCAL,
+            DynamicConfig {
+                blocks_per_chunk: 64,
+                block_size_granularity: BLOCK,
+                max_block_size: 4 * BLOCK,
+                max_chunk_size: 1 << 30,
+            },
+        )
+    }
+
+    /// Fill two chunks with blocks.
+    /// Then free most blocks of the first chunk and few blocks of the second one.
+    /// Returns blocks left alive.
+    fn fragment(
+        device: &MockDevice,
+        allocator: &mut DynamicAllocator<Backend>,
+    ) -> Vec<DynamicBlock<Backend>> {
+        let count = allocator.blocks_per_chunk;
+        let blocks: Vec<_> = (0..2 * count)
+            .map(|_| allocator.alloc(device, BLOCK, 1).unwrap().0)
+            .collect();
+        assert_eq!(device.allocations(), 2);
+
+        let mut living = Vec::new();
+        for block in blocks {
+            let keep = if block.index < count {
+                block.index % 16 == 0
+            } else {
+                block.index % 8 != 0
+            };
+            if keep {
+                living.push(block);
+            } else {
+                assert_eq!(allocator.free(device, block), 0);
+            }
+        }
+        living
+    }
+
+    fn free_blocks(allocator: &DynamicAllocator<Backend>) -> Vec<u32> {
+        hibitset::BitSetLike::iter(&allocator.sizes[&0].blocks).collect()
+    }
+
+    #[test]
+    fn defragmentation_commit() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+        let count = allocator.blocks_per_chunk;
+        let chunk_size = BLOCK * count as u64;
+
+        let mut living = fragment(&device, &mut allocator);
+        let before = allocator.utilization();
+        let sparse = living.iter().filter(|block| block.index < count).count();
+
+        let relocations = allocator.plan_defragmentation();
+        assert_eq!(relocations.len(), sparse);
+
+        let mut freed = 0;
+        for relocation in relocations {
+            assert!(
+                relocation.target().index >= count,
+                "Blocks must be moved to the dense chunk"
+            );
+            let position = living
+                .iter()
+                .position(|block| relocation.is_source(block))
+                .expect("Source must be living block");
+            let block = living.swap_remove(position);
+            let (target, released) = allocator.commit_relocation(&device, relocation, block);
+            living.push(target);
+            freed += released;
+        }
+
+        // Sparse chunk is returned to the device.
+        assert_eq!(freed, chunk_size);
+        assert_eq!(device.frees(), 1);
+        assert_eq!(device.live(), 1);
+        assert!(living.iter().all(|block| block.index >= count));
+
+        let after = allocator.utilization();
+        assert_eq!(after.blocks, before.blocks);
+        assert_eq!(after.used, before.used);
+        assert_eq!(after.chunks, 1);
+        assert_eq!(after.allocated, chunk_size);
+
+        // Nothing is left to defragment.
+        assert!(allocator.plan_defragmentation().is_empty());
+
+        for block in living {
+            allocator.free(&device, block);
+        }
+        assert_eq!(device.live(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn defragmentation_cancel() {
+        let device = MockDevice::default();
+        let mut allocator = allocator();
+        let count = allocator.blocks_per_chunk;
+
+        let mut living = fragment(&device, &mut allocator);
+        let before = allocator.utilization();
+        let free_before = free_blocks(&allocator);
+
+        let relocations = allocator.plan_defragmentation();
+        assert!(!relocations.is_empty());
+        for relocation in relocations {
+            assert_eq!(allocator.cancel_relocation(&device, relocation), 0);
+        }
+
+        assert_eq!(allocator.utilization(), before);
+        assert_eq!(free_blocks(&allocator), free_before);
+        assert!(allocator.sizes[&0].evacuating.is_empty());
+        assert_eq!(device.frees(), 0);
+
+        // Free blocks of both chunks are reused.
+        let free = 2 * count as usize - living.len();
+        for _ in 0..free {
+            living.push(allocator.alloc(&device, BLOCK, 1).unwrap().0);
+        }
+        assert_eq!(device.allocations(), 2);
+
+        for block in living {
+            allocator.free(&device, block);
+        }
+        assert_eq!(device.live(), 0);
+        allocator.dispose();
+    }
+
+    #[test]
+    fn chunks_fit_max_chunk_size() {
+        let device = MockDevice::default();
+        let count = std::mem::size_of::<usize>() as u64 * 8;
+        let mut allocator = DynamicAllocator::<Backend>::new(
+            gfx_hal::MemoryTypeId(0),
+            gfx_hal::memory::Properties::DEVICE_LOCAL,
+            DynamicConfig {
+                blocks_per_chunk: 64,
+                block_size_granularity: BLOCK,
+                max_block_size: 4 * BLOCK,
+                max_chunk_size: 2 * BLOCK * count,
+            },
+        );
+
+        // Blocks are limited by chunk size rather than by `max_block_size`.
+        assert_eq!(allocator.max_allocation(), 2 * BLOCK);
+        assert_eq!(allocator.allocation_size(2 * BLOCK, 1), 2 * BLOCK * count);
+
+        let (block, allocated) = allocator.alloc(&device, 2 * BLOCK, 1).unwrap();
+        assert_eq!(allocated, 2 * BLOCK * count);
+        assert_eq!(device.allocations(), 1);
+
+        allocator.free(&device, block);
+        assert_eq!(device.live(), 0);
+        allocator.dispose();
+    }
+}
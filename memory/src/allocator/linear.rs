@@ -6,6 +6,7 @@ use crate::{
     mapping::*,
     memory::*,
     util::*,
+    utilization::AllocatorUtilization,
 };
 
 /// Memory block allocated from `LinearAllocator`
@@ -40,6 +41,28 @@ where
     fn dispose(self) {
         self.relevant.dispose();
     }
+
+    /// Get mapping for the range of the block.
+    /// Linear blocks are always mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        assert!(
+            range.start <= range.end,
+            "Memory mapping region must have valid size"
+        );
+        if !self.shared_memory().host_visible() {
+            return Err(gfx_hal::mapping::Error::InvalidAccess);
+        }
+
+        if let Some((ptr, range)) = mapped_sub_range(self.ptr, self.range.clone(), range) {
+            let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
+            Ok(mapping)
+        } else {
+            Err(gfx_hal::mapping::Error::OutOfBounds)
+        }
+    }
 }
 
 impl<B> Block<B> for LinearBlock<B>
@@ -67,20 +90,7 @@ where
         _device: &impl gfx_hal::Device<B>,
         range: Range<u64>,
     ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
-        assert!(
-            range.start <= range.end,
-            "Memory mapping region must have valid size"
-        );
-        if !self.shared_memory().host_visible() {
-            return Err(gfx_hal::mapping::Error::InvalidAccess);
-        }
-
-        if let Some((ptr, range)) = mapped_sub_range(self.ptr, self.range.clone(), range) {
-            let mapping = unsafe { MappedRange::from_raw(self.shared_memory(), ptr, range) };
-            Ok(mapping)
-        } else {
-            Err(gfx_hal::mapping::Error::OutOfBounds)
-        }
+        self.persistent_map(range)
     }
 
     #[inline]
@@ -113,6 +123,7 @@ pub struct LinearAllocator<B: gfx_hal::Backend> {
     memory_properties: gfx_hal::memory::Properties,
     linear_size: u64,
     offset: u64,
+    blocks: u64,
     lines: VecDeque<Line<B>>,
 }
 
@@ -167,10 +178,28 @@ where
             memory_properties,
             linear_size: config.linear_size,
             offset: 0,
+            blocks: 0,
             lines: VecDeque::new(),
         }
     }
 
+    /// Get amount of memory allocation would take from device.
+    /// Returns 0 if request fits into the last line.
+    pub fn allocation_size(&self, size: u64, align: u64) -> u64 {
+        match self.lines.back() {
+            Some(line) if self.linear_size - size > aligned(line.used, align) => 0,
+            _ => self.linear_size,
+        }
+    }
+
+    /// Get utilization of the allocator.
+    /// Each line is a memory object of `linear_size` bytes.
+    pub fn utilization(&self) -> AllocatorUtilization {
+        let chunks = self.lines.len() as u64;
+        let used = self.lines.iter().map(|line| line.used - line.free).sum();
+        AllocatorUtilization::new(self.blocks, chunks, chunks * self.linear_size, used)
+    }
+
     /// Perform full cleanup of the memory allocated.
     pub fn dispose(mut self, device: &impl gfx_hal::Device<B>) {
         let _ = self.cleanup(device, 0);
@@ -233,6 +262,7 @@ where
             if self.linear_size - size > aligned {
                 line.used = aligned + size;
                 line.free += overhead;
+                self.blocks += 1;
                 let (ptr, range) =
                     mapped_sub_range(line.ptr, 0..self.linear_size, aligned..aligned + size)
                         .expect("This sub-range must fit in line mapping");
@@ -286,6 +316,7 @@ where
         };
 
         self.lines.push_back(line);
+        self.blocks += 1;
         Ok((block, self.linear_size))
     }
 
@@ -304,6 +335,7 @@ where
             let ref mut line = self.lines[index];
             line.free += block.size();
         }
+        self.blocks -= 1;
         block.dispose();
 
         self.cleanup(device, 1)
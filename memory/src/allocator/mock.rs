@@ -0,0 +1,462 @@
+//! Fake device for allocator tests.
+//! Only memory allocation is implemented.
+
+use {
+    gfx_backend_empty::Backend,
+    gfx_hal::{
+        buffer, device, format, image, mapping, pass, pso, query,
+        range::RangeArg,
+        window::{Backbuffer, SwapchainConfig},
+        Device, MemoryTypeId,
+    },
+    std::{
+        borrow::Borrow,
+        ops::Range,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    },
+};
+
+/// Device that counts memory objects allocated and freed.
+#[derive(Debug, Default)]
+pub(crate) struct MockDevice {
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+    /// Sizes of memory objects not freed yet.
+    sizes: Mutex<Vec<u64>>,
+    limit: Option<u64>,
+}
+
+impl MockDevice {
+    /// Device that fails allocations exceeding `limit` bytes in total.
+    pub(crate) fn with_limit(limit: u64) -> Self {
+        MockDevice {
+            limit: Some(limit),
+            ..MockDevice::default()
+        }
+    }
+
+    /// Number of memory objects allocated.
+    pub(crate) fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::SeqCst)
+    }
+
+    /// Number of memory objects freed.
+    pub(crate) fn frees(&self) -> usize {
+        self.frees.load(Ordering::SeqCst)
+    }
+
+    /// Number of memory objects not freed yet.
+    pub(crate) fn live(&self) -> usize {
+        self.allocations() - self.frees()
+    }
+
+    /// Total size of memory objects not freed yet.
+    ///
+    /// Memory objects of the empty backend carry no data,
+    /// so freeing one forgets the smallest live size.
+    /// This never underestimates and is exact when all objects are freed
+    /// or have equal size.
+    pub(crate) fn allocated(&self) -> u64 {
+        self.sizes.lock().unwrap().iter().sum()
+    }
+}
+
+impl Device<Backend> for MockDevice {
+    unsafe fn allocate_memory(
+        &self,
+        _memory_type: MemoryTypeId,
+        size: u64,
+    ) -> Result<(), device::AllocationError> {
+        let mut sizes = self.sizes.lock().unwrap();
+        let total = sizes.iter().sum::<u64>() + size;
+        if self.limit.map_or(false, |limit| total > limit) {
+            return Err(device::OutOfMemory::OutOfDeviceMemory.into());
+        }
+        sizes.push(size);
+        self.allocations.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    unsafe fn free_memory(&self, _memory: ()) {
+        let mut sizes = self.sizes.lock().unwrap();
+        let smallest = (0..sizes.len())
+            .min_by_key(|&index| sizes[index])
+            .expect("Memory freed more times than allocated");
+        sizes.swap_remove(smallest);
+        self.frees.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe fn create_command_pool(
+        &self,
+        _family: gfx_hal::queue::QueueFamilyId,
+        _flags: gfx_hal::pool::CommandPoolCreateFlags,
+    ) -> Result<gfx_backend_empty::RawCommandPool, device::OutOfMemory> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_command_pool(&self, _pool: gfx_backend_empty::RawCommandPool) {
+        unimplemented!()
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        _attachments: IA,
+        _subpasses: IS,
+        _dependencies: ID,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_render_pass(&self, _rp: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        _set_layouts: IS,
+        _push_constant: IR,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<()>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, _layout: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        _data: Option<&[u8]>,
+    ) -> Result<(), device::OutOfMemory> {
+        unimplemented!()
+    }
+
+    unsafe fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, device::OutOfMemory> {
+        unimplemented!()
+    }
+
+    unsafe fn merge_pipeline_caches<I>(
+        &self,
+        _target: &(),
+        _sources: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _cache: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        _desc: &pso::GraphicsPipelineDesc<'a, Backend>,
+        _cache: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_graphics_pipeline(&self, _pipeline: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        _desc: &pso::ComputePipelineDesc<'a, Backend>,
+        _cache: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, _pipeline: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _pass: &(),
+        _attachments: I,
+        _extent: image::Extent,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_framebuffer(&self, _buf: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_shader_module(&self, _spirv_data: &[u8]) -> Result<(), device::ShaderError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_shader_module(&self, _shader: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        _size: u64,
+        _usage: buffer::Usage,
+    ) -> Result<(), buffer::CreationError> {
+        unimplemented!()
+    }
+
+    unsafe fn get_buffer_requirements(&self, _buf: &()) -> gfx_hal::memory::Requirements {
+        unimplemented!()
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        _memory: &(),
+        _offset: u64,
+        _buf: &mut (),
+    ) -> Result<(), device::BindError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_buffer(&self, _buffer: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_buffer_view<R: RangeArg<u64>>(
+        &self,
+        _buf: &(),
+        _fmt: Option<format::Format>,
+        _range: R,
+    ) -> Result<(), buffer::ViewCreationError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_buffer_view(&self, _view: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_image(
+        &self,
+        _kind: image::Kind,
+        _mip_levels: image::Level,
+        _format: format::Format,
+        _tiling: image::Tiling,
+        _usage: image::Usage,
+        _view_caps: image::ViewCapabilities,
+    ) -> Result<(), image::CreationError> {
+        unimplemented!()
+    }
+
+    unsafe fn get_image_requirements(&self, _image: &()) -> gfx_hal::memory::Requirements {
+        unimplemented!()
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        _image: &(),
+        _subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        unimplemented!()
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        _memory: &(),
+        _offset: u64,
+        _image: &mut (),
+    ) -> Result<(), device::BindError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_image(&self, _image: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        _image: &(),
+        _view_kind: image::ViewKind,
+        _format: format::Format,
+        _swizzle: format::Swizzle,
+        _range: image::SubresourceRange,
+    ) -> Result<(), image::ViewError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_image_view(&self, _view: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_sampler(
+        &self,
+        _info: image::SamplerInfo,
+    ) -> Result<(), device::AllocationError> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_sampler(&self, _sampler: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        _max_sets: usize,
+        _descriptor_ranges: I,
+    ) -> Result<gfx_backend_empty::DescriptorPool, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, _pool: gfx_backend_empty::DescriptorPool) {
+        unimplemented!()
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        _bindings: I,
+        _immutable_samplers: J,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<()>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _layout: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, _write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend>>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, _copy_iter: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend>>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn map_memory<R>(&self, _memory: &(), _range: R) -> Result<*mut u8, mapping::Error>
+    where
+        R: RangeArg<u64>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I, R>(
+        &self,
+        _ranges: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a (), R)>,
+        R: RangeArg<u64>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I, R>(
+        &self,
+        _ranges: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a (), R)>,
+        R: RangeArg<u64>,
+    {
+        unimplemented!()
+    }
+
+    unsafe fn unmap_memory(&self, _memory: &()) {
+        unimplemented!()
+    }
+
+    fn create_semaphore(&self) -> Result<(), device::OutOfMemory> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_semaphore(&self, _semaphore: ()) {
+        unimplemented!()
+    }
+
+    fn create_fence(&self, _signaled: bool) -> Result<(), device::OutOfMemory> {
+        unimplemented!()
+    }
+
+    unsafe fn get_fence_status(&self, _fence: &()) -> Result<bool, device::DeviceLost> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_fence(&self, _fence: ()) {
+        unimplemented!()
+    }
+
+    fn create_query_pool(&self, _ty: query::Type, _count: query::Id) -> Result<(), query::Error> {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_query_pool(&self, _pool: ()) {
+        unimplemented!()
+    }
+
+    unsafe fn get_query_pool_results(
+        &self,
+        _pool: &(),
+        _queries: Range<query::Id>,
+        _data: &mut [u8],
+        _stride: buffer::Offset,
+        _flags: query::ResultFlags,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        unimplemented!()
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        _surface: &mut gfx_backend_empty::Surface,
+        _config: SwapchainConfig,
+        _old_swapchain: Option<gfx_backend_empty::Swapchain>,
+    ) -> Result<(gfx_backend_empty::Swapchain, Backbuffer<Backend>), gfx_hal::window::CreationError>
+    {
+        unimplemented!()
+    }
+
+    unsafe fn destroy_swapchain(&self, _swapchain: gfx_backend_empty::Swapchain) {
+        unimplemented!()
+    }
+
+    fn wait_idle(&self) -> Result<(), gfx_hal::error::HostExecutionError> {
+        unimplemented!()
+    }
+}
@@ -1,14 +1,19 @@
 //! This module provides `Allocator` trait and few allocators that implements the trait.
 
+mod buddy;
 mod dedicated;
 mod dynamic;
 mod linear;
 
+#[cfg(test)]
+pub(crate) mod mock;
+
 use crate::block::Block;
 
 pub use self::{
+    buddy::{BuddyAllocator, BuddyBlock, BuddyConfig},
     dedicated::{DedicatedAllocator, DedicatedBlock},
-    dynamic::{DynamicAllocator, DynamicBlock, DynamicConfig},
+    dynamic::{DynamicAllocator, DynamicBlock, DynamicConfig, DynamicRelocation},
     linear::{LinearAllocator, LinearBlock, LinearConfig},
 };
 
@@ -25,6 +30,10 @@ pub enum Kind {
     /// Fast and low overhead.
     /// Suitable for one-time-use allocations.
     Linear,
+
+    /// Splits memory into power of two sized blocks.
+    /// Suitable for mid-sized allocations of varying size.
+    Buddy,
 }
 
 /// Allocator trait implemented for various allocators.
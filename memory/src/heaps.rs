@@ -1,6 +1,14 @@
-use std::ops::Range;
+use std::{borrow::Cow, collections::HashMap, ops::Range};
 
-use crate::{allocator::*, block::Block, mapping::*, usage::MemoryUsage, util::*};
+use crate::{
+    allocator::*,
+    block::Block,
+    mapping::*,
+    policy::{FitnessPolicy, MemoryTypeInfo, MemoryTypePolicy, MemoryTypeRank},
+    usage::{MemoryUsage, MemoryUsageValue},
+    util::*,
+    utilization::{HeapsUtilization, MemoryHeapUtilization, MemoryTypeUtilization},
+};
 
 /// Possible errors returned by `Heaps`.
 #[allow(missing_copy_implementations)]
@@ -16,6 +24,10 @@ pub enum HeapsError {
         _0, _1
     )]
     NoSuitableMemory(u32, gfx_hal::memory::Properties),
+
+    /// All suitable heaps with enough memory available would exceed their soft budgets.
+    #[fail(display = "Soft budget of heap {} exceeded", _0)]
+    BudgetExceeded(usize),
 }
 
 impl From<gfx_hal::device::AllocationError> for HeapsError {
@@ -39,13 +51,48 @@ pub struct HeapsConfig {
 
     /// Config for dynamic sub-allocator.
     pub dynamic: Option<DynamicConfig>,
+
+    /// Config for buddy sub-allocator.
+    pub buddy: Option<BuddyConfig>,
 }
 
+/// Callback invoked when allocation would exceed soft budget of the heap or the heap is full.
+/// Receives heap index and amount of memory to release.
+/// Returns memory blocks to free.
+pub type EvictionCallback<B> = Box<dyn FnMut(usize, u64) -> Vec<MemoryBlock<B>> + Send>;
+
 /// Heaps available on particular physical device.
-#[derive(Debug)]
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
 pub struct Heaps<B: gfx_hal::Backend> {
     types: Vec<MemoryType<B>>,
     heaps: Vec<MemoryHeap>,
+    #[derivative(Debug = "ignore")]
+    eviction: Option<EvictionCallback<B>>,
+    policy: Box<dyn MemoryTypePolicy>,
+    allocations: HashMap<u64, AllocationInfo>,
+    next_allocation: u64,
+}
+
+/// Information about memory block allocated from `Heaps`.
+#[derive(Clone, Debug)]
+pub struct AllocationInfo {
+    /// Size of the block.
+    pub size: u64,
+
+    /// Memory type id the block is allocated from.
+    pub memory_type: u32,
+
+    /// Sub-allocator the block is allocated with.
+    pub kind: Kind,
+
+    /// User tag of the block.
+    pub tag: Option<Cow<'static, str>>,
+
+    /// Allocation site.
+    /// Captured only in debug builds.
+    #[cfg(debug_assertions)]
+    pub backtrace: backtrace::Backtrace,
 }
 
 impl<B> Heaps<B>
@@ -53,14 +100,15 @@ where
     B: gfx_hal::Backend,
 {
     /// This must be called with `gfx_hal::memory::Properties` fetched from physical device.
+    /// Heaps are specified with size and optional soft budget.
     pub unsafe fn new<P, H>(types: P, heaps: H) -> Self
     where
         P: IntoIterator<Item = (gfx_hal::memory::Properties, u32, HeapsConfig)>,
-        H: IntoIterator<Item = u64>,
+        H: IntoIterator<Item = (u64, Option<u64>)>,
     {
         let heaps = heaps
             .into_iter()
-            .map(|size| MemoryHeap::new(size))
+            .map(|(size, budget)| MemoryHeap::new(size, budget))
             .collect::<Vec<_>>();
         Heaps {
             types: types
@@ -82,14 +130,67 @@ where
                 })
                 .collect(),
             heaps,
+            eviction: None,
+            policy: Box::new(FitnessPolicy),
+            allocations: HashMap::new(),
+            next_allocation: 0,
         }
     }
 
+    /// Set soft budget for the heap.
+    /// `None` removes the budget.
+    pub fn set_budget(&mut self, heap_index: usize, budget: Option<u64>) {
+        self.heaps[heap_index].budget = budget;
+    }
+
+    /// Set callback to be invoked when allocation would exceed soft budget of the heap
+    /// or the heap is full.
+    /// Callback receives heap index and amount of memory to release.
+    /// Memory blocks returned by the callback are freed before allocation proceeds.
+    /// Callback runs while `Heaps` are borrowed by the allocation,
+    /// so it must return blocks instead of freeing them.
+    ///
+    /// # Safety
+    ///
+    /// Device must not use memory blocks returned by the callback.
+    pub unsafe fn set_eviction_callback(
+        &mut self,
+        callback: impl FnMut(usize, u64) -> Vec<MemoryBlock<B>> + Send + 'static,
+    ) {
+        self.eviction = Some(Box::new(callback));
+    }
+
+    /// Set policy that ranks memory types for allocations.
+    /// `FitnessPolicy` is used by default.
+    pub fn set_policy(&mut self, policy: impl MemoryTypePolicy + 'static) {
+        self.policy = Box::new(policy);
+    }
+
+    /// Get description of all memory types.
+    /// Useful to configure `MemoryTypePolicy`.
+    pub fn memory_types(&self) -> impl Iterator<Item = MemoryTypeInfo> + '_ {
+        self.types
+            .iter()
+            .enumerate()
+            .map(move |(index, mt)| MemoryTypeInfo {
+                id: index as u32,
+                heap_index: mt.heap_index,
+                heap_size: self.heaps[mt.heap_index].size,
+                properties: mt.properties,
+            })
+    }
+
     /// Allocate memory block
     /// from one of memory types specified by `mask`,
     /// for intended `usage`,
     /// with `size`
     /// and `align` requirements.
+    ///
+    /// Memory types are tried in order of rank given by `MemoryTypePolicy`.
+    /// Types the policy marks as spill-over are tried last.
+    /// If allocation would exceed soft budget of the heap or the heap is full
+    /// eviction callback is asked to release memory of that heap first.
+    /// If memory still can't be allocated next memory type is tried.
     pub fn allocate(
         &mut self,
         device: &impl gfx_hal::Device<B>,
@@ -100,40 +201,122 @@ where
     ) -> Result<MemoryBlock<B>, HeapsError> {
         debug_assert!(fits_u32(self.types.len()));
 
-        let (memory_index, _, _) = {
-            let suitable_types = self
-                .types
-                .iter()
-                .enumerate()
-                .filter(|(index, _)| (mask & (1u32 << index)) != 0)
-                .filter_map(|(index, mt)| {
-                    if mt.properties.contains(usage.properties_required()) {
-                        let fitness = usage.memory_fitness(mt.properties);
-                        Some((index, mt, fitness))
-                    } else {
+        let ref policy = self.policy;
+        let mut suitable_types = self
+            .memory_types()
+            .filter(|info| (mask & (1u32 << info.id)) != 0)
+            .filter_map(|info| {
+                let fitness = if info.properties.contains(usage.properties_required()) {
+                    Some(usage.memory_fitness(info.properties))
+                } else {
+                    None
+                };
+                match policy.rank(usage.value(), &info, fitness) {
+                    MemoryTypeRank::Never => None,
+                    // Only `Data` may spill over to memory lacking required properties,
+                    // and only to host-visible memory.
+                    _ if fitness.is_none()
+                        && (usage.value() != MemoryUsageValue::Data
+                            || !info
+                                .properties
+                                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)) =>
+                    {
                         None
                     }
-                })
-                .collect::<smallvec::SmallVec<[_; 64]>>();
+                    rank => Some((info.id as usize, info.heap_index, rank)),
+                }
+            })
+            .collect::<smallvec::SmallVec<[_; 64]>>();
 
-            if suitable_types.is_empty() {
-                return Err(HeapsError::NoSuitableMemory(
-                    mask,
-                    usage.properties_required(),
-                ));
+        if suitable_types.is_empty() {
+            return Err(HeapsError::NoSuitableMemory(
+                mask,
+                usage.properties_required(),
+            ));
+        }
+
+        suitable_types.sort_by_key(|&(_, _, rank)| std::cmp::Reverse(rank));
+
+        let mut evicted = smallvec::SmallVec::<[usize; 16]>::new();
+        let mut exceeded = None;
+        for (memory_index, heap_index, rank) in suitable_types {
+            // Sub-allocators take whole chunks from device.
+            let ref memory_type = self.types[memory_index];
+            let mut required = memory_type.allocation_size(usage, size, align);
+
+            let ref heap = self.heaps[heap_index];
+            if (heap.available() < required || !heap.fits_budget(required))
+                && !evicted.contains(&heap_index)
+            {
+                evicted.push(heap_index);
+                self.evict(device, heap_index, required);
+
+                // Evicted blocks may leave room in existing chunks.
+                let ref memory_type = self.types[memory_index];
+                required = memory_type.allocation_size(usage, size, align);
             }
 
-            suitable_types
-                .into_iter()
-                .filter(|(_, mt, _)| self.heaps[mt.heap_index].available() > size + align)
-                .max_by_key(|&(_, _, fitness)| fitness)
-                .ok_or_else(|| {
-                    log::error!("All suitable heaps are exhausted. {:#?}", self);
-                    gfx_hal::device::OutOfMemory::OutOfDeviceMemory
-                })?
+            if self.heaps[heap_index].available() < required {
+                continue;
+            }
+
+            if !self.heaps[heap_index].fits_budget(required) {
+                log::warn!(
+                    "Allocation of {} bytes from memory type {} exceeds soft budget of heap {}",
+                    size,
+                    memory_index,
+                    heap_index
+                );
+                exceeded = exceeded.or(Some(heap_index));
+                continue;
+            }
+
+            if let MemoryTypeRank::Spill(_) = rank {
+                log::warn!(
+                    "Allocation of {} bytes for {:?} usage spills over to memory type {}",
+                    size,
+                    usage.value(),
+                    memory_index
+                );
+            }
+
+            return self.allocate_from(device, memory_index as u32, usage, size, align);
+        }
+
+        if let Some(heap_index) = exceeded {
+            Err(HeapsError::BudgetExceeded(heap_index))
+        } else {
+            log::error!("All suitable heaps are exhausted. {:#?}", self);
+            Err(gfx_hal::device::OutOfMemory::OutOfDeviceMemory.into())
+        }
+    }
+
+    /// Ask eviction callback to release memory of the heap
+    /// so that `size` bytes can be allocated from device within its budget.
+    fn evict(&mut self, device: &impl gfx_hal::Device<B>, heap_index: usize, size: u64) {
+        let ref heap = self.heaps[heap_index];
+        let limit = heap
+            .budget
+            .map_or(heap.size, |budget| budget.min(heap.size));
+        let required = (heap.used + size).saturating_sub(limit);
+        let blocks = match self.eviction.as_mut() {
+            Some(eviction) => eviction(heap_index, required),
+            None => return,
         };
 
-        self.allocate_from(device, memory_index as u32, usage, size, align)
+        let count = blocks.len();
+        let used = self.heaps[heap_index].used;
+        for block in blocks {
+            self.free(device, block);
+        }
+
+        log::trace!(
+            "Evicted {} blocks releasing {} of {} bytes required from heap {}",
+            count,
+            used - self.heaps[heap_index].used,
+            required,
+            heap_index
+        );
     }
 
     /// Allocate memory block
@@ -159,13 +342,50 @@ where
             return Err(gfx_hal::device::OutOfMemory::OutOfDeviceMemory.into());
         }
 
-        let (block, allocated) = memory_type.alloc(device, usage, size, align)?;
+        let (mut block, allocated) = memory_type.alloc(device, usage, size, align)?;
         memory_heap.used += allocated;
 
-        Ok(MemoryBlock {
+        if usage.persistent_mapping()
+            && memory_type
+                .properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+        {
+            // Sub-allocators keep their memory mapped already.
+            if let BlockFlavor::Dedicated(dedicated) = &mut block {
+                match dedicated.map_persistent(device) {
+                    Ok(()) => {}
+                    Err(gfx_hal::mapping::Error::OutOfMemory(error)) => {
+                        memory_heap.used -= memory_type.free(device, block);
+                        return Err(error.into());
+                    }
+                    Err(_) => panic!("Unexpected mapping failure"),
+                }
+            }
+        }
+
+        let id = self.next_allocation;
+        self.next_allocation += 1;
+
+        let block = MemoryBlock {
             block,
             memory_index,
-        })
+            id,
+        };
+
+        let range = block.range();
+        self.allocations.insert(
+            id,
+            AllocationInfo {
+                size: range.end - range.start,
+                memory_type: memory_index,
+                kind: block.block.kind(),
+                tag: None,
+                #[cfg(debug_assertions)]
+                backtrace: backtrace::Backtrace::new_unresolved(),
+            },
+        );
+
+        Ok(block)
     }
 
     /// Free memory block.
@@ -180,12 +400,129 @@ where
         let ref mut memory_heap = self.heaps[memory_type.heap_index];
         let freed = memory_type.free(device, block.block);
         memory_heap.used -= freed;
+        self.allocations.remove(&block.id);
+    }
+
+    /// Set user tag for the memory block.
+    /// Tag is reported along with the block if it leaks.
+    pub fn set_tag(&mut self, block: &MemoryBlock<B>, tag: impl Into<Cow<'static, str>>) {
+        self.allocations
+            .get_mut(&block.id)
+            .expect("Block must be allocated from this heaps")
+            .tag = Some(tag.into());
+    }
+
+    /// Get information about memory block.
+    pub fn allocation_info(&self, block: &MemoryBlock<B>) -> &AllocationInfo {
+        &self.allocations[&block.id]
+    }
+
+    /// Get information about all memory blocks that are not freed yet.
+    pub fn live_allocations(&self) -> impl Iterator<Item = &AllocationInfo> + '_ {
+        self.allocations.values()
+    }
+
+    /// Plan relocation of blocks allocated by dynamic sub-allocators
+    /// from sparse chunks into denser ones.
+    ///
+    /// Caller decides which relocations to perform.
+    /// For each approved relocation caller must copy content of the source block
+    /// to the target block, rebind resources and call `commit_relocation`.
+    /// All other relocations must be passed to `cancel_relocation`.
+    pub fn plan_defragmentation(&mut self) -> Vec<Relocation<B>> {
+        let mut relocations = Vec::new();
+        for (index, memory_type) in self.types.iter_mut().enumerate() {
+            if let Some(dynamic) = memory_type.dynamic.as_mut() {
+                relocations.extend(
+                    dynamic
+                        .plan_defragmentation()
+                        .into_iter()
+                        .map(|relocation| Relocation {
+                            relocation,
+                            memory_index: index as u32,
+                        }),
+                );
+            }
+        }
+        relocations
+    }
+
+    /// Commit relocation planned by `plan_defragmentation`.
+    /// Frees source block and returns target block.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `block` is not the source of the relocation.
+    pub fn commit_relocation(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        relocation: Relocation<B>,
+        block: MemoryBlock<B>,
+    ) -> MemoryBlock<B> {
+        assert!(
+            relocation.is_source(&block),
+            "Block is not the source of the relocation"
+        );
+        let memory_index = relocation.memory_index;
+        let id = block.id;
+        let ref mut memory_type = self.types[memory_index as usize];
+        let ref mut memory_heap = self.heaps[memory_type.heap_index];
+
+        let block = match block.block {
+            BlockFlavor::Dynamic(block) => block,
+            _ => unreachable!(),
+        };
+        let (block, freed) = memory_type.dynamic.as_mut().unwrap().commit_relocation(
+            device,
+            relocation.relocation,
+            block,
+        );
+        memory_heap.used -= freed;
+
+        MemoryBlock {
+            block: BlockFlavor::Dynamic(block),
+            memory_index,
+            id,
+        }
+    }
+
+    /// Cancel relocation planned by `plan_defragmentation`.
+    pub fn cancel_relocation(
+        &mut self,
+        device: &impl gfx_hal::Device<B>,
+        relocation: Relocation<B>,
+    ) {
+        let ref mut memory_type = self.types[relocation.memory_index as usize];
+        let ref mut memory_heap = self.heaps[memory_type.heap_index];
+        let freed = memory_type
+            .dynamic
+            .as_mut()
+            .unwrap()
+            .cancel_relocation(device, relocation.relocation);
+        memory_heap.used -= freed;
+    }
+
+    /// Get utilization of memory heaps and memory types.
+    pub fn utilization(&self) -> HeapsUtilization {
+        HeapsUtilization {
+            heaps: self.heaps.iter().map(MemoryHeap::utilization).collect(),
+            types: self.types.iter().map(MemoryType::utilization).collect(),
+        }
     }
 
     /// Dispose of allocator.
     /// Cleanup allocators before dropping.
-    /// Will panic if memory instances are left allocated.
-    pub fn dispose(self, device: &impl gfx_hal::Device<B>) {
+    /// Memory blocks left allocated are logged as leaked.
+    pub fn dispose(mut self, device: &impl gfx_hal::Device<B>) {
+        if !self.allocations.is_empty() {
+            log::error!("{} memory blocks leaked", self.allocations.len());
+            for info in self.allocations.values_mut() {
+                #[cfg(debug_assertions)]
+                info.backtrace.resolve();
+                log::error!("Leaked block: {:#?}", info);
+            }
+        }
+
         for mt in self.types {
             mt.dispose(device)
         }
@@ -197,6 +534,7 @@ where
 pub struct MemoryBlock<B: gfx_hal::Backend> {
     block: BlockFlavor<B>,
     memory_index: u32,
+    id: u64,
 }
 
 impl<B> MemoryBlock<B>
@@ -209,12 +547,46 @@ where
     }
 }
 
+/// Relocation of the `MemoryBlock` planned by `Heaps::plan_defragmentation`.
+/// Must be either committed or canceled.
+#[derive(Debug)]
+pub struct Relocation<B: gfx_hal::Backend> {
+    relocation: DynamicRelocation<B>,
+    memory_index: u32,
+}
+
+impl<B> Relocation<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Check if `block` is the block to be relocated.
+    pub fn is_source(&self, block: &MemoryBlock<B>) -> bool {
+        match &block.block {
+            BlockFlavor::Dynamic(dynamic) => {
+                block.memory_index == self.memory_index && self.relocation.is_source(dynamic)
+            }
+            _ => false,
+        }
+    }
+
+    /// Get block reserved for relocation.
+    /// Content of the source block must be copied here.
+    pub fn target(&self) -> &DynamicBlock<B> {
+        self.relocation.target()
+    }
+
+    /// Get memory type id.
+    pub fn memory_type(&self) -> u32 {
+        self.memory_index
+    }
+}
+
 #[derive(Debug)]
 enum BlockFlavor<B: gfx_hal::Backend> {
     Dedicated(DedicatedBlock<B>),
     Linear(LinearBlock<B>),
     Dynamic(DynamicBlock<B>),
-    // Chunk(ChunkBlock<B>),
+    Buddy(BuddyBlock<B>),
 }
 
 macro_rules! any_block {
@@ -224,7 +596,7 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
-            // Chunk($block) => $expr,
+            Buddy($block) => $expr,
         }
     }};
     (& $self:ident. $block:ident => $expr:expr) => {{
@@ -233,7 +605,7 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
-            // Chunk($block) => $expr,
+            Buddy($block) => $expr,
         }
     }};
     (&mut $self:ident. $block:ident => $expr:expr) => {{
@@ -242,11 +614,25 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
-            // Chunk($block) => $expr,
+            Buddy($block) => $expr,
         }
     }};
 }
 
+impl<B> BlockFlavor<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn kind(&self) -> Kind {
+        match self {
+            BlockFlavor::Dedicated(_) => Kind::Dedicated,
+            BlockFlavor::Linear(_) => Kind::Linear,
+            BlockFlavor::Dynamic(_) => Kind::Dynamic,
+            BlockFlavor::Buddy(_) => Kind::Buddy,
+        }
+    }
+}
+
 impl<B> Block<B> for MemoryBlock<B>
 where
     B: gfx_hal::Backend,
@@ -279,20 +665,53 @@ where
     }
 }
 
+impl<B> MemoryBlock<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Get mapping for the range of the block without remapping memory.
+    /// Blocks allocated for usages with persistent mapping are always mapped.
+    /// Fails with `MappingFailed` if the block isn't mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        any_block!(&mut self.block => block.persistent_map(range))
+    }
+}
+
 #[derive(Debug)]
 struct MemoryHeap {
     size: u64,
     used: u64,
+    budget: Option<u64>,
 }
 
 impl MemoryHeap {
-    fn new(size: u64) -> Self {
-        MemoryHeap { size, used: 0 }
+    fn new(size: u64, budget: Option<u64>) -> Self {
+        MemoryHeap {
+            size,
+            used: 0,
+            budget,
+        }
     }
 
     fn available(&self) -> u64 {
         self.size - self.used
     }
+
+    fn fits_budget(&self, size: u64) -> bool {
+        self.budget
+            .map_or(true, |budget| self.used + size <= budget)
+    }
+
+    fn utilization(&self) -> MemoryHeapUtilization {
+        MemoryHeapUtilization {
+            size: self.size,
+            used: self.used,
+            budget: self.budget,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -302,7 +721,7 @@ struct MemoryType<B: gfx_hal::Backend> {
     dedicated: DedicatedAllocator,
     linear: Option<LinearAllocator<B>>,
     dynamic: Option<DynamicAllocator<B>>,
-    // chunk: Option<ChunkAllocator>,
+    buddy: Option<BuddyAllocator<B>>,
 }
 
 impl<B> MemoryType<B>
@@ -329,6 +748,59 @@ where
             dynamic: config
                 .dynamic
                 .map(|config| DynamicAllocator::new(memory_type, properties, config)),
+            buddy: config
+                .buddy
+                .map(|config| BuddyAllocator::new(memory_type, properties, config)),
+        }
+    }
+
+    /// Pick most fit sub-allocator that can serve the request.
+    /// Prefer one with smaller allocations limit if fitness is equal.
+    /// Buddy allocator only serves requests too big for dynamic allocator,
+    /// as its smallest blocks are much bigger than dynamic ones.
+    fn kind(&self, usage: impl MemoryUsage, size: u64, align: u64) -> Kind {
+        let dynamic_max = self.dynamic.as_ref().map_or(0, |a| a.max_allocation());
+        let kind = [
+            (
+                Kind::Dynamic,
+                self.dynamic.as_ref().map(|a| a.max_allocation()),
+            ),
+            (
+                Kind::Linear,
+                self.linear.as_ref().map(|a| a.max_allocation()),
+            ),
+            (Kind::Buddy, self.buddy.as_ref().map(|a| a.max_allocation())),
+        ]
+        .iter()
+        .filter_map(|&(kind, max_allocation)| {
+            max_allocation
+                .filter(|&max_allocation| max_allocation >= size)
+                .map(|max_allocation| (kind, max_allocation))
+        })
+        .filter(|&(kind, _)| usage.allocator_fitness(kind) > 0)
+        .filter(|&(kind, _)| kind != Kind::Buddy || size > dynamic_max)
+        .max_by_key(|&(kind, max_allocation)| {
+            (
+                usage.allocator_fitness(kind),
+                std::cmp::Reverse(max_allocation),
+            )
+        })
+        .map_or(Kind::Dedicated, |(kind, _)| kind);
+
+        match kind {
+            // Alignment is too big for buddy chunks.
+            Kind::Buddy if !self.buddy.as_ref().unwrap().fits(size, align) => Kind::Dedicated,
+            kind => kind,
+        }
+    }
+
+    /// Get amount of memory allocation would take from device.
+    fn allocation_size(&self, usage: impl MemoryUsage, size: u64, align: u64) -> u64 {
+        match self.kind(usage, size, align) {
+            Kind::Dedicated => size,
+            Kind::Linear => self.linear.as_ref().unwrap().allocation_size(size, align),
+            Kind::Dynamic => self.dynamic.as_ref().unwrap().allocation_size(size, align),
+            Kind::Buddy => self.buddy.as_ref().unwrap().allocation_size(size, align),
         }
     }
 
@@ -339,53 +811,29 @@ where
         size: u64,
         align: u64,
     ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
-        match (self.dynamic.as_mut(), self.linear.as_mut()) {
-            (Some(dynamic), Some(linear)) => {
-                if dynamic.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Dynamic)
-                        > usage.allocator_fitness(Kind::Linear)
-                {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else if linear.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Linear) > 0
-                {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (Some(dynamic), None) => {
-                if dynamic.max_allocation() >= size && usage.allocator_fitness(Kind::Dynamic) > 0 {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, Some(linear)) => {
-                if linear.max_allocation() >= size && usage.allocator_fitness(Kind::Linear) > 0 {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, None) => self
+        match self.kind(usage, size, align) {
+            Kind::Dedicated => self
                 .dedicated
                 .alloc(device, size, align)
                 .map(|(block, size)| (BlockFlavor::Dedicated(block), size)),
+            Kind::Linear => self
+                .linear
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Linear(block), size)),
+            Kind::Dynamic => self
+                .dynamic
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Dynamic(block), size)),
+            Kind::Buddy => self
+                .buddy
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Buddy(block), size)),
         }
     }
 
@@ -394,6 +842,18 @@ where
             BlockFlavor::Dedicated(block) => self.dedicated.free(device, block),
             BlockFlavor::Linear(block) => self.linear.as_mut().unwrap().free(device, block),
             BlockFlavor::Dynamic(block) => self.dynamic.as_mut().unwrap().free(device, block),
+            BlockFlavor::Buddy(block) => self.buddy.as_mut().unwrap().free(device, block),
+        }
+    }
+
+    fn utilization(&self) -> MemoryTypeUtilization {
+        MemoryTypeUtilization {
+            heap_index: self.heap_index,
+            properties: self.properties,
+            dedicated: self.dedicated.utilization(),
+            linear: self.linear.as_ref().map(LinearAllocator::utilization),
+            dynamic: self.dynamic.as_ref().map(DynamicAllocator::utilization),
+            buddy: self.buddy.as_ref().map(BuddyAllocator::utilization),
         }
     }
 
@@ -404,5 +864,179 @@ where
         if let Some(dynamic) = self.dynamic {
             dynamic.dispose();
         }
+        if let Some(buddy) = self.buddy {
+            buddy.dispose();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            allocator::mock::MockDevice,
+            usage::{Data, Upload},
+        },
+        gfx_backend_empty::Backend,
+    };
+
+    const BLOCK: u64 = 256;
+
+    fn heaps(budget: u64) -> Heaps<Backend> {
+        heaps_with(budget, None)
+    }
+
+    fn heaps_with(budget: u64, buddy: Option<BuddyConfig>) -> Heaps<Backend> {
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                blocks_per_chunk: 64,
+                block_size_granularity: BLOCK,
+                max_block_size: 4 * BLOCK,
+                max_chunk_size: 1 << 30,
+            }),
+            buddy,
+        };
+        unsafe {
+            Heaps::new(
+                Some((gfx_hal::memory::Properties::DEVICE_LOCAL, 0, config)),
+                Some((1 << 30, Some(budget))),
+            )
+        }
+    }
+
+    #[test]
+    fn budget_counts_whole_chunks() {
+        // Dynamic allocator always uses block per bit of `usize`.
+        let chunk = BLOCK * std::mem::size_of::<usize>() as u64 * 8;
+        let device = MockDevice::default();
+
+        // Block fits into the budget, but chunk allocated for it doesn't.
+        let mut heaps = heaps(chunk - 1);
+        match heaps.allocate(&device, 1, Data, BLOCK, 1) {
+            Err(HeapsError::BudgetExceeded(0)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(device.allocations(), 0);
+
+        // Second block is allocated from existing chunk without exceeding the budget.
+        heaps.set_budget(0, Some(chunk));
+        let first = heaps.allocate(&device, 1, Data, BLOCK, 1).unwrap();
+        let second = heaps.allocate(&device, 1, Data, BLOCK, 1).unwrap();
+        assert_eq!(device.allocations(), 1);
+
+        heaps.free(&device, first);
+        heaps.free(&device, second);
+        assert_eq!(device.live(), 0);
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    fn buddy_serves_only_requests_too_big_for_dynamic() {
+        let device = MockDevice::default();
+        let mut heaps = heaps_with(
+            1 << 30,
+            Some(BuddyConfig {
+                min_block_size: 256 * 1024,
+                chunk_size: 16 * 1024 * 1024,
+            }),
+        );
+
+        let small = heaps.allocate(&device, 1, Data, BLOCK, 1).unwrap();
+        assert_eq!(heaps.allocation_info(&small).kind, Kind::Dynamic);
+
+        let big = heaps
+            .allocate(&device, 1, Data, 4 * 1024 * 1024, 1)
+            .unwrap();
+        assert_eq!(heaps.allocation_info(&big).kind, Kind::Buddy);
+
+        heaps.free(&device, small);
+        heaps.free(&device, big);
+        assert_eq!(device.live(), 0);
+        assert_eq!(device.allocated(), 0);
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    fn evicts_when_heap_is_full() {
+        const SIZE: u64 = 1024;
+        let device = MockDevice::with_limit(2 * SIZE);
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: None,
+            buddy: None,
+        };
+        let mut heaps = unsafe {
+            Heaps::<Backend>::new(
+                Some((gfx_hal::memory::Properties::DEVICE_LOCAL, 0, config)),
+                Some((2 * SIZE, None)),
+            )
+        };
+
+        let first = heaps.allocate(&device, 1, Data, SIZE, 1).unwrap();
+        let second = heaps.allocate(&device, 1, Data, SIZE, 1).unwrap();
+
+        let evictable = std::sync::Arc::new(std::sync::Mutex::new(vec![first]));
+        let callback_evictable = evictable.clone();
+        unsafe {
+            heaps.set_eviction_callback(move |heap_index, size| {
+                assert_eq!((heap_index, size), (0, SIZE));
+                callback_evictable.lock().unwrap().drain(..).collect()
+            });
+        }
+
+        // Heap has no room left until first block is evicted.
+        let third = heaps.allocate(&device, 1, Data, SIZE, 1).unwrap();
+        assert!(evictable.lock().unwrap().is_empty());
+        assert_eq!(device.allocated(), 2 * SIZE);
+
+        heaps.free(&device, second);
+        heaps.free(&device, third);
+        assert_eq!(device.live(), 0);
+        heaps.dispose(&device);
+    }
+
+    /// Policy that allows any memory type.
+    #[derive(Debug)]
+    struct AnyPolicy;
+
+    impl MemoryTypePolicy for AnyPolicy {
+        fn rank(&self, _: MemoryUsageValue, _: &MemoryTypeInfo, _: Option<u32>) -> MemoryTypeRank {
+            MemoryTypeRank::Suitable(0)
+        }
+    }
+
+    #[test]
+    fn required_properties_override_policy() {
+        let device = MockDevice::default();
+        let config = || HeapsConfig {
+            linear: None,
+            dynamic: None,
+            buddy: None,
+        };
+        let mut heaps = unsafe {
+            Heaps::<Backend>::new(
+                vec![
+                    (gfx_hal::memory::Properties::DEVICE_LOCAL, 0, config()),
+                    (gfx_hal::memory::Properties::CPU_VISIBLE, 1, config()),
+                ],
+                vec![(1 << 30, None), (1 << 30, None)],
+            )
+        };
+        heaps.set_policy(AnyPolicy);
+
+        // Device-local memory can't be used for uploads.
+        match heaps.allocate(&device, 0b01, Upload, BLOCK, 1) {
+            Err(HeapsError::NoSuitableMemory(0b01, _)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // Data may spill over to host-visible memory.
+        let block = heaps.allocate(&device, 0b10, Data, BLOCK, 1).unwrap();
+        assert_eq!(heaps.allocation_info(&block).memory_type, 1);
+
+        heaps.free(&device, block);
+        heaps.dispose(&device);
     }
 }
@@ -16,14 +16,20 @@ mod block;
 mod heaps;
 mod mapping;
 mod memory;
+mod policy;
 mod usage;
 mod util;
+mod utilization;
 
 pub use crate::{
     allocator::*,
     block::Block,
-    heaps::{Heaps, HeapsConfig, HeapsError, MemoryBlock},
+    heaps::{
+        AllocationInfo, EvictionCallback, Heaps, HeapsConfig, HeapsError, MemoryBlock, Relocation,
+    },
     mapping::{write::Write, Coherent, MappedRange, MaybeCoherent, NonCoherent},
     memory::Memory,
+    policy::*,
     usage::*,
+    utilization::*,
 };
@@ -121,7 +121,7 @@ where
         let (ptr, range) = mapped_sub_range(self.ptr, self.range.clone(), range)
             .ok_or_else(|| gfx_hal::mapping::Error::OutOfBounds)?;
 
-        if self.coherent.0 {
+        if !self.coherent.0 {
             device
                 .invalidate_mapped_memory_ranges(Some((self.memory.raw(), self.range.clone())))?;
         }
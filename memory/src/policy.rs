@@ -0,0 +1,190 @@
+//! Memory type selection policies.
+//! See `MemoryTypePolicy` for details.
+
+use crate::usage::MemoryUsageValue;
+
+/// Description of memory type passed to `MemoryTypePolicy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryTypeInfo {
+    /// Memory type id.
+    pub id: u32,
+
+    /// Index of the heap this memory type belongs to.
+    pub heap_index: usize,
+
+    /// Size of the heap this memory type belongs to.
+    pub heap_size: u64,
+
+    /// Properties of the memory type.
+    pub properties: gfx_hal::memory::Properties,
+}
+
+/// Rank of the memory type for particular allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryTypeRank {
+    /// Memory type must not be used.
+    Never,
+
+    /// Memory type is used only if all suitable types failed.
+    /// Such allocations are logged with warning.
+    /// Greater values are tried first.
+    Spill(u32),
+
+    /// Memory type is suitable.
+    /// Greater values are tried first.
+    Suitable(u32),
+}
+
+/// Policy that decides which memory types `Heaps` allocate from.
+pub trait MemoryTypePolicy: std::fmt::Debug + Send + Sync {
+    /// Rank memory type for allocation with specified usage.
+    /// `fitness` is `MemoryUsage::memory_fitness` for the memory type
+    /// or `None` if memory type lacks properties required by the usage.
+    ///
+    /// `Heaps` never allocate from memory types lacking required properties,
+    /// except host-visible ones for `Data` usage, whatever rank is returned.
+    fn rank(
+        &self,
+        usage: MemoryUsageValue,
+        memory_type: &MemoryTypeInfo,
+        fitness: Option<u32>,
+    ) -> MemoryTypeRank;
+}
+
+/// Policy that ranks memory types solely by `MemoryUsage::memory_fitness`.
+/// This is the default policy of `Heaps`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FitnessPolicy;
+
+impl MemoryTypePolicy for FitnessPolicy {
+    fn rank(
+        &self,
+        _usage: MemoryUsageValue,
+        _memory_type: &MemoryTypeInfo,
+        fitness: Option<u32>,
+    ) -> MemoryTypeRank {
+        fitness.map_or(MemoryTypeRank::Never, MemoryTypeRank::Suitable)
+    }
+}
+
+/// Configurable memory type policy.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BasicMemoryTypePolicy {
+    /// Heaps that are tried before others.
+    /// Earlier heaps are preferred over later ones.
+    pub preferred_heaps: Vec<usize>,
+
+    /// Memory types that are never used.
+    pub never_types: Vec<u32>,
+
+    /// Allow `Data` allocations to spill over to host memory
+    /// when device-local memory is exhausted.
+    pub spill_to_host: bool,
+
+    /// Heaps that only `Dynamic` allocations may use.
+    pub dynamic_heaps: Vec<usize>,
+}
+
+impl BasicMemoryTypePolicy {
+    /// Preset for discrete devices.
+    /// Prefers the biggest device-local heap,
+    /// reserves small device-local host-visible (BAR) heaps for `Dynamic` usage
+    /// and allows `Data` to spill over to host memory.
+    pub fn discrete(types: impl IntoIterator<Item = MemoryTypeInfo>) -> Self {
+        let types = types.into_iter().collect::<Vec<_>>();
+        let device_local = |info: &MemoryTypeInfo| {
+            info.properties
+                .contains(gfx_hal::memory::Properties::DEVICE_LOCAL)
+        };
+        let host_visible = |info: &MemoryTypeInfo| {
+            info.properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+        };
+
+        let main_heap = types
+            .iter()
+            .filter(|info| device_local(info) && !host_visible(info))
+            .max_by_key(|info| info.heap_size)
+            .map(|info| info.heap_index);
+
+        let mut dynamic_heaps = types
+            .iter()
+            .filter(|info| device_local(info) && host_visible(info))
+            .map(|info| info.heap_index)
+            .filter(|&heap_index| Some(heap_index) != main_heap)
+            .collect::<Vec<_>>();
+        dynamic_heaps.sort();
+        dynamic_heaps.dedup();
+
+        BasicMemoryTypePolicy {
+            preferred_heaps: main_heap.into_iter().collect(),
+            never_types: Vec::new(),
+            spill_to_host: true,
+            dynamic_heaps,
+        }
+    }
+
+    /// Preset for integrated devices with unified memory.
+    /// All memory is shared with host, so no heap is reserved and nothing spills.
+    pub fn integrated() -> Self {
+        BasicMemoryTypePolicy::default()
+    }
+
+    /// Pick preset based on memory types layout.
+    /// Device is considered integrated if all its device-local memory is host-visible.
+    pub fn detect(types: impl IntoIterator<Item = MemoryTypeInfo>) -> Self {
+        let types = types.into_iter().collect::<Vec<_>>();
+        let discrete = types.iter().any(|info| {
+            info.properties
+                .contains(gfx_hal::memory::Properties::DEVICE_LOCAL)
+                && !info
+                    .properties
+                    .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+        });
+
+        if discrete {
+            Self::discrete(types)
+        } else {
+            Self::integrated()
+        }
+    }
+}
+
+impl MemoryTypePolicy for BasicMemoryTypePolicy {
+    fn rank(
+        &self,
+        usage: MemoryUsageValue,
+        memory_type: &MemoryTypeInfo,
+        fitness: Option<u32>,
+    ) -> MemoryTypeRank {
+        if self.never_types.contains(&memory_type.id) {
+            return MemoryTypeRank::Never;
+        }
+
+        if usage != MemoryUsageValue::Dynamic
+            && self.dynamic_heaps.contains(&memory_type.heap_index)
+        {
+            return MemoryTypeRank::Never;
+        }
+
+        let preference = self
+            .preferred_heaps
+            .iter()
+            .position(|&heap_index| heap_index == memory_type.heap_index)
+            .map_or(0, |position| (self.preferred_heaps.len() - position) as u32);
+
+        match fitness {
+            // Fitness values are small, preference dominates.
+            Some(fitness) => MemoryTypeRank::Suitable(preference << 16 | fitness),
+            None if self.spill_to_host && usage == MemoryUsageValue::Data => {
+                // Uncached memory is faster for device to read.
+                let uncached = !memory_type
+                    .properties
+                    .contains(gfx_hal::memory::Properties::CPU_CACHED);
+                MemoryTypeRank::Spill(uncached as u32)
+            }
+            None => MemoryTypeRank::Never,
+        }
+    }
+}
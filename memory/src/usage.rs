@@ -17,6 +17,29 @@ pub trait MemoryUsage: Copy + std::fmt::Debug {
 
     /// Get comparable fitness value for memory allocator.
     fn allocator_fitness(&self, kind: Kind) -> u32;
+
+    /// Check if memory of this usage should stay mapped while allocated.
+    /// Returns `false` by default.
+    fn persistent_mapping(&self) -> bool {
+        false
+    }
+
+    /// Get well-known usage value.
+    /// `MemoryTypePolicy` ranks memory types for the usage by this value.
+    ///
+    /// Returns `Upload` for usages that require host-visible memory
+    /// and `Data` for others by default.
+    /// Usages that should be treated as `Dynamic` must override it.
+    fn value(&self) -> MemoryUsageValue {
+        if self
+            .properties_required()
+            .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+        {
+            MemoryUsageValue::Upload
+        } else {
+            MemoryUsageValue::Data
+        }
+    }
 }
 
 /// Full speed GPU access.
@@ -44,8 +67,13 @@ impl MemoryUsage for Data {
             Kind::Dedicated => 1,
             Kind::Dynamic => 2,
             Kind::Linear => 0,
+            Kind::Buddy => 2,
         }
     }
+
+    fn value(&self) -> MemoryUsageValue {
+        MemoryUsageValue::Data
+    }
 }
 
 /// CPU to GPU data flow with update commands.
@@ -75,8 +103,17 @@ impl MemoryUsage for Dynamic {
             Kind::Dedicated => 1,
             Kind::Dynamic => 2,
             Kind::Linear => 0,
+            Kind::Buddy => 2,
         }
     }
+
+    fn persistent_mapping(&self) -> bool {
+        true
+    }
+
+    fn value(&self) -> MemoryUsageValue {
+        MemoryUsageValue::Dynamic
+    }
 }
 
 /// CPU to GPU data flow with mapping.
@@ -105,8 +142,17 @@ impl MemoryUsage for Upload {
             Kind::Dedicated => 0,
             Kind::Dynamic => 1,
             Kind::Linear => 2,
+            Kind::Buddy => 1,
         }
     }
+
+    fn persistent_mapping(&self) -> bool {
+        true
+    }
+
+    fn value(&self) -> MemoryUsageValue {
+        MemoryUsageValue::Upload
+    }
 }
 
 /// GPU to CPU data flow with mapping.
@@ -135,12 +181,18 @@ impl MemoryUsage for Download {
             Kind::Dedicated => 0,
             Kind::Dynamic => 1,
             Kind::Linear => 2,
+            Kind::Buddy => 1,
         }
     }
+
+    fn value(&self) -> MemoryUsageValue {
+        MemoryUsageValue::Download
+    }
 }
 
 /// Well-known memory usage types.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MemoryUsageValue {
     /// See [`Data`]
     ///
@@ -191,4 +243,17 @@ impl MemoryUsage for MemoryUsageValue {
             MemoryUsageValue::Download => Download.allocator_fitness(kind),
         }
     }
+
+    fn persistent_mapping(&self) -> bool {
+        match self {
+            MemoryUsageValue::Data => Data.persistent_mapping(),
+            MemoryUsageValue::Dynamic => Dynamic.persistent_mapping(),
+            MemoryUsageValue::Upload => Upload.persistent_mapping(),
+            MemoryUsageValue::Download => Download.persistent_mapping(),
+        }
+    }
+
+    fn value(&self) -> MemoryUsageValue {
+        *self
+    }
 }
@@ -0,0 +1,90 @@
+//! Memory utilization report types.
+//! See `Heaps::utilization` for details.
+
+/// Utilization of single sub-allocator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllocatorUtilization {
+    /// Number of live blocks allocated by user.
+    pub blocks: u64,
+
+    /// Number of memory objects allocated from device.
+    pub chunks: u64,
+
+    /// Amount of memory allocated from device.
+    pub allocated: u64,
+
+    /// Amount of memory occupied by live blocks.
+    pub used: u64,
+
+    /// Amount of memory allocated from device but not occupied by live blocks.
+    /// Includes alignment overhead and fragmentation.
+    pub wasted: u64,
+}
+
+impl AllocatorUtilization {
+    /// Create utilization value from counters.
+    pub(crate) fn new(blocks: u64, chunks: u64, allocated: u64, used: u64) -> Self {
+        debug_assert!(used <= allocated);
+        AllocatorUtilization {
+            blocks,
+            chunks,
+            allocated,
+            used,
+            wasted: allocated - used,
+        }
+    }
+}
+
+/// Utilization of single memory type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryTypeUtilization {
+    /// Index of the heap this memory type belongs to.
+    pub heap_index: usize,
+
+    /// Properties of the memory type.
+    pub properties: gfx_hal::memory::Properties,
+
+    /// Utilization of dedicated sub-allocator.
+    pub dedicated: AllocatorUtilization,
+
+    /// Utilization of linear sub-allocator.
+    /// `None` if memory type has no linear sub-allocator.
+    pub linear: Option<AllocatorUtilization>,
+
+    /// Utilization of dynamic sub-allocator.
+    /// `None` if memory type has no dynamic sub-allocator.
+    pub dynamic: Option<AllocatorUtilization>,
+
+    /// Utilization of buddy sub-allocator.
+    /// `None` if memory type has no buddy sub-allocator.
+    pub buddy: Option<AllocatorUtilization>,
+}
+
+/// Utilization of single memory heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryHeapUtilization {
+    /// Size of the heap.
+    pub size: u64,
+
+    /// Amount of memory allocated from the heap.
+    pub used: u64,
+
+    /// Soft budget of the heap.
+    pub budget: Option<u64>,
+}
+
+/// Utilization of all memory heaps and types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeapsUtilization {
+    /// Utilization of memory heaps.
+    /// Indexed by heap index.
+    pub heaps: Vec<MemoryHeapUtilization>,
+
+    /// Utilization of memory types.
+    /// Indexed by memory type id.
+    pub types: Vec<MemoryTypeUtilization>,
+}
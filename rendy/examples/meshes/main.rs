@@ -223,7 +223,7 @@ where
 
     fn prepare(
         &mut self,
-        factory: &mut Factory<B>,
+        factory: &Factory<B>,
         set_layouts: &[B::DescriptorSetLayout],
         index: usize,
         scene: &Scene<B>,
@@ -303,7 +303,7 @@ where
 
     fn prepare(
         &mut self,
-        _factory: &mut Factory<B>,
+        _factory: &Factory<B>,
         _sets: &[B::DescriptorSetLayout],
         _index: usize,
         _aux: &T,
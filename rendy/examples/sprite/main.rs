@@ -14,7 +14,7 @@ use rendy::{
     memory::MemoryUsageValue,
     mesh::{AsVertex, PosTex},
     resource::buffer::Buffer,
-    shader::{Shader, ShaderKind, SourceLanguage, StaticShaderInfo},
+    shader::{Shader, ShaderKind, SourceLanguage, StaticReloadableShader, StaticShaderInfo},
     texture::{pixel::Rgba8Srgb, Texture, TextureBuilder},
 };
 
@@ -30,19 +30,19 @@ type Backend = rendy::metal::Backend;
 type Backend = rendy::vulkan::Backend;
 
 lazy_static::lazy_static! {
-    static ref VERTEX: StaticShaderInfo = StaticShaderInfo::new(
+    static ref VERTEX: StaticReloadableShader = StaticReloadableShader::new(StaticShaderInfo::new(
         concat!(env!("CARGO_MANIFEST_DIR"), "/examples/sprite/shader.vert"),
         ShaderKind::Vertex,
         SourceLanguage::GLSL,
         "main",
-    );
+    ));
 
-    static ref FRAGMENT: StaticShaderInfo = StaticShaderInfo::new(
+    static ref FRAGMENT: StaticReloadableShader = StaticReloadableShader::new(StaticShaderInfo::new(
         concat!(env!("CARGO_MANIFEST_DIR"), "/examples/sprite/shader.frag"),
         ShaderKind::Fragment,
         SourceLanguage::GLSL,
         "main",
-    );
+    ));
 }
 
 #[derive(Debug)]
@@ -79,28 +79,19 @@ where
         factory: &mut Factory<B>,
         _aux: &mut T,
     ) -> gfx_hal::pso::GraphicsShaderSet<'b, B> {
-        storage.clear();
-
-        log::trace!("Load shader module '{:#?}'", *VERTEX);
-        storage.push(VERTEX.module(factory).unwrap());
-
-        log::trace!("Load shader module '{:#?}'", *FRAGMENT);
-        storage.push(FRAGMENT.module(factory).unwrap());
-
-        gfx_hal::pso::GraphicsShaderSet {
-            vertex: gfx_hal::pso::EntryPoint {
-                entry: "main",
-                module: &storage[0],
-                specialization: gfx_hal::pso::Specialization::default(),
-            },
-            fragment: Some(gfx_hal::pso::EntryPoint {
-                entry: "main",
-                module: &storage[1],
-                specialization: gfx_hal::pso::Specialization::default(),
-            }),
-            hull: None,
-            domain: None,
-            geometry: None,
+        load_shaders(storage, factory).unwrap()
+    }
+
+    fn reload_shader_set<'b>(
+        &self,
+        storage: &'b mut Vec<B::ShaderModule>,
+        factory: &Factory<B>,
+        _aux: &T,
+    ) -> Option<Result<gfx_hal::pso::GraphicsShaderSet<'b, B>, failure::Error>> {
+        if VERTEX.changed() || FRAGMENT.changed() {
+            Some(load_shaders(storage, factory))
+        } else {
+            None
         }
     }
 
@@ -232,7 +223,7 @@ where
 
     fn prepare(
         &mut self,
-        factory: &mut Factory<B>,
+        factory: &Factory<B>,
         _set_layouts: &[B::DescriptorSetLayout],
         _index: usize,
         _aux: &T,
@@ -312,6 +303,35 @@ where
     fn dispose(self, _factory: &mut Factory<B>, _aux: &mut T) {}
 }
 
+fn load_shaders<'b, B: gfx_hal::Backend>(
+    storage: &'b mut Vec<B::ShaderModule>,
+    factory: &Factory<B>,
+) -> Result<gfx_hal::pso::GraphicsShaderSet<'b, B>, failure::Error> {
+    storage.clear();
+
+    log::trace!("Load shader module '{:#?}'", *VERTEX);
+    storage.push(VERTEX.module(factory)?);
+
+    log::trace!("Load shader module '{:#?}'", *FRAGMENT);
+    storage.push(FRAGMENT.module(factory)?);
+
+    Ok(gfx_hal::pso::GraphicsShaderSet {
+        vertex: gfx_hal::pso::EntryPoint {
+            entry: "main",
+            module: &storage[0],
+            specialization: gfx_hal::pso::Specialization::default(),
+        },
+        fragment: Some(gfx_hal::pso::EntryPoint {
+            entry: "main",
+            module: &storage[1],
+            specialization: gfx_hal::pso::Specialization::default(),
+        }),
+        hull: None,
+        domain: None,
+        geometry: None,
+    })
+}
+
 #[cfg(any(feature = "dx12", feature = "metal", feature = "vulkan"))]
 fn run(
     event_loop: &mut EventsLoop,
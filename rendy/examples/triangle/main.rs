@@ -20,7 +20,7 @@ use rendy::{
     memory::MemoryUsageValue,
     mesh::{AsVertex, PosColor},
     resource::buffer::Buffer,
-    shader::{Shader, ShaderKind, SourceLanguage, StaticShaderInfo},
+    shader::Shader,
 };
 
 use winit::{EventsLoop, WindowBuilder};
@@ -34,21 +34,19 @@ type Backend = rendy::metal::Backend;
 #[cfg(feature = "vulkan")]
 type Backend = rendy::vulkan::Backend;
 
-lazy_static::lazy_static! {
-    static ref VERTEX: StaticShaderInfo = StaticShaderInfo::new(
-        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.vert"),
-        ShaderKind::Vertex,
-        SourceLanguage::GLSL,
-        "main",
-    );
+rendy::shader::compile_to_spirv!(
+    struct TriangleVertex {
+        kind: Vertex,
+        lang: GLSL,
+        file: "examples/triangle/shader.vert",
+    }
 
-    static ref FRAGMENT: StaticShaderInfo = StaticShaderInfo::new(
-        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.frag"),
-        ShaderKind::Fragment,
-        SourceLanguage::GLSL,
-        "main",
-    );
-}
+    struct TriangleFragment {
+        kind: Fragment,
+        lang: GLSL,
+        file: "examples/triangle/shader.frag",
+    }
+);
 
 #[derive(Debug)]
 struct TriangleRenderGroup<B: gfx_hal::Backend> {
@@ -83,11 +81,11 @@ where
     ) -> gfx_hal::pso::GraphicsShaderSet<'a, B> {
         storage.clear();
 
-        log::trace!("Load shader module '{:#?}'", *VERTEX);
-        storage.push(VERTEX.module(factory).unwrap());
+        log::trace!("Load shader module '{:#?}'", TriangleVertex);
+        storage.push(TriangleVertex.module(factory).unwrap());
 
-        log::trace!("Load shader module '{:#?}'", *FRAGMENT);
-        storage.push(FRAGMENT.module(factory).unwrap());
+        log::trace!("Load shader module '{:#?}'", TriangleFragment);
+        storage.push(TriangleFragment.module(factory).unwrap());
 
         gfx_hal::pso::GraphicsShaderSet {
             vertex: gfx_hal::pso::EntryPoint {
@@ -122,7 +120,7 @@ where
 
     fn prepare(
         &mut self,
-        factory: &mut Factory<B>,
+        factory: &Factory<B>,
         _set_layouts: &[B::DescriptorSetLayout],
         _index: usize,
         _aux: &T,
@@ -13,7 +13,7 @@ use crate::{
 };
 
 /// Buffer info.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Info {
     /// Buffer size.
     pub size: u64,
@@ -46,7 +46,8 @@ impl<B> Inner<B>
 where
     B: gfx_hal::Backend,
 {
-    pub(super) fn dispose(self) -> (B::Buffer, MemoryBlock<B>) {
+    #[doc(hidden)]
+    pub fn dispose(self) -> (B::Buffer, MemoryBlock<B>) {
         self.relevant.dispose();
         (self.raw, self.block)
     }
@@ -121,10 +122,15 @@ where
         self.escape.block.map(device, range)
     }
 
-    // /// Map range of the buffer to the CPU accessible memory.
-    // pub fn persistent_map(&mut self, range: std::ops::Range<u64>) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
-    //     self.escape.block.map(device, range)
-    // }
+    /// Get mapping for the range of the buffer without remapping memory.
+    /// Buffers created with `Dynamic` and `Upload` usage are kept mapped.
+    /// Fails with `MappingFailed` if the buffer memory isn't mapped.
+    pub fn persistent_map<'a>(
+        &'a mut self,
+        range: std::ops::Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::mapping::Error> {
+        self.escape.block.persistent_map(range)
+    }
 
     /// Get raw buffer handle.
     ///
@@ -135,6 +141,11 @@ where
         &self.escape.raw
     }
 
+    /// Get memory block bound to the buffer.
+    pub fn block(&self) -> &MemoryBlock<B> {
+        &self.escape.block
+    }
+
     /// Get buffer info.
     pub fn info(&self) -> &Info {
         &self.info
@@ -13,7 +13,7 @@ use crate::{
 };
 
 /// Image info.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Info {
     /// Kind of the image.
     pub kind: Kind,
@@ -122,6 +122,12 @@ where
         &self.escape.raw
     }
 
+    /// Get memory block owned by the image.
+    /// Images bound to memory they don't own have no block.
+    pub fn block(&self) -> Option<&MemoryBlock<B>> {
+        self.escape.block.as_ref()
+    }
+
     /// Get image [`Info`].
     ///
     /// [`Info`]: struct.Info.html
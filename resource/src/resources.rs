@@ -195,6 +195,55 @@ where
         })
     }
 
+    /// Create an image without memory bound to it.
+    /// Returns raw image with its memory requirements.
+    /// Memory must be bound with `bind_image` before image is used.
+    pub fn create_unbound_image(
+        &self,
+        device: &impl gfx_hal::Device<B>,
+        info: &image::Info,
+    ) -> Result<(B::Image, gfx_hal::memory::Requirements), failure::Error> {
+        log::trace!("Create unbound image: {:#?}", info);
+        let img = unsafe {
+            device.create_image(
+                info.kind,
+                info.levels,
+                info.format,
+                info.tiling,
+                info.usage,
+                info.view_caps,
+            )
+        }?;
+        let reqs = unsafe { device.get_image_requirements(&img) };
+        Ok((img, reqs))
+    }
+
+    /// Bind memory of the block at `offset` to the raw image.
+    /// Image doesn't own the memory,
+    /// so the same memory can be bound to multiple images.
+    ///
+    /// # Safety
+    ///
+    /// * `raw` must be created by `create_unbound_image` with the same `info`.
+    /// * Memory region must satisfy image memory requirements.
+    /// * Memory block must not be freed until image is destroyed.
+    ///
+    /// Raw image is destroyed if binding fails.
+    pub unsafe fn bind_image(
+        &self,
+        device: &impl gfx_hal::Device<B>,
+        mut raw: B::Image,
+        info: image::Info,
+        block: &impl Block<B>,
+        offset: u64,
+    ) -> Result<image::Image<B>, failure::Error> {
+        if let Err(err) = device.bind_image_memory(block.memory(), offset, &mut raw) {
+            device.destroy_image(raw);
+            return Err(err.into());
+        }
+        Ok(image::Image::new(info, raw, None, &self.images))
+    }
+
     /// Create an image view.
     pub fn create_image_view(
         &self,
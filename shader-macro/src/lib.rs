@@ -0,0 +1,312 @@
+//! Build-time shader compilation.
+//! Use `rendy_shader::compile_to_spirv!` instead of this crate directly.
+
+#![recursion_limit = "128"]
+
+extern crate proc_macro;
+
+use {
+    proc_macro2::{Span, TokenStream, TokenTree},
+    quote::quote,
+    rendy_util::{resolve_include, vk_make_version},
+    syn::{
+        braced, bracketed,
+        parse::{Parse, ParseStream},
+        punctuated::Punctuated,
+        Attribute, Ident, LitStr, Token, Visibility,
+    },
+};
+
+/// Path to `rendy_shader` crate followed by shader declarations.
+struct Input {
+    krate: TokenStream,
+    shaders: Vec<ShaderDecl>,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        // `$crate` of `rendy_shader` is passed through by the wrapper macro.
+        let krate = input.step(|cursor| {
+            let mut rest = *cursor;
+            let mut tokens = Vec::new();
+            while let Some((tt, next)) = rest.token_tree() {
+                match tt {
+                    TokenTree::Punct(ref punct) if punct.as_char() == ';' => {
+                        return Ok((tokens.into_iter().collect::<TokenStream>(), next));
+                    }
+                    tt => {
+                        tokens.push(tt);
+                        rest = next;
+                    }
+                }
+            }
+            Err(cursor.error("expected `;` after crate path"))
+        })?;
+
+        let mut shaders = Vec::new();
+        while !input.is_empty() {
+            shaders.push(input.parse()?);
+        }
+
+        Ok(Input { krate, shaders })
+    }
+}
+
+/// `struct Name { kind: Vertex, lang: GLSL, file: "path", entry: "main", defines: { NAME: "value" }, include: ["dir"] }`
+struct ShaderDecl {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    ident: Ident,
+    kind: Ident,
+    lang: Option<Ident>,
+    file: LitStr,
+    entry: Option<LitStr>,
+    defines: Vec<Define>,
+    include: Vec<LitStr>,
+}
+
+/// `NAME: "value"`
+struct Define {
+    name: Ident,
+    value: LitStr,
+}
+
+impl Parse for Define {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let value = input.parse()?;
+        Ok(Define { name, value })
+    }
+}
+
+enum Field {
+    Kind(Ident),
+    Lang(Ident),
+    File(LitStr),
+    Entry(LitStr),
+    Defines(Vec<Define>),
+    Include(Vec<LitStr>),
+}
+
+impl Parse for Field {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        match name.to_string().as_str() {
+            "kind" => Ok(Field::Kind(input.parse()?)),
+            "lang" => Ok(Field::Lang(input.parse()?)),
+            "file" => Ok(Field::File(input.parse()?)),
+            "entry" => Ok(Field::Entry(input.parse()?)),
+            "defines" => {
+                let content;
+                braced!(content in input);
+                let defines = Punctuated::<Define, Token![,]>::parse_terminated(&content)?;
+                Ok(Field::Defines(defines.into_iter().collect()))
+            }
+            "include" => {
+                let content;
+                bracketed!(content in input);
+                let include = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                Ok(Field::Include(include.into_iter().collect()))
+            }
+            _ => Err(syn::Error::new(
+                name.span(),
+                "expected one of `kind`, `lang`, `file`, `entry`, `defines`, `include`",
+            )),
+        }
+    }
+}
+
+impl Parse for ShaderDecl {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![struct]>()?;
+        let ident: Ident = input.parse()?;
+
+        let content;
+        braced!(content in input);
+        let fields = Punctuated::<Field, Token![,]>::parse_terminated(&content)?;
+
+        let (mut kind, mut lang, mut file, mut entry) = (None, None, None, None);
+        let (mut defines, mut include) = (Vec::new(), Vec::new());
+        for field in fields {
+            match field {
+                Field::Kind(value) => kind = Some(value),
+                Field::Lang(value) => lang = Some(value),
+                Field::File(value) => file = Some(value),
+                Field::Entry(value) => entry = Some(value),
+                Field::Defines(value) => defines = value,
+                Field::Include(value) => include = value,
+            }
+        }
+
+        Ok(ShaderDecl {
+            attrs,
+            vis,
+            kind: kind.ok_or_else(|| syn::Error::new(ident.span(), "missing `kind` field"))?,
+            lang,
+            file: file.ok_or_else(|| syn::Error::new(ident.span(), "missing `file` field"))?,
+            entry,
+            defines,
+            include,
+            ident,
+        })
+    }
+}
+
+fn shader_kind(kind: &Ident) -> syn::Result<shaderc::ShaderKind> {
+    Ok(match kind.to_string().as_str() {
+        "Vertex" => shaderc::ShaderKind::Vertex,
+        "Fragment" => shaderc::ShaderKind::Fragment,
+        "Compute" => shaderc::ShaderKind::Compute,
+        "Geometry" => shaderc::ShaderKind::Geometry,
+        "TessControl" => shaderc::ShaderKind::TessControl,
+        "TessEvaluation" => shaderc::ShaderKind::TessEvaluation,
+        _ => {
+            return Err(syn::Error::new(
+                kind.span(),
+                "expected one of `Vertex`, `Fragment`, `Compute`, `Geometry`, `TessControl`, `TessEvaluation`",
+            ))
+        }
+    })
+}
+
+fn source_language(lang: Option<&Ident>) -> syn::Result<shaderc::SourceLanguage> {
+    match lang.map(|lang| lang.to_string()) {
+        None => Ok(shaderc::SourceLanguage::GLSL),
+        Some(ref lang) if lang == "GLSL" => Ok(shaderc::SourceLanguage::GLSL),
+        Some(ref lang) if lang == "HLSL" => Ok(shaderc::SourceLanguage::HLSL),
+        Some(_) => Err(syn::Error::new(
+            lang.unwrap().span(),
+            "expected one of `GLSL`, `HLSL`",
+        )),
+    }
+}
+
+/// Compile shader file with the same options `ShaderInfo` uses by default.
+/// Include directories are relative to `manifest_dir`
+/// and searched before the directory of the shader.
+/// Returns SPIR-V words and files included by the shader.
+/// Errors point at the path literal and contain shaderc diagnostics
+/// with file and line of the shader source.
+fn compile(
+    decl: &ShaderDecl,
+    manifest_dir: &std::path::Path,
+    path: &std::path::Path,
+) -> syn::Result<(Vec<u32>, Vec<std::path::PathBuf>)> {
+    let kind = shader_kind(&decl.kind)?;
+    let lang = source_language(decl.lang.as_ref())?;
+    let entry = decl
+        .entry
+        .as_ref()
+        .map_or_else(|| "main".to_owned(), LitStr::value);
+    let span = decl.file.span();
+
+    let code = std::fs::read_to_string(path).map_err(|err| {
+        syn::Error::new(
+            span,
+            format!("Failed to read '{}': {}", path.display(), err),
+        )
+    })?;
+
+    let mut compiler = shaderc::Compiler::new()
+        .ok_or_else(|| syn::Error::new(Span::call_site(), "Failed to init Shaderc"))?;
+    let mut ops = shaderc::CompileOptions::new()
+        .ok_or_else(|| syn::Error::new(Span::call_site(), "Failed to init Shaderc"))?;
+    ops.set_target_env(shaderc::TargetEnv::Vulkan, vk_make_version!(1, 0, 0));
+    ops.set_source_language(lang);
+    ops.set_optimization_level(shaderc::OptimizationLevel::Performance);
+    for define in &decl.defines {
+        ops.add_macro_definition(
+            &define.name.to_string(),
+            Some(define.value.value().as_str()),
+        );
+    }
+
+    let include_paths: Vec<_> = decl
+        .include
+        .iter()
+        .map(|dir| manifest_dir.join(dir.value()))
+        .collect();
+    let dirs: Vec<_> = include_paths
+        .iter()
+        .map(|dir| dir.as_path())
+        .chain(path.parent())
+        .collect();
+
+    let includes = std::cell::RefCell::new(Vec::new());
+    ops.set_include_callback(|name, ty, source, _depth| {
+        let relative = ty == shaderc::IncludeType::Relative;
+        let file = resolve_include(name, relative, source.as_ref(), &dirs)?;
+        let content = std::fs::read_to_string(&file)
+            .map_err(|err| format!("Failed to read '{}': {}", file.display(), err))?;
+        let resolved_name = file.display().to_string();
+        let mut includes = includes.borrow_mut();
+        if !includes.contains(&file) {
+            includes.push(file);
+        }
+        Ok(shaderc::ResolvedInclude {
+            resolved_name,
+            content,
+        })
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(&code, kind, &path.display().to_string(), &entry, Some(&ops))
+        .map_err(|err| syn::Error::new(span, format!("Failed to compile shader:\n{}", err)))?;
+    drop(ops);
+
+    Ok((artifact.as_binary().to_vec(), includes.into_inner()))
+}
+
+fn expand(krate: &TokenStream, decl: &ShaderDecl) -> syn::Result<TokenStream> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(Span::call_site(), "CARGO_MANIFEST_DIR is not set"))?;
+    let manifest_dir = std::path::Path::new(&manifest_dir);
+    let path = manifest_dir.join(decl.file.value());
+    let (words, includes) = compile(decl, manifest_dir, &path)?;
+    let files: Vec<String> = std::iter::once(path)
+        .chain(includes)
+        .map(|path| path.display().to_string())
+        .collect();
+
+    let ShaderDecl {
+        attrs, vis, ident, ..
+    } = decl;
+
+    Ok(quote! {
+        #(#attrs)*
+        #[derive(Clone, Copy, Debug, Default)]
+        #vis struct #ident;
+
+        impl #ident {
+            /// Compiled SPIR-V words.
+            pub const SPIRV: &'static [u32] = &[#(#words),*];
+
+            // Rebuild when shader source or included files change.
+            #[allow(dead_code)]
+            const SOURCES: &'static [&'static [u8]] = &[#(include_bytes!(#files)),*];
+        }
+
+        impl #krate::Shader for #ident {
+            fn spirv(&self) -> std::result::Result<std::borrow::Cow<'static, [u8]>, #krate::__failure::Error> {
+                Ok(std::borrow::Cow::Borrowed(#krate::__spirv_bytes(Self::SPIRV)))
+            }
+        }
+    })
+}
+
+/// Compile shaders and embed SPIR-V into the binary.
+/// Invoked by `rendy_shader::compile_to_spirv!`.
+#[proc_macro]
+pub fn compile_to_spirv(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let Input { krate, shaders } = syn::parse_macro_input!(input as Input);
+
+    shaders
+        .iter()
+        .map(|decl| expand(&krate, decl).unwrap_or_else(|err| err.to_compile_error()))
+        .collect::<TokenStream>()
+        .into()
+}
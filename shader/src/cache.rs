@@ -0,0 +1,278 @@
+use {
+    crate::{modification_time, FileStamp},
+    rendy_util::resolve_include,
+    std::{
+        hash::{Hash, Hasher},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Bumped when layout of cache key or files changes.
+const CACHE_VERSION: u32 = 1;
+
+const SPIRV_MAGIC: [u8; 4] = [0x03, 0x02, 0x23, 0x07];
+
+/// Persistent cache of compiled SPIR-V.
+///
+/// Shaders are stored in the directory by hash of their source,
+/// included files, kind, entry point and compile options.
+/// Changing any of them makes shader compile again.
+/// Files are written atomically so several processes can share the directory.
+///
+/// Stale shaders are never removed automatically,
+/// so the directory grows with every changed source or options.
+/// `clear` is the only way to bound its size.
+#[derive(Clone, Debug)]
+pub struct SpirvCache {
+    dir: PathBuf,
+}
+
+impl SpirvCache {
+    /// Cache in the directory.
+    /// Directory is created on first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SpirvCache { dir: dir.into() }
+    }
+
+    /// Get cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Remove all cached shaders.
+    pub fn clear(&self) -> std::io::Result<()> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "spv") {
+                remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove cached shader.
+    pub(crate) fn remove(&self, key: u64) -> std::io::Result<()> {
+        remove_file(&self.path(key))
+    }
+
+    /// Load cached shader.
+    /// Returns `None` if shader is not cached or cached file is corrupted.
+    pub(crate) fn load(&self, key: u64) -> Option<Vec<u8>> {
+        let spirv = std::fs::read(self.path(key)).ok()?;
+        if spirv.len() % 4 != 0 || !spirv.starts_with(&SPIRV_MAGIC) {
+            return None;
+        }
+        Some(spirv)
+    }
+
+    /// Store compiled shader.
+    /// Shader is written to temporary file first and then renamed,
+    /// so readers never see partially written file and concurrent writers don't interleave.
+    pub(crate) fn store(&self, key: u64, spirv: &[u8]) -> std::io::Result<()> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        std::fs::create_dir_all(&self.dir)?;
+        let temp = self.dir.join(format!(
+            "{:016x}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, spirv)?;
+
+        let path = self.path(key);
+        match std::fs::rename(&temp, &path) {
+            Ok(()) => Ok(()),
+            // Some platforms can't replace existing file.
+            // Other writer stored the same shader.
+            Err(_) if path.is_file() => remove_file(&temp),
+            Err(err) => {
+                let _ = remove_file(&temp);
+                Err(err)
+            }
+        }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.spv", key))
+    }
+}
+
+fn remove_file(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// FNV-1a hasher.
+/// Unlike `DefaultHasher` it is guaranteed to give the same hashes in different builds.
+#[derive(Debug)]
+pub(crate) struct CacheHasher(u64);
+
+impl CacheHasher {
+    pub(crate) fn new() -> Self {
+        let mut hasher = CacheHasher(0xcbf2_9ce4_8422_2325);
+        CACHE_VERSION.hash(&mut hasher);
+        hasher
+    }
+}
+
+impl Hasher for CacheHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Hash files included by the source and push them to `files`
+/// with modification times taken before reading.
+/// Directives in inactive preprocessor branches are followed too,
+/// which may only cause unnecessary recompilation.
+pub(crate) fn hash_includes(
+    code: &str,
+    source: &Path,
+    dirs: &[&Path],
+    hasher: &mut CacheHasher,
+    files: &mut Vec<FileStamp>,
+) {
+    for (name, ty) in code.lines().filter_map(parse_include) {
+        let relative = ty == shaderc::IncludeType::Relative;
+        let path = match resolve_include(name, relative, source, dirs) {
+            Ok(path) => path,
+            Err(_) => {
+                // Compilation fails unless the directive is inactive.
+                name.hash(hasher);
+                continue;
+            }
+        };
+
+        path.hash(hasher);
+        if files.iter().any(|(file, _)| *file == path) {
+            continue;
+        }
+
+        let modified = modification_time(&path);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                content.hash(hasher);
+                files.push((path.clone(), modified));
+                hash_includes(&content, &path, dirs, hasher, files);
+            }
+            Err(_) => files.push((path, modified)),
+        }
+    }
+}
+
+/// Parse `#include "name"` or `#include <name>` directive.
+fn parse_include(line: &str) -> Option<(&str, shaderc::IncludeType)> {
+    let line = line.trim_start();
+    if !line.starts_with('#') {
+        return None;
+    }
+    let line = line[1..].trim_start();
+    if !line.starts_with("include") {
+        return None;
+    }
+    let line = line["include".len()..].trim_start();
+
+    let (close, ty) = match line.chars().next() {
+        Some('"') => ('"', shaderc::IncludeType::Relative),
+        Some('<') => ('>', shaderc::IncludeType::Standard),
+        _ => return None,
+    };
+    let name = &line[1..];
+    name.find(close).map(|end| (&name[..end], ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, shaderc::IncludeType};
+
+    #[test]
+    fn parse_include_directives() {
+        let parsed = |line| match parse_include(line) {
+            Some((name, IncludeType::Relative)) => Some((name, '"')),
+            Some((name, IncludeType::Standard)) => Some((name, '<')),
+            None => None,
+        };
+
+        assert_eq!(
+            parsed("#include \"common.glsl\""),
+            Some(("common.glsl", '"'))
+        );
+        assert_eq!(
+            parsed("  #  include <lib/light.glsl> // comment"),
+            Some(("lib/light.glsl", '<'))
+        );
+        assert_eq!(parsed("#include \"unterminated"), None);
+        assert_eq!(parsed("#include common.glsl"), None);
+        assert_eq!(parsed("#define include"), None);
+        assert_eq!(parsed("// #include \"common.glsl\""), None);
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        // FNV-1a test vector.
+        let mut hasher = CacheHasher(0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        // Cache keys must not change between builds unless `CACHE_VERSION` is bumped.
+        let mut hasher = CacheHasher::new();
+        hasher.write(b"rendy");
+        if cfg!(target_endian = "little") {
+            assert_eq!(hasher.finish(), 0x8c1a_8ff2_0380_9462);
+        }
+    }
+
+    #[test]
+    fn store_load_remove() {
+        let dir = std::env::temp_dir().join(format!("rendy-spirv-cache-{}", std::process::id()));
+        let cache = SpirvCache::new(&dir);
+        let spirv = [&SPIRV_MAGIC[..], &[1, 0, 0, 0]].concat();
+
+        assert_eq!(cache.load(1), None);
+        cache.store(1, &spirv).unwrap();
+        assert_eq!(cache.load(1), Some(spirv.clone()));
+
+        // Overwriting is fine.
+        cache.store(1, &spirv).unwrap();
+        assert_eq!(cache.load(1), Some(spirv.clone()));
+
+        // Corrupted files are ignored.
+        cache.store(2, &[0; 8]).unwrap();
+        assert_eq!(cache.load(2), None);
+        cache.store(3, &spirv[..6]).unwrap();
+        assert_eq!(cache.load(3), None);
+
+        cache.remove(1).unwrap();
+        assert_eq!(cache.load(1), None);
+        cache.remove(1).unwrap();
+
+        std::fs::write(dir.join("other"), b"other").unwrap();
+        cache.clear().unwrap();
+        let mut left = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, vec![std::ffi::OsString::from("other")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        cache.clear().unwrap();
+    }
+}
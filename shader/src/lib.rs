@@ -10,14 +10,67 @@
     unused_import_braces,
     unused_qualifications
 )]
-pub use shaderc::{self, ShaderKind, SourceLanguage};
+pub use shaderc::{self, OptimizationLevel, ShaderKind, SourceLanguage, TargetEnv};
 
-macro_rules! vk_make_version {
-    ($major: expr, $minor: expr, $patch: expr) => {
-        (($major as u32) << 22) | (($minor as u32) << 12) | $patch as u32
+use rendy_util::{resolve_include, vk_make_version};
+
+mod cache;
+mod permutations;
+mod reflect;
+mod reload;
+
+pub use self::{cache::*, permutations::*, reflect::*, reload::*};
+
+#[doc(hidden)]
+pub use {failure as __failure, rendy_shader_macro::compile_to_spirv as __compile_to_spirv};
+
+/// Compile shaders at build time and embed SPIR-V into the binary.
+///
+/// Each declaration defines unit struct that implements [`Shader`].
+/// Paths are relative to the `CARGO_MANIFEST_DIR` of the crate being compiled.
+/// `lang` defaults to `GLSL` and `entry` defaults to `"main"`.
+/// `defines` and `include` match `ShaderInfo::with_define` and `ShaderInfo::with_include_path`,
+/// and shader is compiled with the defaults of `ShaderInfo`.
+/// Shader compilation errors are reported as Rust compilation errors.
+/// `#include` directives are resolved relative to the including file,
+/// then in `include` directories and the directory of the shader.
+/// Crate is rebuilt when the shader or any file it includes changes.
+///
+/// ```ignore
+/// rendy_shader::compile_to_spirv!(
+///     /// Vertex shader of the triangle.
+///     struct TriangleVertex {
+///         kind: Vertex,
+///         lang: GLSL,
+///         file: "shaders/triangle.vert",
+///         entry: "main",
+///     }
+///
+///     struct TriangleFragment {
+///         kind: Fragment,
+///         file: "shaders/triangle.frag",
+///         defines: { LIGHTS: "4" },
+///         include: ["shaders/common"],
+///     }
+/// );
+/// ```
+///
+/// [`Shader`]: trait.Shader.html
+#[macro_export]
+macro_rules! compile_to_spirv {
+    ($($tokens:tt)*) => {
+        $crate::__compile_to_spirv!($crate; $($tokens)*);
     };
 }
 
+#[doc(hidden)]
+pub fn __spirv_bytes(words: &'static [u32]) -> &'static [u8] {
+    unsafe {
+        // Any `u32` is valid sequence of bytes.
+        std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 4)
+    }
+}
+
 /// Interface to create shader modules from shaders.
 /// Implemented for static shaders via [`compile_to_spirv!`] macro.
 ///
@@ -36,58 +89,313 @@ pub trait Shader {
         unsafe { gfx_hal::Device::create_shader_module(factory.device(), &self.spirv()?) }
             .map_err(Into::into)
     }
+
+    /// Reflect descriptor bindings, push constants and inputs of the shader.
+    fn reflect(&self) -> Result<Reflection, failure::Error> {
+        Ok(Reflection::new(&self.spirv()?)?)
+    }
 }
 
+/// Preprocessor macro definitions by name.
+pub type Defines = std::collections::BTreeMap<String, String>;
+
 /// Dynamic shader.
-#[derive(Clone, Copy, Debug)]
+///
+/// `ShaderInfo` is not `Copy` since it owns preprocessor defines, include paths and cache settings.
+/// Use `clone` where it was copied before.
+#[derive(Clone, Debug)]
 pub struct ShaderInfo<P, E> {
     path: P,
     kind: ShaderKind,
     lang: SourceLanguage,
     entry: E,
+    defines: Defines,
+    include_paths: Vec<std::path::PathBuf>,
+    target_env: (TargetEnv, u32),
+    optimization_level: OptimizationLevel,
+    debug_info: bool,
+    cache: Option<SpirvCache>,
 }
 
 impl<P, E> ShaderInfo<P, E> {
     /// New dynamic shader.
+    /// Shader is compiled for Vulkan 1.0 with `Performance` optimization level by default.
     pub fn new(path: P, kind: ShaderKind, lang: SourceLanguage, entry: E) -> Self {
         ShaderInfo {
             path: path.into(),
             kind,
             lang,
             entry: entry.into(),
+            defines: Defines::new(),
+            include_paths: Vec::new(),
+            target_env: (TargetEnv::Vulkan, vk_make_version!(1, 0, 0)),
+            optimization_level: OptimizationLevel::Performance,
+            debug_info: false,
+            cache: None,
+        }
+    }
+
+    /// Get preprocessor macro definitions.
+    pub fn defines(&self) -> &Defines {
+        &self.defines
+    }
+
+    /// Define preprocessor macro.
+    pub fn add_define(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    /// Define preprocessor macro.
+    pub fn with_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.add_define(name, value);
+        self
+    }
+
+    /// Add directory to search files included with `#include`.
+    /// Directories are searched in order they were added, followed by the directory of the shader.
+    pub fn add_include_path(&mut self, path: impl Into<std::path::PathBuf>) -> &mut Self {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    /// Add directory to search files included with `#include`.
+    /// Directories are searched in order they were added, followed by the directory of the shader.
+    pub fn with_include_path(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.add_include_path(path);
+        self
+    }
+
+    /// Set target environment and its version.
+    pub fn set_target_env(&mut self, env: TargetEnv, version: u32) -> &mut Self {
+        self.target_env = (env, version);
+        self
+    }
+
+    /// Set target environment and its version.
+    pub fn with_target_env(mut self, env: TargetEnv, version: u32) -> Self {
+        self.set_target_env(env, version);
+        self
+    }
+
+    /// Set optimization level.
+    pub fn set_optimization_level(&mut self, level: OptimizationLevel) -> &mut Self {
+        self.optimization_level = level;
+        self
+    }
+
+    /// Set optimization level.
+    pub fn with_optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.set_optimization_level(level);
+        self
+    }
+
+    /// Set if debug information should be generated.
+    pub fn set_debug_info(&mut self, debug_info: bool) -> &mut Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Set if debug information should be generated.
+    pub fn with_debug_info(mut self, debug_info: bool) -> Self {
+        self.set_debug_info(debug_info);
+        self
+    }
+
+    /// Load compiled shader from the cache and store it there after compilation.
+    pub fn set_cache(&mut self, cache: SpirvCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Load compiled shader from the cache and store it there after compilation.
+    pub fn with_cache(mut self, cache: SpirvCache) -> Self {
+        self.set_cache(cache);
+        self
+    }
+
+    /// Always compile shader.
+    pub fn set_no_cache(&mut self) -> &mut Self {
+        self.cache = None;
+        self
+    }
+
+    /// Always compile shader.
+    pub fn with_no_cache(mut self) -> Self {
+        self.set_no_cache();
+        self
+    }
+}
+
+impl<P, E> ShaderInfo<P, E>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    E: AsRef<str>,
+{
+    /// Remove cached shader compiled from current source with additional `defines`.
+    /// Pass empty `defines` for the shader compiled with its own defines only.
+    /// Does nothing if shader doesn't use cache.
+    pub fn invalidate_cache(&self, defines: &Defines) -> Result<(), failure::Error> {
+        if let Some(cache) = &self.cache {
+            let code = std::fs::read_to_string(self.path.as_ref())?;
+            let (key, _) = self.cache_key(&code, &self.all_defines(defines), &self.include_dirs());
+            cache.remove(key)?;
+        }
+        Ok(())
+    }
+
+    /// Defines of the shader extended with additional `defines`.
+    fn all_defines(&self, defines: &Defines) -> Defines {
+        let mut all_defines = self.defines.clone();
+        all_defines.extend(
+            defines
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        all_defines
+    }
+
+    /// Directories to search included files.
+    fn include_dirs(&self) -> Vec<&std::path::Path> {
+        self.include_paths
+            .iter()
+            .map(|path| path.as_path())
+            .chain(self.path.as_ref().parent())
+            .collect()
+    }
+
+    /// Hash everything compilation result depends on.
+    /// Returns the hash and included files with their modification times.
+    fn cache_key(
+        &self,
+        code: &str,
+        defines: &Defines,
+        dirs: &[&std::path::Path],
+    ) -> (u64, Vec<FileStamp>) {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = CacheHasher::new();
+        let path = self.path.as_ref();
+        path.hash(&mut hasher);
+        code.hash(&mut hasher);
+        format!("{:?}", self.kind).hash(&mut hasher);
+        format!("{:?}", self.lang).hash(&mut hasher);
+        self.entry.as_ref().hash(&mut hasher);
+        defines.hash(&mut hasher);
+        format!("{:?}", self.target_env).hash(&mut hasher);
+        format!("{:?}", self.optimization_level).hash(&mut hasher);
+        self.debug_info.hash(&mut hasher);
+
+        let mut files = Vec::new();
+        hash_includes(code, path, dirs, &mut hasher, &mut files);
+        (hasher.finish(), files)
+    }
+
+    /// Compile shader with additional `defines`.
+    /// Source and files included by the shader are pushed to `files`
+    /// even if compilation fails.
+    /// Modification time of each file is taken before the file is read,
+    /// so changes made during compilation are not missed.
+    /// Cached shader is returned without compilation if available.
+    fn compile(
+        &self,
+        defines: &Defines,
+        files: &mut Vec<FileStamp>,
+    ) -> Result<Vec<u8>, failure::Error> {
+        let path = self.path.as_ref();
+        files.push((path.to_owned(), modification_time(path)));
+        let code = std::fs::read_to_string(path)?;
+        let dirs = self.include_dirs();
+
+        let all_defines = self.all_defines(defines);
+
+        let cached = match &self.cache {
+            Some(cache) => {
+                let (key, includes) = self.cache_key(&code, &all_defines, &dirs);
+                if let Some(spirv) = cache.load(key) {
+                    log::trace!("Shader {:?} is loaded from cache", self.path);
+                    files.extend(includes);
+                    return Ok(spirv);
+                }
+                Some((cache, key))
+            }
+            None => None,
+        };
+
+        let resolved = std::cell::RefCell::new(Vec::new());
+
+        let result = {
+            let mut ops = shaderc::CompileOptions::new()
+                .ok_or_else(|| failure::format_err!("Failed to init Shaderc"))?;
+            ops.set_target_env(self.target_env.0, self.target_env.1);
+            ops.set_source_language(self.lang);
+            ops.set_optimization_level(self.optimization_level);
+            if self.debug_info {
+                ops.set_generate_debug_info();
+            }
+            for (name, value) in &all_defines {
+                ops.add_macro_definition(name, Some(value.as_str()));
+            }
+            ops.set_include_callback(|name, ty, source, _depth| {
+                let relative = ty == shaderc::IncludeType::Relative;
+                let file = resolve_include(name, relative, source.as_ref(), &dirs)?;
+                let modified = modification_time(&file);
+                let content = std::fs::read_to_string(&file)
+                    .map_err(|err| format!("Failed to read '{}': {}", file.display(), err))?;
+                let resolved_name = file.display().to_string();
+                resolved.borrow_mut().push((file, modified));
+                Ok(shaderc::ResolvedInclude {
+                    resolved_name,
+                    content,
+                })
+            });
+
+            shaderc::Compiler::new()
+                .ok_or_else(|| failure::format_err!("Failed to init Shaderc"))?
+                .compile_into_spirv(
+                    &code,
+                    self.kind,
+                    path.to_str().ok_or_else(|| {
+                        failure::format_err!("'{:?}' is not valid UTF-8 string", self.path)
+                    })?,
+                    self.entry.as_ref(),
+                    Some(&ops),
+                )
+        };
+
+        files.extend(resolved.into_inner());
+        let spirv: Vec<u8> = result?.as_binary_u8().into();
+
+        if let Some((cache, key)) = cached {
+            if let Err(err) = cache.store(key, &spirv) {
+                log::warn!("Failed to store shader {:?} in cache: {}", self.path, err);
+            }
         }
+
+        Ok(spirv)
     }
 }
 
+/// File path with modification time.
+/// Time is `None` if file doesn't exist.
+pub(crate) type FileStamp = (std::path::PathBuf, Option<std::time::SystemTime>);
+
+/// Get modification time of the file.
+pub(crate) fn modification_time(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 impl<P, E> Shader for ShaderInfo<P, E>
 where
     P: AsRef<std::path::Path> + std::fmt::Debug,
     E: AsRef<str>,
 {
     fn spirv(&self) -> Result<std::borrow::Cow<'static, [u8]>, failure::Error> {
-        let code = std::fs::read_to_string(&self.path)?;
-
-        let artifact = shaderc::Compiler::new()
-            .ok_or_else(|| failure::format_err!("Failed to init Shaderc"))?
-            .compile_into_spirv(
-                &code,
-                self.kind,
-                self.path.as_ref().to_str().ok_or_else(|| {
-                    failure::format_err!("'{:?}' is not valid UTF-8 string", self.path)
-                })?,
-                self.entry.as_ref(),
-                Some({
-                    let mut ops = shaderc::CompileOptions::new()
-                        .ok_or_else(|| failure::format_err!("Failed to init Shaderc"))?;
-                    ops.set_target_env(shaderc::TargetEnv::Vulkan, vk_make_version!(1, 0, 0));
-                    ops.set_source_language(self.lang);
-                    ops.set_optimization_level(shaderc::OptimizationLevel::Performance);
-                    ops
-                })
-                .as_ref(),
-            )?;
-
-        Ok(std::borrow::Cow::Owned(artifact.as_binary_u8().into()))
+        Ok(std::borrow::Cow::Owned(
+            self.compile(&Defines::new(), &mut Vec::new())?,
+        ))
     }
 }
 
@@ -0,0 +1,217 @@
+use {
+    crate::{Defines, ShaderInfo},
+    std::collections::HashMap,
+};
+
+/// Variants of the shader compiled with all combinations of preprocessor macro values.
+///
+/// ```ignore
+/// let mut permutations = ShaderPermutations::new(info)
+///     .with_flag("SKINNED")
+///     .with_values("LIGHTS", vec!["1", "4", "16"]);
+/// permutations.compile()?;
+///
+/// let mut defines = Defines::new();
+/// defines.insert("SKINNED".into(), "1".into());
+/// defines.insert("LIGHTS".into(), "4".into());
+/// let module = permutations.module(factory, &defines)?;
+/// ```
+#[derive(Debug)]
+pub struct ShaderPermutations<P, E> {
+    info: ShaderInfo<P, E>,
+
+    /// Values each macro takes. `None` value leaves macro undefined.
+    macros: Vec<(String, Vec<Option<String>>)>,
+
+    /// Compiled variants by macros defined for them.
+    variants: HashMap<Defines, Vec<u8>>,
+}
+
+impl<P, E> ShaderPermutations<P, E> {
+    /// Permutations of the shader.
+    /// Defines of the `info` are shared by all variants.
+    pub fn new(info: ShaderInfo<P, E>) -> Self {
+        ShaderPermutations {
+            info,
+            macros: Vec::new(),
+            variants: HashMap::new(),
+        }
+    }
+
+    /// Add macro that is either undefined or defined as `1`.
+    pub fn add_flag(&mut self, name: impl Into<String>) -> &mut Self {
+        self.macros
+            .push((name.into(), vec![None, Some("1".to_owned())]));
+        self
+    }
+
+    /// Add macro that is either undefined or defined as `1`.
+    pub fn with_flag(mut self, name: impl Into<String>) -> Self {
+        self.add_flag(name);
+        self
+    }
+
+    /// Add macro that is defined with each of the values.
+    pub fn add_values<V>(&mut self, name: impl Into<String>, values: V) -> &mut Self
+    where
+        V: IntoIterator,
+        V::Item: Into<String>,
+    {
+        self.macros.push((
+            name.into(),
+            values.into_iter().map(|value| Some(value.into())).collect(),
+        ));
+        self
+    }
+
+    /// Add macro that is defined with each of the values.
+    pub fn with_values<V>(mut self, name: impl Into<String>, values: V) -> Self
+    where
+        V: IntoIterator,
+        V::Item: Into<String>,
+    {
+        self.add_values(name, values);
+        self
+    }
+
+    /// Get shader info shared by all variants.
+    pub fn info(&self) -> &ShaderInfo<P, E> {
+        &self.info
+    }
+
+    /// Get macros defined for each variant.
+    pub fn permutations(&self) -> Vec<Defines> {
+        self.macros
+            .iter()
+            .fold(vec![Defines::new()], |permutations, (name, values)| {
+                permutations
+                    .iter()
+                    .flat_map(|defines| {
+                        values.iter().map(move |value| {
+                            let mut defines = defines.clone();
+                            if let Some(value) = value {
+                                defines.insert(name.clone(), value.clone());
+                            }
+                            defines
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    /// Get SPIR-V of compiled variant.
+    /// Returns `None` if variant with exactly these `defines` is not compiled.
+    pub fn spirv(&self, defines: &Defines) -> Option<&[u8]> {
+        self.variants.get(defines).map(|spirv| &spirv[..])
+    }
+
+    /// Create shader module of compiled variant.
+    pub fn module<B>(
+        &self,
+        factory: &rendy_factory::Factory<B>,
+        defines: &Defines,
+    ) -> Result<B::ShaderModule, failure::Error>
+    where
+        B: gfx_hal::Backend,
+    {
+        let spirv = self
+            .spirv(defines)
+            .ok_or_else(|| failure::format_err!("Shader variant {:?} is not compiled", defines))?;
+        unsafe { gfx_hal::Device::create_shader_module(factory.device(), spirv) }
+            .map_err(Into::into)
+    }
+
+    /// Forget compiled variants.
+    /// Next call to `compile` compiles all variants again.
+    pub fn clear(&mut self) {
+        self.variants.clear();
+    }
+}
+
+impl<P, E> ShaderPermutations<P, E>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    E: AsRef<str>,
+{
+    /// Remove cached variants compiled from current source.
+    /// Does nothing if shader doesn't use cache.
+    pub fn invalidate_cache(&self) -> Result<(), failure::Error> {
+        for defines in self.permutations() {
+            self.info.invalidate_cache(&defines)?;
+        }
+        Ok(())
+    }
+
+    /// Compile all variants that are not compiled yet.
+    pub fn compile(&mut self) -> Result<(), failure::Error> {
+        for defines in self.permutations() {
+            if self.variants.contains_key(&defines) {
+                continue;
+            }
+
+            let spirv = self
+                .info
+                .compile(&defines, &mut Vec::new())
+                .map_err(|err| {
+                    failure::format_err!("Failed to compile shader variant {:?}: {}", defines, err)
+                })?;
+            self.variants.insert(defines, spirv);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{ShaderKind, SourceLanguage},
+    };
+
+    fn defines(pairs: &[(&str, &str)]) -> Defines {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn permutations_of_flags_and_values() {
+        let info = ShaderInfo::new(
+            "shader.frag",
+            ShaderKind::Fragment,
+            SourceLanguage::GLSL,
+            "main",
+        );
+        let permutations = ShaderPermutations::new(info)
+            .with_flag("SKINNED")
+            .with_values("LIGHTS", vec!["1", "4"]);
+
+        assert_eq!(
+            permutations.permutations(),
+            vec![
+                defines(&[("LIGHTS", "1")]),
+                defines(&[("LIGHTS", "4")]),
+                defines(&[("SKINNED", "1"), ("LIGHTS", "1")]),
+                defines(&[("SKINNED", "1"), ("LIGHTS", "4")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_macros_give_single_permutation() {
+        let info = ShaderInfo::new(
+            "shader.frag",
+            ShaderKind::Fragment,
+            SourceLanguage::GLSL,
+            "main",
+        );
+        let mut permutations = ShaderPermutations::new(info);
+        assert_eq!(permutations.permutations(), vec![Defines::new()]);
+
+        // Macro without values leaves nothing to compile.
+        permutations.add_values("EMPTY", Vec::<String>::new());
+        assert_eq!(permutations.permutations(), Vec::<Defines>::new());
+    }
+}
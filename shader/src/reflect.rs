@@ -0,0 +1,1023 @@
+//! SPIR-V reflection.
+//! Extracts descriptor bindings, push constants, vertex inputs and workgroup sizes
+//! from compiled shaders.
+
+use {
+    gfx_hal::{
+        format::{ChannelType, Format},
+        pso::{DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags},
+    },
+    rendy_mesh::VertexFormat,
+    std::{collections::HashMap, ops::Range},
+};
+
+const MAGIC: u32 = 0x0723_0203;
+
+// Opcodes.
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations.
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes.
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+// Execution models and modes.
+const MODEL_VERTEX: u32 = 0;
+const MODE_LOCAL_SIZE: u32 = 17;
+
+// Image dimensions.
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// Types nested deeper are considered invalid.
+/// Protects from types that reference themselves.
+const MAX_TYPE_DEPTH: u32 = 64;
+
+/// Vertex inputs at greater locations are not supported.
+/// Far above `maxVertexInputAttributes` of any device.
+const MAX_VERTEX_LOCATION: u32 = 1 << 16;
+
+/// Error of SPIR-V reflection or reflection checks.
+#[derive(Clone, Debug, PartialEq, failure::Fail)]
+pub enum ReflectError {
+    /// Data is not valid SPIR-V module.
+    #[fail(display = "Invalid SPIR-V: {}", _0)]
+    Invalid(&'static str),
+
+    /// Module uses feature reflection doesn't support.
+    #[fail(display = "Unsupported SPIR-V: {}", _0)]
+    Unsupported(String),
+
+    /// Shaders declare the same binding differently.
+    #[fail(
+        display = "Binding {} of set {} has different declarations in shaders",
+        _1, _0
+    )]
+    BindingConflict(u32, u32),
+
+    /// Vertex shader input has no matching vertex attribute.
+    #[fail(display = "No vertex attribute for shader input at location {}", _0)]
+    MissingVertexAttribute(u32),
+
+    /// Vertex attribute format is incompatible with shader input.
+    #[fail(
+        display = "Vertex attribute at location {} has format {:?} incompatible with shader input {:?}",
+        _0, _2, _1
+    )]
+    VertexAttributeMismatch(u32, Format, Format),
+}
+
+/// Descriptor binding used by shaders.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    /// Descriptor set index.
+    pub set: u32,
+
+    /// Binding index within set.
+    pub binding: u32,
+
+    /// Descriptor type.
+    pub ty: DescriptorType,
+
+    /// Number of descriptors in array.
+    pub count: usize,
+
+    /// Stages that use the binding.
+    pub stages: ShaderStageFlags,
+}
+
+/// Vertex shader input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    /// Input location.
+    pub location: u32,
+
+    /// Format of the input as declared in shader.
+    pub format: Format,
+}
+
+/// Interface of shaders extracted from SPIR-V.
+/// Reflections of individual stages are combined with `Reflection::merge`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reflection {
+    /// Stages of entry points.
+    pub stages: ShaderStageFlags,
+
+    /// Descriptor bindings sorted by set and binding.
+    pub bindings: Vec<Binding>,
+
+    /// Push constant ranges with stages that use them.
+    pub push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+
+    /// Vertex shader inputs sorted by location.
+    pub vertex_inputs: Vec<VertexInput>,
+
+    /// Local workgroup size of compute shader.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+impl Reflection {
+    /// Reflect SPIR-V module.
+    pub fn new(spirv: &[u8]) -> Result<Self, ReflectError> {
+        Module::parse(spirv)?.reflect()
+    }
+
+    /// Combine reflections of shaders of one pipeline.
+    /// Bindings used by several stages must have the same type and count.
+    pub fn merge<'a>(
+        reflections: impl IntoIterator<Item = &'a Reflection>,
+    ) -> Result<Self, ReflectError> {
+        let mut merged = Reflection::default();
+        for reflection in reflections {
+            merged.stages |= reflection.stages;
+
+            for binding in &reflection.bindings {
+                match merged
+                    .bindings
+                    .iter_mut()
+                    .find(|b| (b.set, b.binding) == (binding.set, binding.binding))
+                {
+                    Some(existing) => {
+                        if (existing.ty, existing.count) != (binding.ty, binding.count) {
+                            return Err(ReflectError::BindingConflict(
+                                binding.set,
+                                binding.binding,
+                            ));
+                        }
+                        existing.stages |= binding.stages;
+                    }
+                    None => merged.bindings.push(binding.clone()),
+                }
+            }
+
+            merged
+                .push_constants
+                .extend(reflection.push_constants.iter().cloned());
+
+            if !reflection.vertex_inputs.is_empty() {
+                merged.vertex_inputs = reflection.vertex_inputs.clone();
+            }
+
+            if reflection.workgroup_size.is_some() {
+                merged.workgroup_size = reflection.workgroup_size;
+            }
+        }
+        merged.bindings.sort_by_key(|b| (b.set, b.binding));
+        Ok(merged)
+    }
+
+    /// Get descriptor set layout bindings for each set up to the last one used.
+    /// Sets not used by shaders are empty.
+    pub fn set_bindings(&self) -> Vec<Vec<DescriptorSetLayoutBinding>> {
+        let count = self.bindings.iter().map(|b| b.set + 1).max().unwrap_or(0);
+        (0..count)
+            .map(|set| {
+                self.bindings
+                    .iter()
+                    .filter(|b| b.set == set)
+                    .map(|b| DescriptorSetLayoutBinding {
+                        binding: b.binding,
+                        ty: b.ty,
+                        count: b.count,
+                        stage_flags: b.stages,
+                        immutable_samplers: false,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Check that vertex shader inputs are provided by vertex format.
+    /// Attributes get consecutive locations starting from 0.
+    pub fn check_vertex_format(&self, format: &VertexFormat<'_>) -> Result<(), ReflectError> {
+        self.check_vertex_formats(std::slice::from_ref(format))
+    }
+
+    /// Check that vertex shader inputs are provided by vertex formats of several buffers.
+    /// Attributes get consecutive locations starting from 0 in order of buffers.
+    pub fn check_vertex_formats(&self, formats: &[VertexFormat<'_>]) -> Result<(), ReflectError> {
+        let attributes: Vec<Format> = formats
+            .iter()
+            .flat_map(|format| format.attributes.iter().map(|attribute| attribute.format))
+            .collect();
+
+        for input in &self.vertex_inputs {
+            let format = *attributes
+                .get(input.location as usize)
+                .ok_or(ReflectError::MissingVertexAttribute(input.location))?;
+            if numeric_class(format) != numeric_class(input.format) {
+                return Err(ReflectError::VertexAttributeMismatch(
+                    input.location,
+                    input.format,
+                    format,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Numeric type shader sees for the format.
+fn numeric_class(format: Format) -> u8 {
+    match format.base_format().1 {
+        ChannelType::Uint => 1,
+        ChannelType::Int => 2,
+        _ => 0,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Clone, Debug, Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    array_stride: Option<u32>,
+    built_in: bool,
+    block: bool,
+    buffer_block: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+    built_in: bool,
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    entry_points: Vec<(u32, u32, Vec<u32>)>,
+    local_sizes: HashMap<u32, [u32; 3]>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+}
+
+impl Module {
+    fn parse(spirv: &[u8]) -> Result<Self, ReflectError> {
+        if spirv.len() % 4 != 0 {
+            return Err(ReflectError::Invalid("size is not multiple of 4"));
+        }
+        let mut words: Vec<u32> = spirv
+            .chunks(4)
+            .map(|b| {
+                u32::from(b[0])
+                    | u32::from(b[1]) << 8
+                    | u32::from(b[2]) << 16
+                    | u32::from(b[3]) << 24
+            })
+            .collect();
+
+        match words.first() {
+            Some(&MAGIC) => {}
+            Some(&magic) if magic.swap_bytes() == MAGIC => {
+                words.iter_mut().for_each(|word| *word = word.swap_bytes());
+            }
+            _ => return Err(ReflectError::Invalid("wrong magic number")),
+        }
+        if words.len() < 5 {
+            return Err(ReflectError::Invalid("header is truncated"));
+        }
+
+        let mut module = Module::default();
+        let mut rest = &words[5..];
+        while !rest.is_empty() {
+            let count = (rest[0] >> 16) as usize;
+            let opcode = rest[0] & 0xffff;
+            if count == 0 || count > rest.len() {
+                return Err(ReflectError::Invalid("instruction is truncated"));
+            }
+            module.instruction(opcode, &rest[1..count])?;
+            rest = &rest[count..];
+        }
+
+        Ok(module)
+    }
+
+    fn instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), ReflectError> {
+        let operand = |index: usize| {
+            operands
+                .get(index)
+                .cloned()
+                .ok_or(ReflectError::Invalid("missing instruction operand"))
+        };
+
+        match opcode {
+            OP_ENTRY_POINT => {
+                // Skip nul-terminated name.
+                let name = operands
+                    .iter()
+                    .skip(2)
+                    .position(|word| (0..4).any(|byte| (word >> (byte * 8)) & 0xff == 0))
+                    .ok_or(ReflectError::Invalid("entry point name is not terminated"))?;
+                self.entry_points
+                    .push((operand(0)?, operand(1)?, operands[3 + name..].to_vec()));
+            }
+            OP_EXECUTION_MODE if operand(1)? == MODE_LOCAL_SIZE => {
+                self.local_sizes
+                    .insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+            }
+            OP_TYPE_BOOL => {
+                self.types.insert(operand(0)?, Type::Bool);
+            }
+            OP_TYPE_INT => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Int {
+                        width: operand(1)?,
+                        signed: operand(2)? != 0,
+                    },
+                );
+            }
+            OP_TYPE_FLOAT => {
+                self.types
+                    .insert(operand(0)?, Type::Float { width: operand(1)? });
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Vector {
+                        component: operand(1)?,
+                        count: operand(2)?,
+                    },
+                );
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Matrix {
+                        column: operand(1)?,
+                        count: operand(2)?,
+                    },
+                );
+            }
+            OP_TYPE_IMAGE => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Image {
+                        dim: operand(2)?,
+                        sampled: operand(6)?,
+                    },
+                );
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Array {
+                        element: operand(1)?,
+                        length: operand(2)?,
+                    },
+                );
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, Type::RuntimeArray);
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Struct {
+                        members: operands.get(1..).unwrap_or(&[]).to_vec(),
+                    },
+                );
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(
+                    operand(0)?,
+                    Type::Pointer {
+                        pointee: operand(2)?,
+                    },
+                );
+            }
+            OP_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            OP_VARIABLE => {
+                self.variables.push((operand(0)?, operand(1)?, operand(2)?));
+            }
+            OP_DECORATE => {
+                let decorations = self.decorations.entry(operand(0)?).or_default();
+                match operand(1)? {
+                    DECORATION_BLOCK => decorations.block = true,
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    DECORATION_LOCATION => decorations.location = Some(operand(2)?),
+                    DECORATION_BINDING => decorations.binding = Some(operand(2)?),
+                    DECORATION_DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE => {
+                let decorations = self
+                    .member_decorations
+                    .entry((operand(0)?, operand(1)?))
+                    .or_default();
+                match operand(2)? {
+                    DECORATION_OFFSET => decorations.offset = Some(operand(3)?),
+                    DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type, ReflectError> {
+        self.types
+            .get(&id)
+            .ok_or(ReflectError::Invalid("reference to unknown type"))
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn array_length(&self, id: u32) -> Result<u32, ReflectError> {
+        self.constants
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| ReflectError::Unsupported("array length is not a constant".into()))
+    }
+
+    fn reflect(&self) -> Result<Reflection, ReflectError> {
+        let stages = self
+            .entry_points
+            .iter()
+            .map(|&(model, _, _)| stage(model))
+            .fold(ShaderStageFlags::empty(), |acc, stage| acc | stage);
+
+        let mut reflection = Reflection {
+            stages,
+            workgroup_size: self
+                .entry_points
+                .iter()
+                .filter_map(|&(_, id, _)| self.local_sizes.get(&id).cloned())
+                .next(),
+            ..Reflection::default()
+        };
+
+        for &(pointer, id, storage) in &self.variables {
+            let pointee = match *self.ty(pointer)? {
+                Type::Pointer { pointee } => pointee,
+                _ => return Err(ReflectError::Invalid("variable type is not a pointer")),
+            };
+            let decorations = self.decorations(id);
+
+            match storage {
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (set, binding) = match (decorations.set, decorations.binding) {
+                        (Some(set), Some(binding)) => (set, binding),
+                        _ => continue,
+                    };
+                    let (ty, count) = self.descriptor(pointee, storage, 0)?;
+                    reflection.bindings.push(Binding {
+                        set,
+                        binding,
+                        ty,
+                        count,
+                        stages,
+                    });
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    let range = self.struct_range(pointee, 0)?;
+                    reflection.push_constants.push((stages, range));
+                }
+                STORAGE_INPUT => {
+                    let in_vertex_interface =
+                        self.entry_points.iter().any(|(model, _, interface)| {
+                            *model == MODEL_VERTEX && interface.contains(&id)
+                        });
+                    if !in_vertex_interface || decorations.built_in {
+                        continue;
+                    }
+                    if let Some(location) = decorations.location {
+                        self.vertex_inputs(pointee, location, &mut reflection.vertex_inputs, 0)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+        reflection.vertex_inputs.sort_by_key(|input| input.location);
+        Ok(reflection)
+    }
+
+    /// Get descriptor type and count of the variable type.
+    fn descriptor(
+        &self,
+        id: u32,
+        storage: u32,
+        depth: u32,
+    ) -> Result<(DescriptorType, usize), ReflectError> {
+        match *self.ty(id)? {
+            Type::Array { element, length } => {
+                let (ty, count) = self.descriptor(element, storage, nested(depth)?)?;
+                let count = count
+                    .checked_mul(self.array_length(length)? as usize)
+                    .ok_or_else(overflow)?;
+                Ok((ty, count))
+            }
+            Type::RuntimeArray => Err(ReflectError::Unsupported(
+                "runtime descriptor arrays".into(),
+            )),
+            Type::Sampler => Ok((DescriptorType::Sampler, 1)),
+            Type::SampledImage => Ok((DescriptorType::CombinedImageSampler, 1)),
+            Type::Image { dim, sampled } => Ok((
+                match (dim, sampled) {
+                    (DIM_SUBPASS_DATA, _) => DescriptorType::InputAttachment,
+                    (DIM_BUFFER, 2) => DescriptorType::StorageTexelBuffer,
+                    (DIM_BUFFER, _) => DescriptorType::UniformTexelBuffer,
+                    (_, 2) => DescriptorType::StorageImage,
+                    (_, _) => DescriptorType::SampledImage,
+                },
+                1,
+            )),
+            Type::Struct { .. } => {
+                let decorations = self.decorations(id);
+                if storage == STORAGE_STORAGE_BUFFER || decorations.buffer_block {
+                    Ok((DescriptorType::StorageBuffer, 1))
+                } else {
+                    Ok((DescriptorType::UniformBuffer, 1))
+                }
+            }
+            ref ty => Err(ReflectError::Unsupported(format!(
+                "descriptor of type {:?}",
+                ty
+            ))),
+        }
+    }
+
+    /// Get range of struct members in bytes.
+    fn struct_range(&self, id: u32, depth: u32) -> Result<Range<u32>, ReflectError> {
+        let members = match self.ty(id)? {
+            Type::Struct { members } => members,
+            _ => return Err(ReflectError::Invalid("push constant is not a struct")),
+        };
+
+        let mut range: Option<Range<u32>> = None;
+        for (index, &member) in members.iter().enumerate() {
+            let decorations = self
+                .member_decorations
+                .get(&(id, index as u32))
+                .cloned()
+                .unwrap_or_default();
+            let offset = decorations.offset.unwrap_or(0);
+            let end = offset
+                .checked_add(self.size(member, decorations.matrix_stride, nested(depth)?)?)
+                .ok_or_else(overflow)?;
+            range = Some(match range {
+                Some(range) => range.start.min(offset)..range.end.max(end),
+                None => offset..end,
+            });
+        }
+
+        // Push constant ranges are multiple of 4 bytes.
+        let range = range.unwrap_or(0..0);
+        let end = range.end.checked_add(3).ok_or_else(overflow)?;
+        Ok(range.start & !3..end & !3)
+    }
+
+    /// Get size of the type in bytes.
+    fn size(&self, id: u32, matrix_stride: Option<u32>, depth: u32) -> Result<u32, ReflectError> {
+        let size = match *self.ty(id)? {
+            Type::Bool => Some(4),
+            Type::Int { width, .. } | Type::Float { width } => Some(width / 8),
+            Type::Vector { component, count } => {
+                count.checked_mul(self.size(component, None, nested(depth)?)?)
+            }
+            Type::Matrix { column, count } => match matrix_stride {
+                Some(stride) => count.checked_mul(stride),
+                None => count.checked_mul(self.size(column, None, nested(depth)?)?),
+            },
+            Type::Array { element, length } => {
+                let length = self.array_length(length)?;
+                match self.decorations(id).array_stride {
+                    Some(stride) => length.checked_mul(stride),
+                    None => {
+                        length.checked_mul(self.size(element, matrix_stride, nested(depth)?)?)
+                    }
+                }
+            }
+            Type::Struct { .. } => Some(self.struct_range(id, nested(depth)?)?.end),
+            ref ty => return Err(ReflectError::Unsupported(format!("size of type {:?}", ty))),
+        };
+        size.ok_or_else(overflow)
+    }
+
+    /// Collect vertex inputs of the variable type.
+    /// Matrices and arrays occupy consecutive locations.
+    fn vertex_inputs(
+        &self,
+        id: u32,
+        location: u32,
+        inputs: &mut Vec<VertexInput>,
+        depth: u32,
+    ) -> Result<u32, ReflectError> {
+        if location >= MAX_VERTEX_LOCATION {
+            return Err(ReflectError::Unsupported(format!(
+                "vertex input at location {}",
+                location
+            )));
+        }
+
+        match *self.ty(id)? {
+            Type::Matrix { column, count } => {
+                for index in 0..count {
+                    let location = location.checked_add(index).ok_or_else(overflow)?;
+                    self.vertex_inputs(column, location, inputs, nested(depth)?)?;
+                }
+                Ok(count)
+            }
+            Type::Array { element, length } => {
+                let mut used = 0u32;
+                for _ in 0..self.array_length(length)? {
+                    let location = location.checked_add(used).ok_or_else(overflow)?;
+                    let count = self.vertex_inputs(element, location, inputs, nested(depth)?)?;
+                    used = used.checked_add(count).ok_or_else(overflow)?;
+                }
+                Ok(used)
+            }
+            _ => {
+                inputs.push(VertexInput {
+                    location,
+                    format: self.format(id)?,
+                });
+                Ok(1)
+            }
+        }
+    }
+
+    /// Get format of scalar or vector type.
+    fn format(&self, id: u32) -> Result<Format, ReflectError> {
+        let (component, count) = match *self.ty(id)? {
+            Type::Vector { component, count } => (component, count),
+            _ => (id, 1),
+        };
+
+        Ok(match (self.ty(component)?, count) {
+            (Type::Float { width: 32 }, 1) => Format::R32Float,
+            (Type::Float { width: 32 }, 2) => Format::Rg32Float,
+            (Type::Float { width: 32 }, 3) => Format::Rgb32Float,
+            (Type::Float { width: 32 }, 4) => Format::Rgba32Float,
+            (Type::Float { width: 64 }, 1) => Format::R64Float,
+            (Type::Float { width: 64 }, 2) => Format::Rg64Float,
+            (Type::Float { width: 64 }, 3) => Format::Rgb64Float,
+            (Type::Float { width: 64 }, 4) => Format::Rgba64Float,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                1,
+            ) => Format::R32Int,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                2,
+            ) => Format::Rg32Int,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                3,
+            ) => Format::Rgb32Int,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                4,
+            ) => Format::Rgba32Int,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                1,
+            ) => Format::R32Uint,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                2,
+            ) => Format::Rg32Uint,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                3,
+            ) => Format::Rgb32Uint,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                4,
+            ) => Format::Rgba32Uint,
+            (ty, count) => {
+                return Err(ReflectError::Unsupported(format!(
+                    "vertex input of type {:?} x {}",
+                    ty, count
+                )))
+            }
+        })
+    }
+}
+
+/// Get depth of nested type.
+fn nested(depth: u32) -> Result<u32, ReflectError> {
+    if depth < MAX_TYPE_DEPTH {
+        Ok(depth + 1)
+    } else {
+        Err(ReflectError::Invalid("types are nested too deep"))
+    }
+}
+
+fn overflow() -> ReflectError {
+    ReflectError::Invalid("size or location overflows")
+}
+
+/// Get shader stage of execution model.
+fn stage(model: u32) -> ShaderStageFlags {
+    match model {
+        0 => ShaderStageFlags::VERTEX,
+        1 => ShaderStageFlags::HULL,
+        2 => ShaderStageFlags::DOMAIN,
+        3 => ShaderStageFlags::GEOMETRY,
+        4 => ShaderStageFlags::FRAGMENT,
+        5 => ShaderStageFlags::COMPUTE,
+        _ => ShaderStageFlags::empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str, kind: shaderc::ShaderKind) -> Reflection {
+        let spirv = shaderc::Compiler::new()
+            .unwrap()
+            .compile_into_spirv(source, kind, "test.glsl", "main", None)
+            .unwrap();
+        Reflection::new(spirv.as_binary_u8()).unwrap()
+    }
+
+    /// Assemble module from instructions of opcode and operands.
+    fn assemble(instructions: &[(u32, &[u32])]) -> Vec<u8> {
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 16, 0];
+        for &(opcode, operands) in instructions {
+            words.push((operands.len() as u32 + 1) << 16 | opcode);
+            words.extend_from_slice(operands);
+        }
+        words
+            .iter()
+            .flat_map(|word| (0..4).map(move |byte| (word >> (byte * 8)) as u8))
+            .collect()
+    }
+
+    const VERTEX: &str = r#"
+        #version 450
+        layout(location = 0) in vec3 position;
+        layout(location = 1) in vec2 uv;
+        layout(location = 2) in mat4 model;
+        layout(location = 6) in ivec4 ids;
+        layout(location = 0) out vec2 out_uv;
+
+        layout(set = 0, binding = 0) uniform Globals {
+            mat4 view_proj;
+        };
+
+        layout(push_constant) uniform Push {
+            vec4 color;
+            float scale;
+        } push;
+
+        void main() {
+            out_uv = uv * push.scale * float(ids.x);
+            gl_Position = view_proj * model * vec4(position, 1.0) * push.color;
+        }
+    "#;
+
+    const FRAGMENT: &str = r#"
+        #version 450
+        layout(location = 0) in vec2 uv;
+        layout(location = 0) out vec4 color;
+
+        layout(set = 0, binding = 1) uniform sampler2D textures[4];
+
+        layout(set = 1, binding = 0) buffer Data {
+            float values[];
+        };
+
+        void main() {
+            color = texture(textures[1], uv) * values[0];
+        }
+    "#;
+
+    #[test]
+    fn vertex_shader() {
+        let reflection = compile(VERTEX, shaderc::ShaderKind::Vertex);
+        assert_eq!(reflection.stages, ShaderStageFlags::VERTEX);
+        assert_eq!(
+            reflection.bindings,
+            vec![Binding {
+                set: 0,
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stages: ShaderStageFlags::VERTEX,
+            }]
+        );
+        assert_eq!(
+            reflection.push_constants,
+            vec![(ShaderStageFlags::VERTEX, 0..20)]
+        );
+
+        let formats: Vec<_> = reflection
+            .vertex_inputs
+            .iter()
+            .map(|input| (input.location, input.format))
+            .collect();
+        assert_eq!(
+            formats,
+            vec![
+                (0, Format::Rgb32Float),
+                (1, Format::Rg32Float),
+                (2, Format::Rgba32Float),
+                (3, Format::Rgba32Float),
+                (4, Format::Rgba32Float),
+                (5, Format::Rgba32Float),
+                (6, Format::Rgba32Int),
+            ]
+        );
+        assert_eq!(reflection.workgroup_size, None);
+    }
+
+    #[test]
+    fn merged_pipeline() {
+        let vertex = compile(VERTEX, shaderc::ShaderKind::Vertex);
+        let fragment = compile(FRAGMENT, shaderc::ShaderKind::Fragment);
+        let merged = Reflection::merge(vec![&vertex, &fragment]).unwrap();
+
+        assert_eq!(
+            merged.stages,
+            ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT
+        );
+        let bindings: Vec<_> = merged
+            .bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.ty, b.count, b.stages))
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                (
+                    0,
+                    0,
+                    DescriptorType::UniformBuffer,
+                    1,
+                    ShaderStageFlags::VERTEX
+                ),
+                (
+                    0,
+                    1,
+                    DescriptorType::CombinedImageSampler,
+                    4,
+                    ShaderStageFlags::FRAGMENT
+                ),
+                (
+                    1,
+                    0,
+                    DescriptorType::StorageBuffer,
+                    1,
+                    ShaderStageFlags::FRAGMENT
+                ),
+            ]
+        );
+        assert_eq!(merged.vertex_inputs, vertex.vertex_inputs);
+
+        let sets = merged.set_bindings();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].len(), 2);
+        assert_eq!(sets[1].len(), 1);
+    }
+
+    #[test]
+    fn compute_shader() {
+        let reflection = compile(
+            r#"
+                #version 450
+                layout(local_size_x = 8, local_size_y = 4) in;
+                void main() {}
+            "#,
+            shaderc::ShaderKind::Compute,
+        );
+        assert_eq!(reflection.stages, ShaderStageFlags::COMPUTE);
+        assert_eq!(reflection.workgroup_size, Some([8, 4, 1]));
+    }
+
+    #[test]
+    fn empty_struct_declaration() {
+        let spirv = assemble(&[(OP_TYPE_STRUCT, &[])]);
+        assert_eq!(
+            Reflection::new(&spirv),
+            Err(ReflectError::Invalid("missing instruction operand"))
+        );
+    }
+
+    #[test]
+    fn self_referencing_type() {
+        // Struct that contains itself used as push constant.
+        let spirv = assemble(&[
+            (OP_TYPE_STRUCT, &[1, 1]),
+            (OP_TYPE_POINTER, &[2, STORAGE_PUSH_CONSTANT, 1]),
+            (OP_VARIABLE, &[2, 3, STORAGE_PUSH_CONSTANT]),
+        ]);
+        assert_eq!(
+            Reflection::new(&spirv),
+            Err(ReflectError::Invalid("types are nested too deep"))
+        );
+    }
+
+    #[test]
+    fn size_overflow() {
+        // `uint[0xffffffff]` with stride of 16 bytes used as push constant.
+        let spirv = assemble(&[
+            (OP_TYPE_INT, &[1, 32, 0]),
+            (OP_CONSTANT, &[1, 2, !0]),
+            (OP_TYPE_ARRAY, &[3, 1, 2]),
+            (OP_DECORATE, &[3, DECORATION_ARRAY_STRIDE, 16]),
+            (OP_TYPE_STRUCT, &[4, 3]),
+            (OP_TYPE_POINTER, &[5, STORAGE_PUSH_CONSTANT, 4]),
+            (OP_VARIABLE, &[5, 6, STORAGE_PUSH_CONSTANT]),
+        ]);
+        assert_eq!(
+            Reflection::new(&spirv),
+            Err(ReflectError::Invalid("size or location overflows"))
+        );
+    }
+}
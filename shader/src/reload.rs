@@ -0,0 +1,85 @@
+use {
+    crate::{modification_time, Defines, FileStamp, Shader, ShaderInfo},
+    std::{path::PathBuf, sync::Mutex},
+};
+
+/// Shader that tracks files it was compiled from.
+/// Use `changed` to find out if shader should be recompiled.
+#[derive(Debug)]
+pub struct ReloadableShader<P, E> {
+    info: ShaderInfo<P, E>,
+
+    /// Source and included files with modification times at last compilation.
+    files: Mutex<Vec<FileStamp>>,
+}
+
+impl<P, E> ReloadableShader<P, E> {
+    /// Wrap dynamic shader.
+    pub fn new(info: ShaderInfo<P, E>) -> Self {
+        ReloadableShader {
+            info,
+            files: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get shader info.
+    pub fn info(&self) -> &ShaderInfo<P, E> {
+        &self.info
+    }
+
+    /// Get source and included files of the last compilation.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Check if any file was modified, created or removed since last compilation.
+    /// Returns `false` if shader wasn't compiled yet.
+    pub fn changed(&self) -> bool {
+        self.files
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(path, modified)| modification_time(path) != *modified)
+    }
+}
+
+impl<P, E> Shader for ReloadableShader<P, E>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    E: AsRef<str>,
+{
+    fn spirv(&self) -> Result<std::borrow::Cow<'static, [u8]>, failure::Error> {
+        // Stamp files of the last compilation before compiling,
+        // in case compilation fails before reaching them.
+        let previous: Vec<FileStamp> = self
+            .files
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, _)| (path.clone(), modification_time(path)))
+            .collect();
+
+        let mut files = Vec::new();
+        let result = self.info.compile(&Defines::new(), &mut files);
+
+        if result.is_err() {
+            // Includes after the error are unknown. Keep watching files of the last compilation.
+            for (path, modified) in previous {
+                if !files.iter().any(|(file, _)| *file == path) {
+                    files.push((path, modified));
+                }
+            }
+        }
+        *self.files.lock().unwrap() = files;
+
+        Ok(std::borrow::Cow::Owned(result?))
+    }
+}
+
+/// Shader info with static data that can be reloaded.
+pub type StaticReloadableShader = ReloadableShader<&'static str, &'static str>;
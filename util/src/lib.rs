@@ -56,3 +56,30 @@ pub fn cast_cow<T: Copy>(cow: Cow<'_, [T]>) -> Cow<'_, [u8]> {
         Cow::Owned(vec) => Cow::Owned(cast_vec(vec)),
     }
 }
+
+/// Make Vulkan version number from major, minor and patch versions.
+#[macro_export]
+macro_rules! vk_make_version {
+    ($major: expr, $minor: expr, $patch: expr) => {
+        (($major as u32) << 22) | (($minor as u32) << 12) | $patch as u32
+    };
+}
+
+/// Find file included by shader with `#include`.
+/// Relative includes are searched in the directory of including `source` file first,
+/// then in `dirs` in order.
+pub fn resolve_include(
+    name: &str,
+    relative: bool,
+    source: &std::path::Path,
+    dirs: &[&std::path::Path],
+) -> Result<std::path::PathBuf, String> {
+    let relative = if relative { source.parent() } else { None };
+
+    relative
+        .into_iter()
+        .chain(dirs.iter().cloned())
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("Included file '{}' is not found", name))
+}
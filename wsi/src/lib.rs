@@ -93,7 +93,7 @@ macro_rules! create_surface_for_backend {
 
 #[allow(unused_variables)]
 fn create_surface<B: gfx_hal::Backend>(
-    instance: &Box<dyn std::any::Any>,
+    instance: &Box<dyn std::any::Any + Send + Sync>,
     window: &winit::Window,
 ) -> B::Surface {
     create_surface_for_backend!(instance, window);
@@ -121,7 +121,10 @@ where
     B: gfx_hal::Backend,
 {
     /// Create surface for the window.
-    pub fn new(instance: &Box<dyn std::any::Any>, window: std::sync::Arc<winit::Window>) -> Self {
+    pub fn new(
+        instance: &Box<dyn std::any::Any + Send + Sync>,
+        window: std::sync::Arc<winit::Window>,
+    ) -> Self {
         let raw = create_surface::<B>(instance, &window);
         Surface { window, raw }
     }
@@ -165,133 +168,26 @@ where
         image_count: u32,
         usage: gfx_hal::image::Usage,
     ) -> Result<Target<B>, failure::Error> {
-        let (capabilities, formats, present_modes, _alpha) =
-            gfx_hal::Surface::compatibility(&self.raw, physical_device);
-
-        let present_mode = *present_modes
-            .iter()
-            .max_by_key(|mode| match mode {
-                gfx_hal::PresentMode::Immediate => 0,
-                gfx_hal::PresentMode::Mailbox => 3,
-                gfx_hal::PresentMode::Fifo => 2,
-                gfx_hal::PresentMode::Relaxed => 1,
-            })
-            .unwrap();
-
-        log::info!(
-            "Surface present modes: {:#?}. Pick {:#?}",
-            present_modes,
-            present_mode
-        );
-
-        let formats = formats.unwrap();
-
-        let format = *formats
-            .iter()
-            .max_by_key(|format| {
-                let base = format.base_format();
-                let desc = base.0.desc();
-                (
-                    !desc.is_compressed(),
-                    base.1 == gfx_hal::format::ChannelType::Srgb,
-                    desc.bits,
-                )
-            })
-            .unwrap();
-
-        log::info!("Surface formats: {:#?}. Pick {:#?}", formats, format);
-
-        let image_count = image_count
-            .min(capabilities.image_count.end)
-            .max(capabilities.image_count.start);
-
-        log::info!(
-            "Surface capabilities: {:#?}. Pick {} images",
-            capabilities.image_count,
-            image_count
-        );
-        assert!(
-            capabilities.usage.contains(usage),
-            "Surface supports {:?}, but {:?} was requested"
-        );
-
-        let extent = capabilities.current_extent.unwrap_or({
-            let hidpi_factor = self.window.get_hidpi_factor();
-            let start = capabilities.extents.start;
-            let end = capabilities.extents.end;
-            let (window_width, window_height) = self
-                .window
-                .get_inner_size()
-                .unwrap()
-                .to_physical(hidpi_factor)
-                .into();
-            gfx_hal::window::Extent2D {
-                width: end.width.min(start.width.max(window_width)),
-                height: end.height.min(start.height.max(window_height)),
-            }
-        });
-
-        let (swapchain, backbuffer) = device.create_swapchain(
+        let (swapchain, backbuffer, terminal, extent) = create_swapchain(
             &mut self.raw,
-            gfx_hal::SwapchainConfig {
-                present_mode,
-                format,
-                extent,
-                image_count,
-                image_layers: 1,
-                image_usage: usage,
-                composite_alpha: gfx_hal::window::CompositeAlpha::Inherit,
-            },
+            &self.window,
+            physical_device,
+            device,
+            image_count,
+            usage,
             None,
         )?;
 
-        let (backbuffer, terminal) = match backbuffer {
-            gfx_hal::Backbuffer::Images(images) => {
-                let terminal = Terminal::new();
-                let backbuffer = Backbuffer::Images(
-                    images
-                        .into_iter()
-                        .map(|image| {
-                            Image::new(
-                                Info {
-                                    kind: gfx_hal::image::Kind::D2(
-                                        extent.width,
-                                        extent.height,
-                                        1,
-                                        1,
-                                    ),
-                                    levels: 1,
-                                    format,
-                                    tiling: gfx_hal::image::Tiling::Optimal,
-                                    view_caps: gfx_hal::image::ViewCapabilities::empty(),
-                                    usage,
-                                },
-                                image,
-                                None,
-                                &terminal,
-                            )
-                        })
-                        .collect(),
-                );
-                (backbuffer, Some(terminal))
-            }
-            gfx_hal::Backbuffer::Framebuffer(raw) => {
-                let backbuffer = Backbuffer::Framebuffer {
-                    raw,
-                    format,
-                    extent,
-                };
-                (backbuffer, None)
-            }
-        };
-
         Ok(Target {
             relevant: relevant::Relevant,
             window: self.window,
             surface: self.raw,
-            swapchain,
+            swapchain: Some(swapchain),
             backbuffer,
             terminal,
+            extent,
+            image_count,
+            usage,
         })
     }
 
@@ -301,6 +197,142 @@ where
     }
 }
 
+/// Create swapchain for the surface.
+/// Extent matches current extent of the surface or the window size.
+unsafe fn create_swapchain<B: gfx_hal::Backend>(
+    surface: &mut B::Surface,
+    window: &winit::Window,
+    physical_device: &B::PhysicalDevice,
+    device: &impl gfx_hal::Device<B>,
+    image_count: u32,
+    usage: gfx_hal::image::Usage,
+    old_swapchain: Option<B::Swapchain>,
+) -> Result<
+    (
+        B::Swapchain,
+        Backbuffer<B>,
+        Option<Terminal<Inner<B>>>,
+        gfx_hal::window::Extent2D,
+    ),
+    failure::Error,
+> {
+    let (capabilities, formats, present_modes, _alpha) =
+        gfx_hal::Surface::compatibility(surface, physical_device);
+
+    let present_mode = *present_modes
+        .iter()
+        .max_by_key(|mode| match mode {
+            gfx_hal::PresentMode::Immediate => 0,
+            gfx_hal::PresentMode::Mailbox => 3,
+            gfx_hal::PresentMode::Fifo => 2,
+            gfx_hal::PresentMode::Relaxed => 1,
+        })
+        .unwrap();
+
+    log::info!(
+        "Surface present modes: {:#?}. Pick {:#?}",
+        present_modes,
+        present_mode
+    );
+
+    let formats = formats.unwrap();
+
+    let format = *formats
+        .iter()
+        .max_by_key(|format| {
+            let base = format.base_format();
+            let desc = base.0.desc();
+            (
+                !desc.is_compressed(),
+                base.1 == gfx_hal::format::ChannelType::Srgb,
+                desc.bits,
+            )
+        })
+        .unwrap();
+
+    log::info!("Surface formats: {:#?}. Pick {:#?}", formats, format);
+
+    let image_count = image_count
+        .min(capabilities.image_count.end)
+        .max(capabilities.image_count.start);
+
+    log::info!(
+        "Surface capabilities: {:#?}. Pick {} images",
+        capabilities.image_count,
+        image_count
+    );
+    assert!(
+        capabilities.usage.contains(usage),
+        "Surface supports {:?}, but {:?} was requested"
+    );
+
+    let extent = capabilities.current_extent.unwrap_or({
+        let hidpi_factor = window.get_hidpi_factor();
+        let start = capabilities.extents.start;
+        let end = capabilities.extents.end;
+        let (window_width, window_height) = window
+            .get_inner_size()
+            .unwrap()
+            .to_physical(hidpi_factor)
+            .into();
+        gfx_hal::window::Extent2D {
+            width: end.width.min(start.width.max(window_width)),
+            height: end.height.min(start.height.max(window_height)),
+        }
+    });
+
+    let (swapchain, backbuffer) = device.create_swapchain(
+        surface,
+        gfx_hal::SwapchainConfig {
+            present_mode,
+            format,
+            extent,
+            image_count,
+            image_layers: 1,
+            image_usage: usage,
+            composite_alpha: gfx_hal::window::CompositeAlpha::Inherit,
+        },
+        old_swapchain,
+    )?;
+
+    let (backbuffer, terminal) = match backbuffer {
+        gfx_hal::Backbuffer::Images(images) => {
+            let terminal = Terminal::new();
+            let backbuffer = Backbuffer::Images(
+                images
+                    .into_iter()
+                    .map(|image| {
+                        Image::new(
+                            Info {
+                                kind: gfx_hal::image::Kind::D2(extent.width, extent.height, 1, 1),
+                                levels: 1,
+                                format,
+                                tiling: gfx_hal::image::Tiling::Optimal,
+                                view_caps: gfx_hal::image::ViewCapabilities::empty(),
+                                usage,
+                            },
+                            image,
+                            None,
+                            &terminal,
+                        )
+                    })
+                    .collect(),
+            );
+            (backbuffer, Some(terminal))
+        }
+        gfx_hal::Backbuffer::Framebuffer(raw) => {
+            let backbuffer = Backbuffer::Framebuffer {
+                raw,
+                format,
+                extent,
+            };
+            (backbuffer, None)
+        }
+    };
+
+    Ok((swapchain, backbuffer, terminal, extent))
+}
+
 /// Backbuffer of the `Target`.
 /// Either collection of `Image`s
 /// or framebuffer.
@@ -327,9 +359,12 @@ pub enum Backbuffer<B: gfx_hal::Backend> {
 /// With swapchain created.
 pub struct Target<B: gfx_hal::Backend> {
     surface: B::Surface,
-    swapchain: B::Swapchain,
+    swapchain: Option<B::Swapchain>,
     backbuffer: Backbuffer<B>,
     terminal: Option<Terminal<Inner<B>>>,
+    extent: gfx_hal::window::Extent2D,
+    image_count: u32,
+    usage: gfx_hal::image::Usage,
     window: std::sync::Arc<winit::Window>,
     relevant: relevant::Relevant,
 }
@@ -342,6 +377,7 @@ where
         fmt.debug_struct("Target")
             .field("window", &self.window.id())
             .field("backbuffer", &self.backbuffer)
+            .field("extent", &self.extent)
             .finish()
     }
 }
@@ -360,18 +396,74 @@ where
         self.terminal
             .map(|mut terminal| terminal.drain().map(Inner::dispose).for_each(drop));
         self.relevant.dispose();
-        device.destroy_swapchain(self.swapchain);
+        if let Some(swapchain) = self.swapchain {
+            device.destroy_swapchain(swapchain);
+        }
         drop(self.surface);
     }
 
+    /// Recreate swapchain.
+    /// Must be called when swapchain is out of date or suboptimal,
+    /// e.g. after window resize.
+    /// New swapchain images match current extent of the surface.
+    ///
+    /// # Safety
+    ///
+    /// Swapchain images must be not in use.
+    /// Old backbuffer is destroyed once new swapchain is created.
+    ///
+    /// Old swapchain is retired even if recreation fails.
+    /// Target has no swapchain then and `next_image` fails with `OutOfDate`
+    /// until swapchain is recreated successfully.
+    pub unsafe fn recreate(
+        &mut self,
+        physical_device: &B::PhysicalDevice,
+        device: &impl gfx_hal::Device<B>,
+    ) -> Result<(), failure::Error> {
+        let (swapchain, backbuffer, terminal, extent) = create_swapchain(
+            &mut self.surface,
+            &self.window,
+            physical_device,
+            device,
+            self.image_count,
+            self.usage,
+            self.swapchain.take(),
+        )?;
+
+        log::info!("Swapchain recreated with extent {:?}", extent);
+        drop(std::mem::replace(&mut self.backbuffer, backbuffer));
+        std::mem::replace(&mut self.terminal, terminal)
+            .map(|mut terminal| terminal.drain().map(Inner::dispose).for_each(drop));
+        self.swapchain = Some(swapchain);
+        self.extent = extent;
+        Ok(())
+    }
+
+    /// Get extent of the swapchain images.
+    pub fn extent(&self) -> gfx_hal::window::Extent2D {
+        self.extent
+    }
+
     /// Get raw surface handle.
     pub fn surface(&self) -> &B::Surface {
         &self.surface
     }
 
-    /// Get raw surface handle.
-    pub fn swapchain(&self) -> &B::Swapchain {
-        &self.swapchain
+    /// Get current extent of the surface.
+    /// Extent is zero while window is minimized.
+    /// Swapchain can't be recreated then.
+    pub fn surface_extent(&self) -> gfx_hal::window::Extent2D {
+        let extent = gfx_hal::Surface::kind(&self.surface).extent();
+        gfx_hal::window::Extent2D {
+            width: extent.width,
+            height: extent.height,
+        }
+    }
+
+    /// Get raw swapchain handle.
+    /// Returns `None` if last recreation failed.
+    pub fn swapchain(&self) -> Option<&B::Swapchain> {
+        self.swapchain.as_ref()
     }
 
     /// Get swapchain impl trait.
@@ -379,8 +471,8 @@ where
     /// # Safety
     ///
     /// Trait usage should not violate this type valid usage.
-    pub unsafe fn swapchain_mut(&mut self) -> &mut impl gfx_hal::Swapchain<B> {
-        &mut self.swapchain
+    pub unsafe fn swapchain_mut(&mut self) -> Option<&mut impl gfx_hal::Swapchain<B>> {
+        self.swapchain.as_mut()
     }
 
     /// Get raw handlers for the swapchain images.
@@ -389,12 +481,14 @@ where
     }
 
     /// Acquire next image.
+    /// Fails with `OutOfDate` if target has no swapchain.
     pub unsafe fn next_image(
         &mut self,
         signal: &B::Semaphore,
     ) -> Result<NextImages<'_, B>, gfx_hal::AcquireError> {
         let index = gfx_hal::Swapchain::acquire_image(
-            &mut self.swapchain,
+            self.swapchain_mut()
+                .ok_or(gfx_hal::AcquireError::OutOfDate)?,
             !0,
             gfx_hal::FrameSync::Semaphore(signal),
         )?;
@@ -410,6 +504,12 @@ where
     }
 }
 
+/// Error returned when swapchain is suboptimal or out of date
+/// and must be recreated.
+#[derive(Clone, Copy, Debug, failure::Fail)]
+#[fail(display = "Swapchain is suboptimal or out of date")]
+pub struct OutOfDate;
+
 /// Represents acquire frames that will be presented next.
 #[derive(Debug)]
 pub struct NextImages<'a, B: gfx_hal::Backend> {
@@ -426,26 +526,26 @@ where
     }
 
     /// Present images by the queue.
-    ///
-    /// # TODO
-    ///
-    /// Use specific presentation error type.
+    /// Fails with `OutOfDate` if swapchains must be recreated.
     pub unsafe fn present<'b>(
         self,
         queue: &mut impl gfx_hal::queue::RawCommandQueue<B>,
         wait: impl IntoIterator<Item = &'b (impl std::borrow::Borrow<B::Semaphore> + 'b)>,
-    ) -> Result<(), failure::Error>
+    ) -> Result<(), OutOfDate>
     where
         'a: 'b,
     {
         queue
             .present(
-                self.targets
-                    .iter()
-                    .map(|(target, index)| (&target.swapchain, *index)),
+                self.targets.iter().map(|(target, index)| {
+                    let swapchain = target
+                        .swapchain()
+                        .expect("Images are acquired from existing swapchain");
+                    (swapchain, *index)
+                }),
                 wait,
             )
-            .map_err(|()| failure::format_err!("Suboptimal or out of date, or what?"))
+            .map_err(|()| OutOfDate)
     }
 }
 